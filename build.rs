@@ -7,6 +7,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             ".",
             "#[derive(serde::Serialize,serde::Deserialize)]"
         )
+        // Streams of server streaming methods are named after snake case methods, i.e. `reindexStream` of `IndexApi`,
+        // and generated server modules already have inner attributes
+        .server_mod_attribute("summa.proto", "#[allow(non_camel_case_types, clippy::mixed_attributes_style)]")
         .file_descriptor_set_path(out_dir + "/summa.bin").compile(
         &[
             "summa/proto/consumer_service.proto",
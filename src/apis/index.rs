@@ -3,16 +3,20 @@
 //! Index GRPC API is using for managing indices
 
 use crate::configs::ApplicationConfigHolder;
-use crate::errors::SummaResult;
+use crate::errors::{SummaResult, ValidationError};
 use crate::proto;
-use crate::search_engine::SummaDocument;
+use crate::search_engine::{IndexBulkResult, SummaDocument};
 use crate::services::IndexService;
+use std::collections::HashMap;
 use std::error::Error;
 use std::io::ErrorKind;
 use std::ops::Deref;
+use std::pin::Pin;
 use std::time::Instant;
+use tantivy::schema::Value;
 use tantivy::SegmentId;
-use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
 use tonic::{Request, Response, Status, Streaming};
 use tracing::{info_span, warn};
 use tracing_futures::Instrument;
@@ -47,6 +51,11 @@ fn match_for_io_error(err_status: &Status) -> Option<&std::io::Error> {
     }
 }
 
+/// Unknown formats are treated as JSON for compatibility with clients sending no format
+fn document_format(format: i32) -> proto::DocumentFormat {
+    proto::DocumentFormat::from_i32(format).unwrap_or(proto::DocumentFormat::Json)
+}
+
 #[tonic::async_trait]
 impl proto::index_api_server::IndexApi for IndexApiImpl {
     async fn commit_index(&self, request: Request<proto::CommitIndexRequest>) -> Result<Response<proto::CommitIndexResponse>, Status> {
@@ -90,8 +99,30 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
             .get_index_holder(&request.index_alias)?
             .index_updater()
             .read()
-            .index_document(SummaDocument::UnboundJsonBytes(&request.document))?;
-        let response = proto::IndexDocumentResponse { opstamp };
+            .index_document(SummaDocument::UnboundBytes((document_format(request.format), &request.document)))?;
+        let response = proto::IndexDocumentResponse {
+            opstamp: opstamp.unwrap_or_default(),
+            skipped: opstamp.is_none(),
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn index_document_bulk(&self, request: Request<proto::IndexDocumentBulkRequest>) -> Result<Response<proto::IndexDocumentBulkResponse>, Status> {
+        let request = request.into_inner();
+        let now = Instant::now();
+        let mut result = IndexBulkResult::default();
+        self.index_service.get_index_holder(&request.index_alias)?.index_updater().read().index_bulk(
+            &request.documents,
+            document_format(request.format),
+            &mut result,
+        );
+        let response = proto::IndexDocumentBulkResponse {
+            success_docs: result.success_docs,
+            failed_docs: result.failed_docs,
+            elapsed_secs: now.elapsed().as_secs_f64(),
+            skipped_docs: result.skipped_docs,
+            errors: result.errors,
+        };
         Ok(Response::new(response))
     }
 
@@ -99,22 +130,19 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
         &self,
         request: Request<Streaming<proto::IndexDocumentStreamRequest>>,
     ) -> Result<Response<proto::IndexDocumentStreamResponse>, Status> {
-        let (mut success_docs, mut failed_docs) = (0u64, 0u64);
+        let mut result = IndexBulkResult::default();
         let mut elapsed_secs = 0f64;
         let mut in_stream = request.into_inner();
         while let Some(chunk) = in_stream.next().await {
             match chunk {
                 Ok(chunk) => {
                     let now = Instant::now();
-                    let (success_bulk_docs, failed_bulk_docs) = self
-                        .index_service
-                        .get_index_holder(&chunk.index_alias)?
-                        .index_updater()
-                        .read()
-                        .index_bulk(&chunk.documents);
+                    self.index_service.get_index_holder(&chunk.index_alias)?.index_updater().read().index_bulk(
+                        &chunk.documents,
+                        document_format(chunk.format),
+                        &mut result,
+                    );
                     elapsed_secs += now.elapsed().as_secs_f64();
-                    success_docs += success_bulk_docs;
-                    failed_docs += failed_bulk_docs;
                 }
                 Err(err) => {
                     if let Some(io_err) = match_for_io_error(&err) {
@@ -126,9 +154,11 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
             }
         }
         let response = proto::IndexDocumentStreamResponse {
-            success_docs,
-            failed_docs,
+            success_docs: result.success_docs,
+            failed_docs: result.failed_docs,
             elapsed_secs,
+            skipped_docs: result.skipped_docs,
+            errors: result.errors,
         };
         Ok(Response::new(response))
     }
@@ -151,6 +181,28 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
         Ok(Response::new(response))
     }
 
+    async fn delete_by_query(&self, proto_request: Request<proto::DeleteByQueryRequest>) -> Result<Response<proto::DeleteByQueryResponse>, Status> {
+        let proto_request = proto_request.into_inner();
+        let query = proto_request.query.ok_or_else(|| ValidationError::EmptyArgument("query".to_owned()))?;
+        let deleted_documents = self.index_service.get_index_holder(&proto_request.index_alias)?.delete_by_query(&query).await?;
+        Ok(Response::new(proto::DeleteByQueryResponse { deleted_documents }))
+    }
+
+    async fn delete_document(&self, proto_request: Request<proto::DeleteDocumentRequest>) -> Result<Response<proto::DeleteDocumentResponse>, Status> {
+        let proto_request = proto_request.into_inner();
+        let primary_key_value: Value = proto_request
+            .primary_key
+            .ok_or_else(|| ValidationError::EmptyArgument("primary_key".to_owned()))?
+            .try_into()?;
+        let opstamp = self
+            .index_service
+            .get_index_holder(&proto_request.index_alias)?
+            .index_updater()
+            .read()
+            .delete_document(&primary_key_value)?;
+        Ok(Response::new(proto::DeleteDocumentResponse { opstamp }))
+    }
+
     async fn delete_index(&self, proto_request: Request<proto::DeleteIndexRequest>) -> Result<Response<proto::DeleteIndexResponse>, Status> {
         Ok(Response::new(self.index_service.delete_index(proto_request.into_inner().into()).await?.into()))
     }
@@ -196,6 +248,15 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
         Ok(Response::new(response))
     }
 
+    type reindexStream = Pin<Box<dyn Stream<Item = Result<proto::ReindexProgress, Status>> + Send>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn reindex(&self, proto_request: Request<proto::ReindexRequest>) -> Result<Response<Self::reindexStream>, Status> {
+        let progress_receiver = self.index_service.reindex(proto_request.into_inner().into())?;
+        let progress_stream = ReceiverStream::new(progress_receiver).map(|progress| progress.map_err(Status::from));
+        Ok(Response::new(Box::pin(progress_stream)))
+    }
+
     async fn set_index_alias(&self, proto_request: Request<proto::SetIndexAliasRequest>) -> Result<Response<proto::SetIndexAliasResponse>, Status> {
         let proto_request = proto_request.into_inner();
         let old_index_name = self
@@ -207,6 +268,32 @@ impl proto::index_api_server::IndexApi for IndexApiImpl {
         Ok(Response::new(response))
     }
 
+    async fn update_document(&self, proto_request: Request<proto::UpdateDocumentRequest>) -> Result<Response<proto::UpdateDocumentResponse>, Status> {
+        let proto_request = proto_request.into_inner();
+        let primary_key_value: Value = proto_request
+            .primary_key
+            .ok_or_else(|| ValidationError::EmptyArgument("primary_key".to_owned()))?
+            .try_into()?;
+        let update_modes = proto_request
+            .update_modes
+            .iter()
+            .map(|(field_name, update_mode)| {
+                proto::UpdateMode::from_i32(*update_mode)
+                    .map(|update_mode| (field_name.to_owned(), update_mode))
+                    .ok_or_else(|| crate::errors::Error::InvalidSyntax(format!("invalid update mode {} for {}", update_mode, field_name)))
+            })
+            .collect::<SummaResult<HashMap<_, _>>>()?;
+        let opstamp = self
+            .index_service
+            .get_index_holder(&proto_request.index_alias)?
+            .update_document(primary_key_value, proto_request.document, &update_modes)
+            .await?;
+        Ok(Response::new(proto::UpdateDocumentResponse {
+            opstamp: opstamp.unwrap_or_default(),
+            skipped: opstamp.is_none(),
+        }))
+    }
+
     async fn vacuum_index(&self, proto_request: Request<proto::VacuumIndexRequest>) -> Result<Response<proto::VacuumIndexResponse>, Status> {
         let proto_request = proto_request.into_inner();
         let index_holder = self.index_service.get_index_holder(&proto_request.index_alias)?;
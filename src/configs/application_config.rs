@@ -49,6 +49,11 @@ impl ApplicationConfig {
         self.data_path.join(index_name)
     }
 
+    /// Directory with ranking models used by `Scorer::Model`
+    pub fn get_path_for_ranking_models(&self) -> PathBuf {
+        self.data_path.join("models")
+    }
+
     /// Copy aliases for the index
     pub fn get_index_aliases_for_index(&self, index_name: &str) -> Vec<String> {
         self.aliases
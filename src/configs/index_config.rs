@@ -1,7 +1,7 @@
 use super::ConsumerConfig;
 use crate::configs::config_holder::AutosaveLockWriteGuard;
 use crate::configs::{ApplicationConfig, ApplicationConfigHolder, ConfigHolder, Persistable};
-use crate::errors::SummaResult;
+use crate::errors::{SummaResult, ValidationError};
 use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -29,6 +29,118 @@ impl Debug for IndexEngine {
     }
 }
 
+/// Scoring function used for text fields
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Similarity {
+    /// BM25 with custom saturation `k1` and length normalization `b`
+    Bm25 {
+        #[serde(default = "Similarity::default_k1")]
+        k1: f32,
+        #[serde(default = "Similarity::default_b")]
+        b: f32,
+    },
+    /// Classic TF-IDF: `sqrt(tf) * idf^2 / sqrt(field_length)` where `idf = 1 + ln(N / (df + 1))`
+    TfIdf,
+    /// Fields having this similarity are scored together as a single BM25F field
+    /// with term frequencies multiplied by `weight` and normalized by `b` before the saturation
+    Bm25f {
+        weight: f32,
+        #[serde(default = "Similarity::default_k1")]
+        k1: f32,
+        #[serde(default = "Similarity::default_b")]
+        b: f32,
+    },
+}
+
+impl Similarity {
+    /// Tantivy default of BM25 saturation
+    pub fn default_k1() -> f32 {
+        1.2
+    }
+
+    /// Tantivy default of BM25 length normalization
+    pub fn default_b() -> f32 {
+        0.75
+    }
+
+    /// Rejects negative BM25 parameters
+    pub fn validate(&self) -> SummaResult<()> {
+        match self {
+            Similarity::Bm25 { k1, b } | Similarity::Bm25f { k1, b, .. } if *k1 < 0.0 || *b < 0.0 => {
+                Err(ValidationError::InvalidSimilarity(format!("{:?}", self)).into())
+            }
+            Similarity::Bm25f { weight, .. } if *weight < 0.0 => Err(ValidationError::InvalidSimilarity(format!("{:?}", self)).into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Action on indexing a document having a version that is not newer than the version of the indexed document
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionConflict {
+    /// Indexing fails with `outdated_version_error`
+    #[default]
+    Reject,
+    /// The document is silently skipped
+    Skip,
+}
+
+/// Handling of keys of JSON documents that are absent in the schema
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownFields {
+    /// Unknown keys are silently dropped
+    #[default]
+    Ignore,
+    /// Unknown keys are dropped with a warning
+    Warn,
+    /// Documents with unknown keys are rejected
+    Reject,
+}
+
+/// Transformation of JSON documents applied before their conversion into documents of the index
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestStep {
+    /// Copies the value of `from` into `to`
+    Copy { from: String, to: String },
+    /// Sets `value` into `field` if the field is missing or null
+    SetDefault { field: String, value: serde_json::Value },
+    /// Moves the value of `from` into `to`
+    Rename { from: String, to: String },
+    /// Parses the date in `field` with the first matching of `formats` and replaces it with RFC 3339 date
+    ///
+    /// Formats are format descriptions of `time` crate, i.e. `[year]-[month]-[day]`. Dates without offset are
+    /// considered UTC and dates without time are considered midnight
+    ParseDate { field: String, formats: Vec<String> },
+    /// Lowercases the string or strings in `field`
+    Lowercase { field: String },
+    /// Trims whitespaces around the string or strings in `field`
+    Trim { field: String },
+    /// Splits the string in `field` by non-empty `separator` into the array of non-empty strings
+    Split { field: String, separator: String },
+    /// Drops fields that are absent in the schema
+    DropUnknown,
+    /// Sets the value of `expression` into `field`
+    ///
+    /// Variables of the expression are bound to numeric values of other fields
+    Expression { field: String, expression: String },
+}
+
+/// Periodical deletion of expired documents
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExpirationConfig {
+    /// Date field holding the expiration date or, if `ttl_secs` is set, the date the TTL is counted from
+    pub date_field: String,
+    /// Documents expire `ttl_secs` seconds after the date stored in `date_field`
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Interval between deletions of expired documents
+    pub interval_ms: u64,
+}
+
 #[derive(Builder, Clone, Serialize, Deserialize)]
 pub struct IndexConfig {
     #[builder(default = "None")]
@@ -37,13 +149,39 @@ pub struct IndexConfig {
     pub consumer_configs: HashMap<String, ConsumerConfig>,
     #[builder(default = "Vec::new()")]
     pub default_fields: Vec<String>,
+    #[builder(default = "None")]
+    #[serde(default)]
+    pub expiration: Option<ExpirationConfig>,
+    /// Nested JSON objects of documents are flattened into dotted field names
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub flatten_nested: bool,
     pub index_engine: IndexEngine,
+    #[builder(default = "Vec::new()")]
+    #[serde(default)]
+    pub ingest_pipeline: Vec<IngestStep>,
     #[builder(default = "None")]
     pub primary_key: Option<String>,
     #[builder(default = "HashSet::new()")]
     pub multi_fields: HashSet<String>,
+    #[builder(default = "HashMap::new()")]
+    #[serde(default)]
+    pub similarities: HashMap<String, Similarity>,
     #[builder(default = "None")]
     pub stop_words: Option<Vec<String>>,
+    #[builder(default = "UnknownFields::Ignore")]
+    #[serde(default)]
+    pub unknown_fields: UnknownFields,
+    #[builder(default = "VersionConflict::Reject")]
+    #[serde(default)]
+    pub version_conflict: VersionConflict,
+    #[builder(default = "None")]
+    #[serde(default)]
+    pub version_field: Option<String>,
+    /// Dimensions of vectors kept in bytes fast fields, values of these fields must be arrays of numbers
+    #[builder(default = "HashMap::new()")]
+    #[serde(default)]
+    pub vector_fields: HashMap<String, usize>,
     #[builder(default = "128 * 1024 * 1024")]
     pub writer_heap_size_bytes: u64,
     #[builder(default = "1")]
@@ -10,6 +10,9 @@ mod metrics_config;
 pub use application_config::{ApplicationConfig, ApplicationConfigBuilder, ApplicationConfigHolder};
 pub use config_holder::{ConfigHolder, Loadable, Persistable};
 pub use grpc_config::{GrpcConfig, GrpcConfigBuilder};
-pub use index_config::{IndexConfig, IndexConfigBuilder, IndexConfigProxy, IndexConfigReadProxy, IndexConfigWriteProxy, IndexEngine};
+pub use index_config::{
+    ExpirationConfig, IndexConfig, IndexConfigBuilder, IndexConfigProxy, IndexConfigReadProxy, IndexConfigWriteProxy, IndexEngine, IngestStep, Similarity,
+    UnknownFields, VersionConflict,
+};
 pub use kafka_consumer_config::ConsumerConfig;
 pub use metrics_config::{MetricsConfig, MetricsConfigBuilder};
@@ -24,12 +24,20 @@ pub enum ValidationError {
     InvalidAggregation,
     #[error("invalid_fast_field_type_error: ({field:?}, {field_type:?})")]
     InvalidFastFieldType { field: String, field_type: FieldType },
+    #[error("invalid_lambda_error: {0}")]
+    InvalidLambda(f32),
     #[error("invalid_memory_error: {0}")]
     InvalidMemory(u64),
     #[error("invalid_primary_key_type_error: {0:?}")]
     InvalidPrimaryKeyType(FieldType),
+    #[error("invalid_primary_key_value_error: {0}")]
+    InvalidPrimaryKeyValue(String),
+    #[error("invalid_ranking_model_error: {0}")]
+    InvalidRankingModel(String),
     #[error("invalid_schema_error: {0}")]
     InvalidFields(String),
+    #[error("invalid_similarity_error: {0}")]
+    InvalidSimilarity(String),
     #[error("invalid_threads_number_error: {0}")]
     InvalidThreadsNumber(u64),
     #[error("missing_consumer_error: {0}")]
@@ -38,12 +46,22 @@ pub enum ValidationError {
     MissingIndex(String),
     #[error("missing_default_field_error: {0}")]
     MissingDefaultField(String),
+    #[error("missing_document_error: {0}")]
+    MissingDocument(String),
     #[error("missing_multi_field_error: {0}")]
     MissingMultiField(String),
     #[error("missing_path_error: {0}")]
     MissingPath(PathBuf),
     #[error("missing_primary_key_error: {0:?}")]
     MissingPrimaryKey(Option<String>),
+    #[error("not_stored_field_error: {0}")]
+    NotStoredField(String),
+    #[error("outdated_version_error: <version: {version}, indexed_version: {indexed_version}>")]
+    OutdatedVersion { version: i128, indexed_version: i128 },
+    #[error("same_index_error: {0}")]
+    SameIndex(String),
+    #[error("uncommitted_document_error: {0}")]
+    UncommittedDocument(String),
     #[error("utf8_error: {0}")]
     Utf8(std::str::Utf8Error),
 }
@@ -203,6 +221,7 @@ impl From<Error> for tonic::Status {
                 },
                 Error::Tantivy(_) => tonic::Code::InvalidArgument,
                 Error::Validation(ValidationError::MissingConsumer(_)) | Error::Validation(ValidationError::MissingIndex(_)) => tonic::Code::NotFound,
+                Error::Validation(ValidationError::OutdatedVersion { .. }) => tonic::Code::Aborted,
                 Error::Validation(_) => tonic::Code::InvalidArgument,
                 Error::FieldDoesNotExist(_) => tonic::Code::NotFound,
                 _ => tonic::Code::Internal,
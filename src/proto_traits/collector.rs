@@ -1,6 +1,7 @@
 #[cfg(test)]
 pub mod shortcuts {
     use crate::proto;
+    use std::collections::HashMap;
 
     pub fn top_docs_collector(limit: u32) -> proto::Collector {
         proto::Collector {
@@ -8,22 +9,136 @@ pub mod shortcuts {
                 limit,
                 offset: 0,
                 scorer: None,
+                rescorer: None,
+                diversification: None,
             })),
         }
     }
 
     pub fn top_docs_collector_with_eval_expr(limit: u32, eval_expr: &str) -> proto::Collector {
+        top_docs_collector_with_eval_expr_and_params(limit, eval_expr, &[])
+    }
+
+    pub fn top_docs_collector_with_eval_expr_and_params(limit: u32, eval_expr: &str, params: &[(&str, f64)]) -> proto::Collector {
         proto::Collector {
             collector: Some(proto::collector::Collector::TopDocs(proto::TopDocsCollector {
                 limit,
                 offset: 0,
                 scorer: Some(proto::Scorer {
                     scorer: Some(proto::scorer::Scorer::EvalExpr(eval_expr.to_owned())),
+                    params: params.iter().map(|(param_name, value)| (param_name.to_string(), *value)).collect(),
+                }),
+                rescorer: None,
+                diversification: None,
+            })),
+        }
+    }
+
+    pub fn top_docs_collector_with_rescorer(limit: u32, offset: u32, window_size: u32, eval_expr: &str) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::TopDocs(proto::TopDocsCollector {
+                limit,
+                offset,
+                scorer: None,
+                rescorer: Some(proto::Rescorer {
+                    window_size,
+                    eval_expr: eval_expr.to_owned(),
+                }),
+                diversification: None,
+            })),
+        }
+    }
+
+    pub fn top_docs_collector_with_model(limit: u32, model_name: &str, window_size: u32, params: &[(&str, f64)]) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::TopDocs(proto::TopDocsCollector {
+                limit,
+                offset: 0,
+                scorer: Some(proto::Scorer {
+                    scorer: Some(proto::scorer::Scorer::Model(proto::ModelScorer {
+                        model_name: model_name.to_owned(),
+                        window_size,
+                    })),
+                    params: params.iter().map(|(param_name, value)| (param_name.to_string(), *value)).collect(),
+                }),
+                rescorer: None,
+                diversification: None,
+            })),
+        }
+    }
+
+    pub fn top_docs_collector_with_random(limit: u32, offset: u32, seed: u64, field: &str) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::TopDocs(proto::TopDocsCollector {
+                limit,
+                offset,
+                scorer: Some(proto::Scorer {
+                    scorer: Some(proto::scorer::Scorer::Random(proto::RandomScorer { seed, field: field.to_owned() })),
+                    params: HashMap::new(),
+                }),
+                rescorer: None,
+                diversification: None,
+            })),
+        }
+    }
+
+    pub fn top_docs_collector_with_diversification(
+        limit: u32,
+        window_size: u32,
+        lambda: f32,
+        similarity: proto::diversification::Similarity,
+    ) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::TopDocs(proto::TopDocsCollector {
+                limit,
+                offset: 0,
+                scorer: None,
+                rescorer: None,
+                diversification: Some(proto::Diversification {
+                    window_size,
+                    lambda: Some(lambda),
+                    similarity: Some(similarity),
                 }),
             })),
         }
     }
 
+    pub fn hybrid_collector(
+        limit: u32,
+        offset: u32,
+        vector_field: &str,
+        vector: &[f32],
+        window_size: u32,
+        fusion: proto::hybrid_collector::Fusion,
+    ) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::Hybrid(proto::HybridCollector {
+                limit,
+                offset,
+                vector_query: Some(proto::VectorQuery {
+                    field: vector_field.to_owned(),
+                    vector: vector.to_vec(),
+                    k: 0,
+                    metric: proto::VectorMetric::Cosine.into(),
+                    filter: None,
+                }),
+                window_size,
+                fusion: Some(fusion),
+            })),
+        }
+    }
+
+    pub fn facet_collector(field: &str, facets: &[&str], top_k: Option<u32>, depth: Option<u32>) -> proto::Collector {
+        proto::Collector {
+            collector: Some(proto::collector::Collector::Facet(proto::FacetCollector {
+                field: field.to_owned(),
+                facets: facets.iter().map(|facet| facet.to_string()).collect(),
+                top_k,
+                depth,
+            })),
+        }
+    }
+
     pub fn scored_doc(document: &str, score: f64, position: u32) -> proto::ScoredDocument {
         proto::ScoredDocument {
             document: document.to_owned(),
@@ -0,0 +1,25 @@
+use crate::configs::IngestStep;
+use crate::errors::{Error, SummaResult};
+use crate::proto;
+
+impl TryFrom<proto::IngestStep> for IngestStep {
+    type Error = Error;
+
+    fn try_from(ingest_step: proto::IngestStep) -> SummaResult<Self> {
+        Ok(match ingest_step.step {
+            Some(proto::ingest_step::Step::Copy(proto::CopyStep { from, to })) => IngestStep::Copy { from, to },
+            Some(proto::ingest_step::Step::SetDefault(proto::SetDefaultStep { field, value })) => IngestStep::SetDefault {
+                field,
+                value: serde_json::from_str(&value).map_err(|_| Error::InvalidSyntax(format!("invalid default value: {}", value)))?,
+            },
+            Some(proto::ingest_step::Step::Rename(proto::CopyStep { from, to })) => IngestStep::Rename { from, to },
+            Some(proto::ingest_step::Step::ParseDate(proto::ParseDateStep { field, formats })) => IngestStep::ParseDate { field, formats },
+            Some(proto::ingest_step::Step::Lowercase(proto::FieldStep { field })) => IngestStep::Lowercase { field },
+            Some(proto::ingest_step::Step::Trim(proto::FieldStep { field })) => IngestStep::Trim { field },
+            Some(proto::ingest_step::Step::Split(proto::SplitStep { field, separator })) => IngestStep::Split { field, separator },
+            Some(proto::ingest_step::Step::DropUnknown(_)) => IngestStep::DropUnknown,
+            Some(proto::ingest_step::Step::Expression(proto::ExpressionStep { field, expression })) => IngestStep::Expression { field, expression },
+            None => return Err(Error::InvalidSyntax("empty ingest step".to_owned())),
+        })
+    }
+}
@@ -1,6 +1,9 @@
 mod aggregation;
 pub mod collector;
 mod compression;
+mod ingest_step;
+mod primary_key;
 pub mod query;
 mod score;
+mod similarity;
 mod sort_by_field;
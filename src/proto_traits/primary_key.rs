@@ -0,0 +1,32 @@
+use crate::errors::{Error, ValidationError};
+use crate::proto;
+use tantivy::schema::Value;
+
+impl TryFrom<proto::PrimaryKey> for Value {
+    type Error = Error;
+
+    fn try_from(primary_key: proto::PrimaryKey) -> Result<Self, Self::Error> {
+        match primary_key.value {
+            Some(proto::primary_key::Value::I64(value)) => Ok(Value::I64(value)),
+            Some(proto::primary_key::Value::Str(value)) => Ok(Value::Str(value)),
+            Some(proto::primary_key::Value::U64(value)) => Ok(Value::U64(value)),
+            Some(proto::primary_key::Value::Bytes(value)) => Ok(Value::Bytes(value)),
+            None => Err(ValidationError::EmptyArgument("primary_key".to_owned()).into()),
+        }
+    }
+}
+
+impl TryFrom<Value> for proto::PrimaryKey {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let value = match value {
+            Value::I64(value) => proto::primary_key::Value::I64(value),
+            Value::Str(value) => proto::primary_key::Value::Str(value),
+            Value::U64(value) => proto::primary_key::Value::U64(value),
+            Value::Bytes(value) => proto::primary_key::Value::Bytes(value),
+            value => return Err(ValidationError::InvalidPrimaryKeyValue(format!("{:?}", value)).into()),
+        };
+        Ok(proto::PrimaryKey { value: Some(value) })
+    }
+}
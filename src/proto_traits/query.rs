@@ -14,6 +14,7 @@ impl ToLabel for proto::Query {
             Some(proto::query::Query::Phrase(_)) => "phrase",
             Some(proto::query::Query::Term(_)) => "term",
             Some(proto::query::Query::MoreLikeThis(_)) => "more_like_this",
+            Some(proto::query::Query::Vector(_)) => "vector",
         }
         .to_owned()
     }
@@ -28,4 +29,25 @@ pub mod shortcuts {
             query: Some(proto::query::Query::Match(proto::MatchQuery { value: value.to_owned() })),
         }
     }
+
+    pub fn term_query(field: &str, value: &str) -> proto::Query {
+        proto::Query {
+            query: Some(proto::query::Query::Term(proto::TermQuery {
+                field: field.to_owned(),
+                value: value.to_owned(),
+            })),
+        }
+    }
+
+    pub fn vector_query(field: &str, vector: &[f32], k: u32, filter: Option<proto::Query>) -> proto::Query {
+        proto::Query {
+            query: Some(proto::query::Query::Vector(Box::new(proto::VectorQuery {
+                field: field.to_owned(),
+                vector: vector.to_vec(),
+                k,
+                metric: proto::VectorMetric::Cosine.into(),
+                filter: filter.map(Box::new),
+            }))),
+        }
+    }
 }
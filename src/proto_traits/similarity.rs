@@ -0,0 +1,25 @@
+use crate::configs::Similarity;
+use crate::errors::{Error, SummaResult};
+use crate::proto;
+
+impl TryFrom<proto::Similarity> for Similarity {
+    type Error = Error;
+
+    /// Unset (zero) `k1` and `b` are replaced with Tantivy defaults
+    fn try_from(similarity: proto::Similarity) -> SummaResult<Self> {
+        let or_default = |value: f32, default: f32| if value == 0.0 { default } else { value };
+        Ok(match similarity.similarity {
+            Some(proto::similarity::Similarity::Bm25(proto::Bm25Similarity { k1, b })) => Similarity::Bm25 {
+                k1: or_default(k1, Similarity::default_k1()),
+                b: or_default(b, Similarity::default_b()),
+            },
+            Some(proto::similarity::Similarity::TfIdf(_)) => Similarity::TfIdf,
+            Some(proto::similarity::Similarity::Bm25f(proto::Bm25fSimilarity { weight, k1, b })) => Similarity::Bm25f {
+                weight,
+                k1: or_default(k1, Similarity::default_k1()),
+                b: or_default(b, Similarity::default_b()),
+            },
+            None => return Err(Error::InvalidSyntax("empty similarity".to_owned())),
+        })
+    }
+}
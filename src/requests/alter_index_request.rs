@@ -1,5 +1,6 @@
-use crate::errors::{Error, SummaResult};
+use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
+use tantivy::schema::Schema as Fields;
 use tantivy::IndexSortByField;
 
 #[derive(Builder)]
@@ -9,6 +10,8 @@ pub struct AlterIndexRequest {
     pub compression: Option<tantivy::store::Compressor>,
     #[builder(default = "None")]
     pub sort_by_field: Option<IndexSortByField>,
+    #[builder(default = "None")]
+    pub added_fields: Option<Fields>,
 }
 
 impl TryFrom<proto::AlterIndexRequest> for AlterIndexRequest {
@@ -23,6 +26,12 @@ impl TryFrom<proto::AlterIndexRequest> for AlterIndexRequest {
                 .and_then(|compression| proto::Compression::from_i32(compression).map(proto::Compression::into)),
         );
         alter_index_request_builder.sort_by_field(proto_request.sort_by_field.map(proto::SortByField::into));
+        alter_index_request_builder.added_fields(
+            proto_request
+                .added_fields
+                .map(|added_fields| serde_yaml::from_str(&added_fields).map_err(|_| ValidationError::InvalidFields(added_fields.to_owned())))
+                .transpose()?,
+        );
         Ok(alter_index_request_builder.build().unwrap())
     }
 }
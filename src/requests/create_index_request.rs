@@ -1,6 +1,9 @@
+use crate::configs::{ExpirationConfig, IngestStep, Similarity, UnknownFields, VersionConflict};
 use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
-use tantivy::schema::Schema as Fields;
+use crate::search_engine::{validate_expiration, validate_primary_key, validate_vector_field, validate_version_field, IngestPipeline};
+use std::collections::HashMap;
+use tantivy::schema::{FieldType, Schema as Fields};
 use tantivy::IndexSortByField;
 
 #[derive(Builder)]
@@ -14,14 +17,30 @@ pub struct CreateIndexRequest {
     pub compression: tantivy::store::Compressor,
     #[builder(default = "Vec::new()")]
     pub default_fields: Vec<String>,
+    #[builder(default = "None")]
+    pub expiration: Option<ExpirationConfig>,
+    #[builder(default = "false")]
+    pub flatten_nested: bool,
+    #[builder(default = "Vec::new()")]
+    pub ingest_pipeline: Vec<IngestStep>,
     #[builder(default = "Vec::new()")]
     pub multi_fields: Vec<String>,
     #[builder(default = "None")]
     pub primary_key: Option<String>,
+    #[builder(default = "HashMap::new()")]
+    pub similarities: HashMap<String, Similarity>,
     #[builder(default = "None")]
     pub sort_by_field: Option<IndexSortByField>,
     #[builder(default = "None")]
     pub stop_words: Option<Vec<String>>,
+    #[builder(default = "UnknownFields::Ignore")]
+    pub unknown_fields: UnknownFields,
+    #[builder(default = "VersionConflict::Reject")]
+    pub version_conflict: VersionConflict,
+    #[builder(default = "None")]
+    pub version_field: Option<String>,
+    #[builder(default = "HashMap::new()")]
+    pub vector_fields: HashMap<String, usize>,
     #[builder(default = "None")]
     pub writer_threads: Option<u64>,
     #[builder(default = "None")]
@@ -29,6 +48,28 @@ pub struct CreateIndexRequest {
 }
 
 impl CreateIndexRequest {
+    /// Checks the primary key and similarities against the fields, both for parsed and built requests
+    pub fn validate(&self) -> SummaResult<()> {
+        if let Some(primary_key) = &self.primary_key {
+            let field = self
+                .fields
+                .get_field(primary_key)
+                .ok_or_else(|| ValidationError::MissingPrimaryKey(Some(primary_key.to_owned())))?;
+            validate_primary_key(&self.fields, field)?;
+        }
+        for (field_name, similarity) in &self.similarities {
+            let field = self
+                .fields
+                .get_field(field_name)
+                .ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+            match self.fields.get_field_entry(field).field_type() {
+                FieldType::Str(_) => similarity.validate()?,
+                field_type => return Err(Error::InvalidFieldType(field_name.to_owned(), field_type.to_owned())),
+            }
+        }
+        Ok(())
+    }
+
     fn parse_fields(fields: &str) -> SummaResult<Fields> {
         serde_yaml::from_str(fields).map_err(|_| Error::Validation(ValidationError::InvalidFields(fields.to_owned())))
     }
@@ -43,15 +84,59 @@ impl CreateIndexRequest {
             .collect::<Result<_, _>>()?)
     }
 
-    fn parse_primary_key(fields: &Fields, primary_key: &Option<String>) -> SummaResult<Option<String>> {
-        Ok(match primary_key {
-            Some(primary_key) => Some(match fields.get_field(primary_key) {
-                Some(_) => primary_key.to_owned(),
-                None => return Err(ValidationError::MissingPrimaryKey(Some(primary_key.to_owned())).into()),
-            }),
+    fn parse_version_field(fields: &Fields, primary_key: &Option<String>, version_field: &Option<String>) -> SummaResult<Option<String>> {
+        Ok(match version_field {
+            Some(version_field) => {
+                if primary_key.is_none() {
+                    return Err(ValidationError::MissingPrimaryKey(None).into());
+                }
+                let field = fields
+                    .get_field(version_field)
+                    .ok_or_else(|| Error::FieldDoesNotExist(version_field.to_owned()))?;
+                validate_version_field(fields, field)?;
+                Some(version_field.to_owned())
+            }
             None => None,
         })
     }
+
+    fn parse_expiration(fields: &Fields, primary_key: &Option<String>, expiration: Option<proto::Expiration>) -> SummaResult<Option<ExpirationConfig>> {
+        expiration
+            .map(|expiration| {
+                let expiration = ExpirationConfig {
+                    date_field: expiration.date_field,
+                    ttl_secs: expiration.ttl_secs,
+                    interval_ms: expiration.interval_ms,
+                };
+                validate_expiration(fields, primary_key, &expiration)?;
+                Ok(expiration)
+            })
+            .transpose()
+    }
+
+    fn parse_ingest_pipeline(fields: &Fields, ingest_pipeline: Vec<proto::IngestStep>) -> SummaResult<Vec<IngestStep>> {
+        let ingest_pipeline = ingest_pipeline.into_iter().map(IngestStep::try_from).collect::<SummaResult<Vec<_>>>()?;
+        IngestPipeline::new(&ingest_pipeline, fields)?;
+        Ok(ingest_pipeline)
+    }
+
+    fn parse_similarities(similarities: HashMap<String, proto::Similarity>) -> SummaResult<HashMap<String, Similarity>> {
+        similarities
+            .into_iter()
+            .map(|(field_name, similarity)| Ok((field_name, similarity.try_into()?)))
+            .collect()
+    }
+
+    fn parse_vector_fields(fields: &Fields, vector_fields: HashMap<String, u32>) -> SummaResult<HashMap<String, usize>> {
+        vector_fields
+            .into_iter()
+            .map(|(field_name, dimension)| {
+                let field = fields.get_field(&field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+                validate_vector_field(fields, field, dimension as usize)?;
+                Ok((field_name, dimension as usize))
+            })
+            .collect()
+    }
 }
 
 impl TryFrom<proto::CreateIndexRequest> for CreateIndexRequest {
@@ -60,7 +145,21 @@ impl TryFrom<proto::CreateIndexRequest> for CreateIndexRequest {
     fn try_from(proto_request: proto::CreateIndexRequest) -> SummaResult<CreateIndexRequest> {
         let fields = CreateIndexRequest::parse_fields(&proto_request.fields)?;
         let default_fields = CreateIndexRequest::parse_default_fields(&fields, &proto_request.default_fields)?;
-        let primary_key = CreateIndexRequest::parse_primary_key(&fields, &proto_request.primary_key)?;
+        let primary_key = proto_request.primary_key;
+        let similarities = CreateIndexRequest::parse_similarities(proto_request.similarities)?;
+        let vector_fields = CreateIndexRequest::parse_vector_fields(&fields, proto_request.vector_fields)?;
+        let version_field = CreateIndexRequest::parse_version_field(&fields, &primary_key, &proto_request.version_field)?;
+        let expiration = CreateIndexRequest::parse_expiration(&fields, &primary_key, proto_request.expiration)?;
+        let ingest_pipeline = CreateIndexRequest::parse_ingest_pipeline(&fields, proto_request.ingest_pipeline)?;
+        let unknown_fields = match proto::UnknownFields::from_i32(proto_request.unknown_fields) {
+            None | Some(proto::UnknownFields::Ignore) => UnknownFields::Ignore,
+            Some(proto::UnknownFields::Warn) => UnknownFields::Warn,
+            Some(proto::UnknownFields::Reject) => UnknownFields::Reject,
+        };
+        let version_conflict = match proto::VersionConflict::from_i32(proto_request.version_conflict) {
+            None | Some(proto::VersionConflict::Reject) => VersionConflict::Reject,
+            Some(proto::VersionConflict::Skip) => VersionConflict::Skip,
+        };
         let compression = proto::Compression::from_i32(proto_request.compression)
             .map(proto::Compression::into)
             .unwrap_or(tantivy::store::Compressor::None);
@@ -81,7 +180,11 @@ impl TryFrom<proto::CreateIndexRequest> for CreateIndexRequest {
             .primary_key(primary_key)
             .compression(compression)
             .default_fields(default_fields)
+            .expiration(expiration)
+            .flatten_nested(proto_request.flatten_nested)
+            .ingest_pipeline(ingest_pipeline)
             .multi_fields(multi_fields)
+            .similarities(similarities)
             .sort_by_field(proto_request.sort_by_field.map(proto::SortByField::into))
             .stop_words(if !proto_request.stop_words.is_empty() {
                 Some(proto_request.stop_words)
@@ -89,6 +192,10 @@ impl TryFrom<proto::CreateIndexRequest> for CreateIndexRequest {
                 None
             })
             .autocommit_interval_ms(proto_request.autocommit_interval_ms)
+            .unknown_fields(unknown_fields)
+            .version_field(version_field)
+            .version_conflict(version_conflict)
+            .vector_fields(vector_fields)
             .writer_threads(proto_request.writer_threads)
             .writer_heap_size_bytes(proto_request.writer_heap_size_bytes)
             .build()
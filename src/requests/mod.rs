@@ -3,9 +3,11 @@ mod create_consumer_request;
 mod create_index_request;
 mod delete_consumer_request;
 mod delete_index_request;
+mod reindex_request;
 
 pub use alter_index_request::{AlterIndexRequest, AlterIndexRequestBuilder};
 pub use create_consumer_request::{CreateConsumerRequest, CreateConsumerRequestBuilder};
 pub use create_index_request::{CreateIndexRequest, CreateIndexRequestBuilder};
 pub use delete_consumer_request::{DeleteConsumerRequest, DeleteConsumerRequestBuilder};
 pub use delete_index_request::{DeleteIndexRequest, DeleteIndexRequestBuilder};
+pub use reindex_request::{ReindexRequest, ReindexRequestBuilder};
@@ -0,0 +1,26 @@
+use crate::proto;
+use std::collections::HashMap;
+
+#[derive(Builder)]
+pub struct ReindexRequest {
+    pub source_index_alias: String,
+    pub target_index_alias: String,
+    #[builder(default = "None")]
+    pub query: Option<proto::Query>,
+    #[builder(default = "HashMap::new()")]
+    pub field_mapping: HashMap<String, String>,
+    #[builder(default = "None")]
+    pub index_alias: Option<String>,
+}
+
+impl From<proto::ReindexRequest> for ReindexRequest {
+    fn from(proto_request: proto::ReindexRequest) -> ReindexRequest {
+        ReindexRequest {
+            source_index_alias: proto_request.source_index_alias,
+            target_index_alias: proto_request.target_index_alias,
+            query: proto_request.query,
+            field_mapping: proto_request.field_mapping,
+            index_alias: proto_request.index_alias,
+        }
+    }
+}
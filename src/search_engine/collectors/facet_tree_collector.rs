@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashMap};
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::fastfield::FacetReader;
+use tantivy::schema::{Facet, Field};
+use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// Returns steps of the facet path, the root facet has no steps
+fn facet_path(facet: &Facet) -> Vec<&str> {
+    if facet.is_root() {
+        vec![]
+    } else {
+        facet.to_path()
+    }
+}
+
+/// `FacetTree` collector counts documents for requested facets and for all their descendants up to `depth` levels below
+///
+/// Unlike `tantivy::collector::FacetCollector` it allows requesting nested facets and counts every document
+/// once per facet even if the document has several facets belonging to the same subtree.
+///
+/// ```rust
+/// use summa::search_engine::collectors::FacetTree;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Facet, FacetOptions, Schema};
+/// use tantivy::{doc, Index};
+///
+/// let mut schema_builder = Schema::builder();
+/// let category = schema_builder.add_facet_field("category", FacetOptions::default());
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema);
+///
+/// let mut index_writer = index.writer(3_000_000).unwrap();
+/// index_writer.add_document(doc!(category => Facet::from("/science/biology/genetics"))).unwrap();
+/// index_writer.add_document(doc!(category => Facet::from("/science/biology/ecology"), category => Facet::from("/science/biology/genetics"))).unwrap();
+/// index_writer.add_document(doc!(category => Facet::from("/science/physics"))).unwrap();
+/// index_writer.add_document(doc!(category => Facet::from("/fiction"))).unwrap();
+/// assert!(index_writer.commit().is_ok());
+///
+/// let reader = index.reader().unwrap();
+/// let searcher = reader.searcher();
+///
+/// // Here comes the important part
+/// let facet_counts = searcher.search(&AllQuery, &FacetTree::for_field(category, vec![Facet::from("/science")], 2)).unwrap();
+///
+/// assert_eq!(facet_counts[&Facet::from("/science")], 3);
+/// assert_eq!(facet_counts[&Facet::from("/science/biology")], 2);
+/// assert_eq!(facet_counts[&Facet::from("/science/biology/genetics")], 2);
+/// assert!(!facet_counts.contains_key(&Facet::from("/fiction")));
+/// ```
+pub struct FacetTree {
+    field: Field,
+    facets: Vec<Facet>,
+    depth: usize,
+}
+
+impl FacetTree {
+    pub fn for_field(field: Field, facets: Vec<Facet>, depth: usize) -> FacetTree {
+        FacetTree { field, facets, depth }
+    }
+}
+
+impl Collector for FacetTree {
+    type Fruit = BTreeMap<Facet, u64>;
+
+    type Child = SegmentFacetTreeCollector;
+
+    fn for_segment(&self, _: SegmentOrdinal, segment_reader: &SegmentReader) -> tantivy::Result<SegmentFacetTreeCollector> {
+        Ok(SegmentFacetTreeCollector {
+            facet_reader: segment_reader.facet_reader(self.field)?,
+            facets: self.facets.clone(),
+            depth: self.depth,
+            ord_to_nodes: HashMap::new(),
+            node_ids: HashMap::new(),
+            nodes: vec![],
+            counts: vec![],
+            facet_ords_buffer: vec![],
+            doc_nodes_buffer: vec![],
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_facet_counts: Vec<BTreeMap<Facet, u64>>) -> tantivy::Result<BTreeMap<Facet, u64>> {
+        let mut facet_counts = BTreeMap::new();
+        for (facet, count) in segment_facet_counts.into_iter().flatten() {
+            *facet_counts.entry(facet).or_insert(0) += count;
+        }
+        Ok(facet_counts)
+    }
+}
+
+pub struct SegmentFacetTreeCollector {
+    facet_reader: FacetReader,
+    facets: Vec<Facet>,
+    depth: usize,
+    /// Counted nodes for every facet ordinal met in the segment
+    ord_to_nodes: HashMap<u64, Vec<usize>>,
+    node_ids: HashMap<Facet, usize>,
+    nodes: Vec<Facet>,
+    counts: Vec<u64>,
+    facet_ords_buffer: Vec<u64>,
+    doc_nodes_buffer: Vec<usize>,
+}
+
+impl SegmentFacetTreeCollector {
+    /// Finds all nodes of the requested subtrees that are ancestors of the facet with `facet_ord`
+    fn resolve_nodes(&mut self, facet_ord: u64) -> Vec<usize> {
+        let mut facet = Facet::root();
+        if self.facet_reader.facet_from_ord(facet_ord, &mut facet).is_err() {
+            return vec![];
+        }
+        let path = facet_path(&facet);
+        let mut resolved_nodes = vec![];
+        for requested_facet in &self.facets {
+            if !(requested_facet.is_root() || requested_facet == &facet || requested_facet.is_prefix_of(&facet)) {
+                continue;
+            }
+            let requested_depth = facet_path(requested_facet).len();
+            for node_depth in requested_depth..=path.len().min(requested_depth + self.depth) {
+                let node = Facet::from_path(&path[..node_depth]);
+                let node_id = match self.node_ids.get(&node) {
+                    Some(node_id) => *node_id,
+                    None => {
+                        self.nodes.push(node.clone());
+                        self.counts.push(0);
+                        self.node_ids.insert(node, self.nodes.len() - 1);
+                        self.nodes.len() - 1
+                    }
+                };
+                resolved_nodes.push(node_id);
+            }
+        }
+        resolved_nodes
+    }
+}
+
+impl SegmentCollector for SegmentFacetTreeCollector {
+    type Fruit = BTreeMap<Facet, u64>;
+
+    fn collect(&mut self, doc_id: DocId, _: Score) {
+        let mut facet_ords = std::mem::take(&mut self.facet_ords_buffer);
+        self.facet_reader.facet_ords(doc_id, &mut facet_ords);
+        self.doc_nodes_buffer.clear();
+        for facet_ord in &facet_ords {
+            if !self.ord_to_nodes.contains_key(facet_ord) {
+                let resolved_nodes = self.resolve_nodes(*facet_ord);
+                self.ord_to_nodes.insert(*facet_ord, resolved_nodes);
+            }
+            self.doc_nodes_buffer.extend(&self.ord_to_nodes[facet_ord]);
+        }
+        self.facet_ords_buffer = facet_ords;
+        // Documents having several facets within the same subtree are counted once for the subtree
+        self.doc_nodes_buffer.sort_unstable();
+        self.doc_nodes_buffer.dedup();
+        for node_id in &self.doc_nodes_buffer {
+            self.counts[*node_id] += 1;
+        }
+    }
+
+    fn harvest(self) -> BTreeMap<Facet, u64> {
+        self.nodes.into_iter().zip(self.counts).filter(|(_, count)| *count > 0).collect()
+    }
+}
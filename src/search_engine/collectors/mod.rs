@@ -1,3 +1,11 @@
+mod facet_tree_collector;
 mod reservoir_sampling_collector;
+mod significant_terms_collector;
+mod tweaked_top_docs_collector;
+mod weighted_reservoir_sampling_collector;
 
+pub use facet_tree_collector::FacetTree;
 pub use reservoir_sampling_collector::ReservoirSampling;
+pub use significant_terms_collector::{SignificanceHeuristic, SignificantTerms};
+pub use tweaked_top_docs_collector::{SegmentTweakedTopDocsCollector, TweakedTopDocs};
+pub use weighted_reservoir_sampling_collector::WeightedReservoirSampling;
@@ -39,13 +39,27 @@ use tantivy::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
 /// ```
 pub struct ReservoirSampling {
     limit: usize,
+    seed: Option<u64>,
 }
 
 /// Implements [Algorithm R](https://en.wikipedia.org/wiki/Reservoir_sampling#Simple_algorithm)
 /// for weighted sampling from the downstream `Fruit`. It uses `O(k)` memory and has `O(n)` time complexity.
 impl ReservoirSampling {
     pub fn with_limit(limit: usize) -> ReservoirSampling {
-        ReservoirSampling { limit }
+        ReservoirSampling { limit, seed: None }
+    }
+
+    /// Makes sampling reproducible for the same set of segments
+    pub fn with_seed(self, seed: u64) -> ReservoirSampling {
+        ReservoirSampling { seed: Some(seed), ..self }
+    }
+}
+
+/// Creates random number generator that is seeded from `seed` and `segment_ord` if `seed` is set and from entropy otherwise
+pub(crate) fn segment_rng(seed: Option<u64>, segment_ord: SegmentOrdinal) -> SmallRng {
+    match seed {
+        Some(seed) => SmallRng::seed_from_u64(seed ^ (segment_ord as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+        None => SmallRng::from_entropy(),
     }
 }
 
@@ -55,7 +69,7 @@ impl Collector for ReservoirSampling {
     type Child = SegmentReservoirSamplingCollector;
 
     fn for_segment(&self, segment_ord: SegmentOrdinal, _: &SegmentReader) -> tantivy::Result<SegmentReservoirSamplingCollector> {
-        Ok(SegmentReservoirSamplingCollector::new(segment_ord, self.limit))
+        Ok(SegmentReservoirSamplingCollector::new(segment_ord, self.limit, self.seed))
     }
 
     fn requires_scoring(&self) -> bool {
@@ -66,7 +80,10 @@ impl Collector for ReservoirSampling {
         let mut total_reservoir = vec![];
         let mut seen_documents = 0;
 
-        let mut rng = SmallRng::from_entropy();
+        let mut rng = match self.seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
 
         for (segment_docs, segment_size) in segment_docs_vec.iter().filter(|(_, segment_size)| *segment_size > 0) {
             // Tracking how much documents has been already taken from the current segment.
@@ -127,8 +144,8 @@ fn w_mul<TRng: Rng>(limit: usize, rng: &mut TRng) -> f64 {
 /// found by the upstream `Query`
 /// It uses `O(k)` memory and has `O(k*(1+log(n/k)))` time complexity
 impl SegmentReservoirSamplingCollector {
-    pub fn new(segment_ord: u32, limit: usize) -> SegmentReservoirSamplingCollector {
-        let mut rng = SmallRng::from_entropy();
+    pub fn new(segment_ord: u32, limit: usize, seed: Option<u64>) -> SegmentReservoirSamplingCollector {
+        let mut rng = segment_rng(seed, segment_ord);
 
         let w = 1f64 * w_mul(limit, &mut rng);
         let next_element = limit + gd_gap(w, &mut rng);
@@ -167,15 +184,39 @@ impl SegmentCollector for SegmentReservoirSamplingCollector {
 
 impl From<proto::ReservoirSamplingCollector> for ReservoirSampling {
     fn from(reservoir_sampling_collector: proto::ReservoirSamplingCollector) -> Self {
-        ReservoirSampling::with_limit(reservoir_sampling_collector.limit.try_into().unwrap())
+        let reservoir_sampling = ReservoirSampling::with_limit(reservoir_sampling_collector.limit.try_into().unwrap());
+        match reservoir_sampling_collector.seed {
+            Some(seed) => reservoir_sampling.with_seed(seed),
+            None => reservoir_sampling,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ReservoirSampling;
+    use crate::search_engine::collectors::WeightedReservoirSampling;
+    use crate::search_engine::scorers::EvalScorer;
 
     use tantivy::collector::Collector;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{Schema, FAST};
+    use tantivy::{doc, Index, LeasedItem, Searcher};
+
+    fn create_searcher() -> (Schema, LeasedItem<Searcher>) {
+        let mut schema_builder = Schema::builder();
+        let weight = schema_builder.add_u64_field("weight", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer(3_000_000).unwrap();
+        for segment in 0..3u64 {
+            for i in 0..100u64 {
+                index_writer.add_document(doc!(weight => (segment * 100 + i) % 2)).unwrap();
+            }
+            index_writer.commit().unwrap();
+        }
+        (schema, index.reader().unwrap().searcher())
+    }
 
     #[test]
     fn test_count_collect_does_not_requires_scoring() {
@@ -186,4 +227,27 @@ mod tests {
     fn test_border_cases() {
         assert!(!ReservoirSampling::with_limit(0).requires_scoring());
     }
+
+    #[test]
+    fn test_seeded_sampling() {
+        let (_, searcher) = create_searcher();
+        let collector = ReservoirSampling::with_limit(10).with_seed(42);
+        let documents = searcher.search(&AllQuery, &collector).unwrap();
+        assert_eq!(documents.len(), 10);
+        assert_eq!(documents, searcher.search(&AllQuery, &collector).unwrap());
+    }
+
+    #[test]
+    fn test_weighted_sampling() {
+        let (schema, searcher) = create_searcher();
+        let collector = WeightedReservoirSampling::new(20, EvalScorer::new("weight", &schema).unwrap()).with_seed(42);
+        let documents = searcher.search(&AllQuery, &collector).unwrap();
+        assert_eq!(documents.len(), 20);
+        assert_eq!(documents, searcher.search(&AllQuery, &collector).unwrap());
+        // Documents with zero weight have even ids
+        assert!(documents.iter().all(|doc_address| doc_address.doc_id % 2 == 1));
+
+        let collector = WeightedReservoirSampling::new(1000, EvalScorer::new("weight", &schema).unwrap());
+        assert_eq!(searcher.search(&AllQuery, &collector).unwrap().len(), 150);
+    }
 }
@@ -0,0 +1,216 @@
+use crate::proto;
+
+use std::collections::{HashMap, HashSet};
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::schema::Field;
+use tantivy::store::StoreReader;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// Scoring function that compares the frequency of a term in the foreground set with its frequency in the background set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignificanceHeuristic {
+    /// [JLH score](https://www.elastic.co/guide/en/elasticsearch/reference/current/search-aggregations-bucket-significantterms-aggregation.html#_jlh_score),
+    /// the absolute change in popularity multiplied by the relative change in popularity
+    Jlh,
+    /// [Pearson's chi-squared statistic](https://en.wikipedia.org/wiki/Pearson%27s_chi-squared_test) for the term occurrence in the foreground
+    /// and the rest of the background. Terms that are less frequent in the foreground than in the rest of the background are not significant.
+    ChiSquare,
+}
+
+impl SignificanceHeuristic {
+    /// Scores a term that occurs in `subset_freq` of `subset_size` foreground documents and
+    /// in `superset_freq` of `superset_size` background documents. The background is assumed to include the foreground.
+    ///
+    /// Returns `0.0` for terms that are not more frequent in the foreground than in the background.
+    pub fn score(&self, subset_freq: u64, subset_size: u64, superset_freq: u64, superset_size: u64) -> f64 {
+        if subset_size == 0 || superset_size == 0 || subset_freq == 0 {
+            return 0.0;
+        }
+        let superset_freq = superset_freq.max(subset_freq);
+        let superset_size = superset_size.max(subset_size);
+        match self {
+            SignificanceHeuristic::Jlh => {
+                let subset_probability = subset_freq as f64 / subset_size as f64;
+                let superset_probability = superset_freq as f64 / superset_size as f64;
+                if subset_probability > superset_probability {
+                    (subset_probability - superset_probability) * (subset_probability / superset_probability)
+                } else {
+                    0.0
+                }
+            }
+            SignificanceHeuristic::ChiSquare => {
+                // Contingency table of term occurrence (1/0) against set membership (foreground/rest of the background)
+                let n11 = subset_freq as f64;
+                let n01 = (subset_size - subset_freq) as f64;
+                let n10 = (superset_freq - subset_freq) as f64;
+                let n00 = (superset_size - subset_size) as f64 - n10;
+                let n_1 = n11 + n01;
+                let n_0 = n10 + n00;
+                let n1_ = n11 + n10;
+                let n0_ = n01 + n00;
+                let n = n_1 + n_0;
+                if n_0 == 0.0 || n0_ == 0.0 || n11 / n_1 <= n10 / n_0 {
+                    return 0.0;
+                }
+                n * (n11 * n00 - n10 * n01).powi(2) / (n_1 * n1_ * n0_ * n_0)
+            }
+        }
+    }
+}
+
+impl From<proto::SignificanceHeuristic> for SignificanceHeuristic {
+    fn from(significance_heuristic: proto::SignificanceHeuristic) -> Self {
+        match significance_heuristic {
+            proto::SignificanceHeuristic::Jlh => SignificanceHeuristic::Jlh,
+            proto::SignificanceHeuristic::ChiSquare => SignificanceHeuristic::ChiSquare,
+        }
+    }
+}
+
+/// `SignificantTerms` collector gathers foreground statistics for finding terms that characterise the set of documents matched by the query
+///
+/// Foreground is built by re-analyzing the stored text of at most `segment_size` matched documents per segment
+/// with the tokenizer of the field. Fruit is the number of foreground documents containing every term
+/// and the total number of analyzed documents. Background frequencies are taken from term dictionaries afterwards.
+///
+/// ```rust
+/// use summa::search_engine::collectors::{SignificanceHeuristic, SignificantTerms};
+/// use tantivy::query::QueryParser;
+/// use tantivy::schema::{Schema, TEXT, STORED};
+/// use tantivy::{doc, Index, Term};
+///
+/// let mut schema_builder = Schema::builder();
+/// let title = schema_builder.add_text_field("title", TEXT | STORED);
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema);
+///
+/// let mut index_writer = index.writer(3_000_000).unwrap();
+/// index_writer.add_document(doc!(title => "The Name of the Wind")).unwrap();
+/// index_writer.add_document(doc!(title => "The Diary of Muadib")).unwrap();
+/// index_writer.add_document(doc!(title => "A Dairy Cow")).unwrap();
+/// index_writer.add_document(doc!(title => "The Diary of a Young Girl")).unwrap();
+/// assert!(index_writer.commit().is_ok());
+///
+/// let reader = index.reader().unwrap();
+/// let searcher = reader.searcher();
+///
+/// // Here comes the important part
+/// let query_parser = QueryParser::for_index(&index, vec![title]);
+/// let query = query_parser.parse_query("diary").unwrap();
+/// let significant_terms = SignificantTerms::for_field(title, index.tokenizer_for_field(title).unwrap(), 100);
+/// let (doc_counts, subset_size) = searcher.search(&query, &significant_terms).unwrap();
+///
+/// assert_eq!(subset_size, 2);
+/// assert_eq!(doc_counts["diary"], 2);
+/// let superset_freq = searcher.doc_freq(&Term::from_field_text(title, "diary")).unwrap();
+/// assert!(SignificanceHeuristic::Jlh.score(doc_counts["diary"], subset_size, superset_freq, searcher.num_docs()) > 0.0);
+/// ```
+pub struct SignificantTerms {
+    field: Field,
+    text_analyzer: TextAnalyzer,
+    segment_size: usize,
+}
+
+impl SignificantTerms {
+    pub fn for_field(field: Field, text_analyzer: TextAnalyzer, segment_size: usize) -> SignificantTerms {
+        SignificantTerms {
+            field,
+            text_analyzer,
+            segment_size,
+        }
+    }
+}
+
+impl Collector for SignificantTerms {
+    type Fruit = (HashMap<String, u64>, u64);
+
+    type Child = SegmentSignificantTermsCollector;
+
+    fn for_segment(&self, _: SegmentOrdinal, segment_reader: &SegmentReader) -> tantivy::Result<SegmentSignificantTermsCollector> {
+        Ok(SegmentSignificantTermsCollector {
+            field: self.field,
+            text_analyzer: self.text_analyzer.clone(),
+            store_reader: segment_reader.get_store_reader()?,
+            segment_size: self.segment_size,
+            doc_counts: HashMap::new(),
+            analyzed_docs: 0,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<(HashMap<String, u64>, u64)>) -> tantivy::Result<(HashMap<String, u64>, u64)> {
+        let mut doc_counts = HashMap::new();
+        let mut analyzed_docs = 0;
+        for (segment_doc_counts, segment_analyzed_docs) in segment_fruits {
+            analyzed_docs += segment_analyzed_docs;
+            for (term, doc_count) in segment_doc_counts {
+                *doc_counts.entry(term).or_insert(0) += doc_count;
+            }
+        }
+        Ok((doc_counts, analyzed_docs))
+    }
+}
+
+pub struct SegmentSignificantTermsCollector {
+    field: Field,
+    text_analyzer: TextAnalyzer,
+    store_reader: StoreReader,
+    segment_size: usize,
+    doc_counts: HashMap<String, u64>,
+    analyzed_docs: u64,
+}
+
+impl SegmentCollector for SegmentSignificantTermsCollector {
+    type Fruit = (HashMap<String, u64>, u64);
+
+    fn collect(&mut self, doc_id: DocId, _: Score) {
+        if self.analyzed_docs as usize >= self.segment_size {
+            return;
+        }
+        let document = match self.store_reader.get(doc_id) {
+            Ok(document) => document,
+            Err(_) => return,
+        };
+        self.analyzed_docs += 1;
+        let mut document_terms = HashSet::new();
+        for text in document.get_all(self.field).filter_map(|value| value.as_text()) {
+            let mut token_stream = self.text_analyzer.token_stream(text);
+            while let Some(token) = token_stream.next() {
+                document_terms.insert(token.text.clone());
+            }
+        }
+        for term in document_terms {
+            *self.doc_counts.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    fn harvest(self) -> (HashMap<String, u64>, u64) {
+        (self.doc_counts, self.analyzed_docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignificanceHeuristic;
+
+    #[test]
+    fn test_jlh() {
+        // 50% of foreground and 10% of background: (0.5 - 0.1) * (0.5 / 0.1)
+        assert!((SignificanceHeuristic::Jlh.score(5, 10, 10, 100) - 2.0).abs() < 1e-9);
+        assert_eq!(SignificanceHeuristic::Jlh.score(1, 10, 10, 100), 0.0);
+        assert_eq!(SignificanceHeuristic::Jlh.score(0, 10, 10, 100), 0.0);
+    }
+
+    #[test]
+    fn test_chi_square() {
+        // Contingency table: [[5, 5], [5, 85]], N = 100
+        let expected = 100.0 * (5.0f64 * 85.0 - 5.0 * 5.0).powi(2) / (10.0 * 10.0 * 90.0 * 90.0);
+        assert!((SignificanceHeuristic::ChiSquare.score(5, 10, 10, 100) - expected).abs() < 1e-9);
+        assert_eq!(SignificanceHeuristic::ChiSquare.score(1, 10, 20, 100), 0.0);
+        assert_eq!(SignificanceHeuristic::ChiSquare.score(10, 10, 100, 100), 0.0);
+    }
+}
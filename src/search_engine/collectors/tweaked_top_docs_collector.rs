@@ -0,0 +1,135 @@
+use crate::errors::SummaResult;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// `TweakedTopDocs` collects top documents scored by segment scorers created by `scorer_factory`
+///
+/// It works like `TopDocs::tweak_score` but `scorer_factory` is fallible, so the search fails instead of panicking
+/// if the segment scorer cannot be created, i.e. if the fast field cannot be read in the segment.
+///
+/// ```rust
+/// use summa::search_engine::collectors::TweakedTopDocs;
+/// use tantivy::fastfield::FastFieldReader;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST};
+/// use tantivy::{doc, DocAddress, DocId, Index, Score, SegmentReader};
+///
+/// let mut schema_builder = Schema::builder();
+/// let popularity = schema_builder.add_u64_field("popularity", FAST);
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema.clone());
+///
+/// let mut index_writer = index.writer(3_000_000).unwrap();
+/// index_writer.add_document(doc!(popularity => 10u64)).unwrap();
+/// index_writer.add_document(doc!(popularity => 30u64)).unwrap();
+/// index_writer.add_document(doc!(popularity => 20u64)).unwrap();
+/// assert!(index_writer.commit().is_ok());
+///
+/// let reader = index.reader().unwrap();
+/// let searcher = reader.searcher();
+///
+/// // Here comes the important part
+/// let collector = TweakedTopDocs::new(2, 0, |segment_reader: &SegmentReader| {
+///     let popularity_reader = segment_reader.fast_fields().u64(popularity)?;
+///     Ok(move |doc_id: DocId, _original_score: Score| popularity_reader.get(doc_id) as f64)
+/// });
+/// let top_docs = searcher.search(&AllQuery, &collector).unwrap();
+///
+/// assert_eq!(top_docs, vec![(30.0, DocAddress::new(0, 1)), (20.0, DocAddress::new(0, 2))]);
+/// ```
+pub struct TweakedTopDocs<TScorerFactory, TSegmentScorer> {
+    limit: usize,
+    offset: usize,
+    scorer_factory: TScorerFactory,
+    _segment_scorer: PhantomData<fn() -> TSegmentScorer>,
+}
+
+impl<TScorerFactory, TSegmentScorer> TweakedTopDocs<TScorerFactory, TSegmentScorer>
+where
+    TScorerFactory: Fn(&SegmentReader) -> SummaResult<TSegmentScorer> + Send + Sync,
+    TSegmentScorer: FnMut(DocId, Score) -> f64 + 'static,
+{
+    pub fn new(limit: usize, offset: usize, scorer_factory: TScorerFactory) -> TweakedTopDocs<TScorerFactory, TSegmentScorer> {
+        TweakedTopDocs {
+            limit,
+            offset,
+            scorer_factory,
+            _segment_scorer: PhantomData,
+        }
+    }
+}
+
+fn compare_scores<T: Ord>(left: &(f64, T), right: &(f64, T)) -> Ordering {
+    right.0.partial_cmp(&left.0).unwrap_or(Ordering::Equal).then_with(|| left.1.cmp(&right.1))
+}
+
+impl<TScorerFactory, TSegmentScorer> Collector for TweakedTopDocs<TScorerFactory, TSegmentScorer>
+where
+    TScorerFactory: Fn(&SegmentReader) -> SummaResult<TSegmentScorer> + Send + Sync,
+    TSegmentScorer: FnMut(DocId, Score) -> f64 + 'static,
+{
+    type Fruit = Vec<(f64, DocAddress)>;
+
+    type Child = SegmentTweakedTopDocsCollector<TSegmentScorer>;
+
+    fn for_segment(&self, segment_ord: SegmentOrdinal, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(SegmentTweakedTopDocsCollector {
+            segment_ord,
+            limit: self.offset + self.limit,
+            segment_scorer: (self.scorer_factory)(segment_reader).map_err(|error| tantivy::TantivyError::InvalidArgument(error.to_string()))?,
+            top_docs: vec![],
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_top_docs: Vec<Vec<(f64, DocAddress)>>) -> tantivy::Result<Vec<(f64, DocAddress)>> {
+        let mut top_docs: Vec<_> = segment_top_docs.into_iter().flatten().collect();
+        top_docs.sort_by(compare_scores);
+        Ok(top_docs.into_iter().skip(self.offset).take(self.limit).collect())
+    }
+}
+
+pub struct SegmentTweakedTopDocsCollector<TSegmentScorer> {
+    segment_ord: SegmentOrdinal,
+    limit: usize,
+    segment_scorer: TSegmentScorer,
+    top_docs: Vec<(f64, DocId)>,
+}
+
+impl<TSegmentScorer: FnMut(DocId, Score) -> f64> SegmentTweakedTopDocsCollector<TSegmentScorer> {
+    /// Keeps only `limit` top documents, it is called when the buffer doubles so collecting has amortized `O(1)` time complexity
+    fn truncate(&mut self) {
+        if self.top_docs.len() > self.limit {
+            if self.limit > 0 {
+                self.top_docs.select_nth_unstable_by(self.limit - 1, compare_scores);
+            }
+            self.top_docs.truncate(self.limit);
+        }
+    }
+}
+
+impl<TSegmentScorer: FnMut(DocId, Score) -> f64 + 'static> SegmentCollector for SegmentTweakedTopDocsCollector<TSegmentScorer> {
+    type Fruit = Vec<(f64, DocAddress)>;
+
+    fn collect(&mut self, doc_id: DocId, score: Score) {
+        let score = (self.segment_scorer)(doc_id, score);
+        self.top_docs.push((score, doc_id));
+        if self.top_docs.len() >= 2 * self.limit.max(1) {
+            self.truncate();
+        }
+    }
+
+    fn harvest(mut self) -> Self::Fruit {
+        self.truncate();
+        let segment_ord = self.segment_ord;
+        self.top_docs
+            .into_iter()
+            .map(|(score, doc_id)| (score, DocAddress::new(segment_ord, doc_id)))
+            .collect()
+    }
+}
@@ -0,0 +1,163 @@
+use super::reservoir_sampling_collector::segment_rng;
+use crate::search_engine::scorers::{EvalScorer, SegmentEvalScorer};
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
+
+/// `WeightedReservoirSampling` collector collects `k` random documents with probabilities proportional to their weights
+///
+/// Weight of the document is calculated by `EvalScorer` so it may be a fast field or an expression over fast fields.
+/// Documents with non-positive weights are never sampled.
+///
+/// ```rust
+/// use summa::search_engine::collectors::WeightedReservoirSampling;
+/// use summa::search_engine::scorers::EvalScorer;
+/// use tantivy::query::AllQuery;
+/// use tantivy::schema::{Schema, FAST, STORED};
+/// use tantivy::{doc, Index};
+///
+/// let mut schema_builder = Schema::builder();
+/// let popularity = schema_builder.add_u64_field("popularity", FAST | STORED);
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema.clone());
+///
+/// let mut index_writer = index.writer(3_000_000).unwrap();
+/// index_writer.add_document(doc!(popularity => 0u64)).unwrap();
+/// index_writer.add_document(doc!(popularity => 10u64)).unwrap();
+/// index_writer.add_document(doc!(popularity => 0u64)).unwrap();
+/// index_writer.add_document(doc!(popularity => 20u64)).unwrap();
+/// assert!(index_writer.commit().is_ok());
+///
+/// let reader = index.reader().unwrap();
+/// let searcher = reader.searcher();
+///
+/// // Here comes the important part
+/// let collector = WeightedReservoirSampling::new(3, EvalScorer::new("popularity", &schema).unwrap()).with_seed(42);
+/// let documents = searcher.search(&AllQuery, &collector).unwrap();
+///
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents, searcher.search(&AllQuery, &collector).unwrap());
+/// ```
+pub struct WeightedReservoirSampling {
+    limit: usize,
+    eval_scorer: EvalScorer,
+    seed: Option<u64>,
+}
+
+/// Implements [Algorithm A-Res](https://en.wikipedia.org/wiki/Reservoir_sampling#Algorithm_A-Res) for weighted sampling.
+/// Every document gets a key `ln(u) / w` where `u` is uniformly distributed in `(0, 1)` and `w` is the weight of the document,
+/// and `k` documents with the largest keys are selected. It uses `O(k)` memory and has `O(n log k)` time complexity as the
+/// smallest of the selected keys is kept on the top of a heap.
+impl WeightedReservoirSampling {
+    pub fn new(limit: usize, eval_scorer: EvalScorer) -> WeightedReservoirSampling {
+        WeightedReservoirSampling {
+            limit,
+            eval_scorer,
+            seed: None,
+        }
+    }
+
+    /// Makes sampling reproducible for the same set of segments
+    pub fn with_seed(self, seed: u64) -> WeightedReservoirSampling {
+        WeightedReservoirSampling { seed: Some(seed), ..self }
+    }
+}
+
+fn compare_keys(left: &(f64, DocAddress), right: &(f64, DocAddress)) -> Ordering {
+    right.0.partial_cmp(&left.0).unwrap_or(Ordering::Equal).then_with(|| left.1.cmp(&right.1))
+}
+
+impl Collector for WeightedReservoirSampling {
+    type Fruit = Vec<DocAddress>;
+
+    type Child = SegmentWeightedReservoirSamplingCollector;
+
+    fn for_segment(&self, segment_ord: SegmentOrdinal, segment_reader: &SegmentReader) -> tantivy::Result<SegmentWeightedReservoirSamplingCollector> {
+        Ok(SegmentWeightedReservoirSamplingCollector {
+            segment_ord,
+            reservoir: BinaryHeap::with_capacity(self.limit),
+            limit: self.limit,
+            eval_scorer: self
+                .eval_scorer
+                .get_for_segment_reader(segment_reader)
+                .map_err(|error| tantivy::TantivyError::InvalidArgument(error.to_string()))?,
+            rng: segment_rng(self.seed, segment_ord),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segment_reservoirs: Vec<Vec<(f64, DocAddress)>>) -> tantivy::Result<Vec<DocAddress>> {
+        // Keys of A-Res are comparable across segments so merging is just taking `k` largest keys
+        let mut total_reservoir: Vec<_> = segment_reservoirs.into_iter().flatten().collect();
+        total_reservoir.sort_by(compare_keys);
+        Ok(total_reservoir.into_iter().take(self.limit).map(|(_, doc_address)| doc_address).collect())
+    }
+}
+
+/// Key of A-Res ordered by `f64::total_cmp` to be kept in `BinaryHeap`
+#[derive(Clone, Copy, Debug)]
+struct ReservoirKey(f64);
+
+impl PartialEq for ReservoirKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ReservoirKey {}
+
+impl PartialOrd for ReservoirKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ReservoirKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+pub struct SegmentWeightedReservoirSamplingCollector {
+    segment_ord: u32,
+    reservoir: BinaryHeap<Reverse<(ReservoirKey, DocAddress)>>,
+    limit: usize,
+    eval_scorer: SegmentEvalScorer,
+    rng: SmallRng,
+}
+
+impl SegmentCollector for SegmentWeightedReservoirSamplingCollector {
+    type Fruit = Vec<(f64, DocAddress)>;
+
+    fn collect(&mut self, doc_id: DocId, _: Score) {
+        let weight = self.eval_scorer.score(doc_id, 0.0);
+        if weight.is_nan() || weight <= 0.0 || self.limit == 0 {
+            return;
+        }
+        // `1 - gen()` lies in `(0, 1]` so the logarithm is always finite
+        let key = (1.0 - self.rng.gen::<f64>()).ln() / weight;
+        let doc_address = DocAddress::new(self.segment_ord, doc_id);
+        if self.reservoir.len() < self.limit {
+            self.reservoir.push(Reverse((ReservoirKey(key), doc_address)));
+        } else if let Some(mut min_item) = self.reservoir.peek_mut() {
+            let Reverse((ReservoirKey(min_key), _)) = *min_item;
+            if key > min_key {
+                *min_item = Reverse((ReservoirKey(key), doc_address));
+            }
+        }
+    }
+
+    fn harvest(self) -> Vec<(f64, DocAddress)> {
+        self.reservoir
+            .into_iter()
+            .map(|Reverse((ReservoirKey(key), doc_address))| (key, doc_address))
+            .collect()
+    }
+}
@@ -0,0 +1,146 @@
+use crate::errors::SummaResult;
+use crate::search_engine::scorers::{fast_field_to_iter, FastFieldIterator};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use tantivy::schema::Field;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{DocAddress, Document, Searcher, SegmentOrdinal};
+
+/// Similarity of documents that is penalized by the diversification
+pub enum DocumentSimilarity {
+    /// Jaccard similarity of sets of tokens of the stored text field
+    TermOverlap { field: Field, text_analyzer: TextAnalyzer },
+    /// Documents having equal values of the fast field are similar, i.e. documents of the same author
+    FastFieldEquality { field_name: String },
+}
+
+enum DocumentFeatures {
+    Terms(HashSet<String>),
+    Value(f64),
+}
+
+impl DocumentFeatures {
+    fn similarity(&self, other: &DocumentFeatures) -> f64 {
+        match (self, other) {
+            (DocumentFeatures::Terms(terms), DocumentFeatures::Terms(other_terms)) => {
+                let union_size = terms.union(other_terms).count();
+                if union_size == 0 {
+                    0.0
+                } else {
+                    terms.intersection(other_terms).count() as f64 / union_size as f64
+                }
+            }
+            (DocumentFeatures::Value(value), DocumentFeatures::Value(other_value)) => (value == other_value) as u8 as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Reorders top documents with [maximal marginal relevance](https://www.cs.cmu.edu/~jgc/publication/The_Use_MMR_Diversity_Based_LTMIR_1998.pdf)
+///
+/// Documents are selected one by one maximizing `lambda * relevance - (1 - lambda) * max_similarity` where `relevance` is
+/// the score of the document min-max normalized over the window and `max_similarity` is the largest similarity to already selected documents.
+pub struct Diversifier {
+    window_size: usize,
+    lambda: f64,
+    document_similarity: DocumentSimilarity,
+}
+
+impl Diversifier {
+    pub fn new(window_size: usize, lambda: f64, document_similarity: DocumentSimilarity) -> Diversifier {
+        Diversifier {
+            window_size,
+            lambda,
+            document_similarity,
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// Extracts features of the document, fast field readers are opened once per segment and kept in `fast_field_iterators`
+    fn document_features(
+        &self,
+        searcher: &Searcher,
+        document: &mut Option<Document>,
+        doc_address: DocAddress,
+        fast_field_iterators: &mut HashMap<SegmentOrdinal, Box<dyn FastFieldIterator>>,
+    ) -> SummaResult<DocumentFeatures> {
+        Ok(match &self.document_similarity {
+            DocumentSimilarity::TermOverlap { field, text_analyzer } => {
+                if document.is_none() {
+                    *document = Some(searcher.doc(doc_address)?);
+                }
+                let document = document.as_ref().expect("document is loaded above");
+                let mut terms = HashSet::new();
+                for text in document.get_all(*field).filter_map(|value| value.as_text()) {
+                    text_analyzer.token_stream(text).process(&mut |token| {
+                        terms.insert(token.text.clone());
+                    });
+                }
+                DocumentFeatures::Terms(terms)
+            }
+            DocumentSimilarity::FastFieldEquality { field_name } => {
+                let fast_field_iterator = match fast_field_iterators.entry(doc_address.segment_ord) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(fast_field_to_iter(
+                        searcher.schema(),
+                        searcher.segment_reader(doc_address.segment_ord),
+                        field_name,
+                    )?),
+                };
+                fast_field_iterator.advance(doc_address.doc_id);
+                DocumentFeatures::Value(*fast_field_iterator.value())
+            }
+        })
+    }
+
+    /// Reorders top `window_size` documents of `ranked_documents` sorted by descending scores, documents keep their scores
+    pub fn diversify(&self, searcher: &Searcher, ranked_documents: &mut Vec<(f64, Option<Document>, DocAddress)>) -> SummaResult<()> {
+        let window_size = self.window_size.min(ranked_documents.len());
+        let mut candidates: Vec<_> = ranked_documents.drain(..window_size).collect();
+        let mut fast_field_iterators = HashMap::new();
+        let features = candidates
+            .iter_mut()
+            .map(|(_, document, doc_address)| self.document_features(searcher, document, *doc_address, &mut fast_field_iterators))
+            .collect::<SummaResult<Vec<_>>>()?;
+        let min_score = candidates.iter().map(|(score, _, _)| *score).fold(f64::INFINITY, f64::min);
+        let max_score = candidates.iter().map(|(score, _, _)| *score).fold(f64::NEG_INFINITY, f64::max);
+        let relevances: Vec<_> = candidates
+            .iter()
+            .map(|(score, _, _)| {
+                if max_score > min_score {
+                    (score - min_score) / (max_score - min_score)
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        let mut max_similarities = vec![0f64; candidates.len()];
+        let mut is_selected = vec![false; candidates.len()];
+        let mut selected = Vec::with_capacity(candidates.len());
+        for _ in 0..candidates.len() {
+            let mut best: Option<(usize, f64)> = None;
+            for position in (0..candidates.len()).filter(|position| !is_selected[*position]) {
+                let marginal_relevance = self.lambda * relevances[position] - (1.0 - self.lambda) * max_similarities[position];
+                // Ties are resolved in favour of the document ranked higher
+                if best.map(|(_, best_relevance)| marginal_relevance > best_relevance).unwrap_or(true) {
+                    best = Some((position, marginal_relevance));
+                }
+            }
+            let (best_position, _) = best.expect("at least one document is not selected yet");
+            is_selected[best_position] = true;
+            selected.push(best_position);
+            for position in (0..candidates.len()).filter(|position| !is_selected[*position]) {
+                max_similarities[position] = max_similarities[position].max(features[best_position].similarity(&features[position]));
+            }
+        }
+
+        let mut candidates: Vec<_> = candidates.into_iter().map(Some).collect();
+        let diversified: Vec<_> = selected.into_iter().map(|position| candidates[position].take().unwrap()).collect();
+        ranked_documents.splice(0..0, diversified);
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use super::{DocumentParsingError, IngestPipeline, SummaDocument};
+use crate::configs::{IndexConfig, UnknownFields};
+use crate::errors::{Error, SummaResult, ValidationError};
+use crate::proto;
+use opentelemetry::metrics::Counter;
+use opentelemetry::{global, KeyValue};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::collections::HashMap;
+use tantivy::schema::{Field, FieldType, Schema as Fields};
+use tantivy::Document;
+use tracing::warn;
+
+/// Appends `json_value` to the value of `key`, turning the value into an array if required
+fn append_value(json_object: &mut JsonMap<String, JsonValue>, key: String, json_value: JsonValue) {
+    match json_object.get_mut(&key) {
+        None => {
+            json_object.insert(key, json_value);
+        }
+        Some(JsonValue::Array(items)) => match json_value {
+            JsonValue::Array(new_items) => items.extend(new_items),
+            json_value => items.push(json_value),
+        },
+        Some(existing_value) => {
+            let mut items = vec![existing_value.take()];
+            match json_value {
+                JsonValue::Array(new_items) => items.extend(new_items),
+                json_value => items.push(json_value),
+            }
+            *existing_value = JsonValue::Array(items);
+        }
+    }
+}
+
+fn flatten_into(fields: &Fields, prefix: &str, json_object: JsonMap<String, JsonValue>, flattened: &mut JsonMap<String, JsonValue>) {
+    for (key, json_value) in json_object {
+        let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+        // Values of schema fields are kept as is, so `JsonObject` fields still receive objects
+        if fields.get_field(&key).is_some() {
+            append_value(flattened, key, json_value);
+            continue;
+        }
+        match json_value {
+            JsonValue::Object(nested_object) => flatten_into(fields, &key, nested_object, flattened),
+            JsonValue::Array(items) if items.iter().any(JsonValue::is_object) => {
+                for item in items {
+                    match item {
+                        JsonValue::Object(nested_object) => flatten_into(fields, &key, nested_object, flattened),
+                        item => append_value(flattened, key.clone(), item),
+                    }
+                }
+            }
+            json_value => append_value(flattened, key, json_value),
+        }
+    }
+}
+
+/// Flattens nested objects into dotted keys, i.e. `{"author": {"name": "x"}}` into `{"author.name": "x"}`
+///
+/// Values of the same dotted key from arrays of objects are collected into arrays. Keys that are fields of the schema
+/// are not flattened.
+pub(crate) fn flatten_json_object(fields: &Fields, json_object: JsonMap<String, JsonValue>) -> JsonMap<String, JsonValue> {
+    let mut flattened = JsonMap::new();
+    flatten_into(fields, "", json_object, &mut flattened);
+    flattened
+}
+
+/// Checks that the field may keep vectors of `dimension`
+///
+/// Vectors are stored in bytes fast fields as little-endian `f32` values, so `VectorQuery` may scan them.
+pub(crate) fn validate_vector_field(fields: &Fields, vector_field: Field, dimension: usize) -> SummaResult<()> {
+    let field_entry = fields.get_field_entry(vector_field);
+    match field_entry.field_type() {
+        FieldType::Bytes(options) if options.is_fast() => (),
+        another_type => {
+            return Err(ValidationError::InvalidFastFieldType {
+                field: field_entry.name().to_owned(),
+                field_type: another_type.to_owned(),
+            }
+            .into())
+        }
+    }
+    if dimension == 0 {
+        return Err(ValidationError::EmptyArgument(format!("dimension of {}", field_entry.name())).into());
+    }
+    Ok(())
+}
+
+/// Converts incoming documents into `tantivy::Document` according to the config of the index
+///
+/// JSON documents are flattened if `flatten_nested` is set and then passed through the ingest pipeline. Remaining keys
+/// that are absent in the schema are handled according to `unknown_fields`. Values of `vector_fields` must be arrays
+/// of numbers of the dimension of the field.
+/// CBOR and MessagePack documents are converted into `tantivy::Document` without JSON unless they are flattened or
+/// transformed by the ingest pipeline, in that case their byte strings are passed to the pipeline as base64 strings
+pub(crate) struct DocumentParser {
+    index_name: String,
+    fields: Fields,
+    flatten_nested: bool,
+    ingest_pipeline: IngestPipeline,
+    unknown_fields: UnknownFields,
+    vector_fields: HashMap<Field, usize>,
+    // Counters
+    dropped_fields_counter: Counter<u64>,
+}
+
+impl DocumentParser {
+    pub fn new(index_name: &str, index_config: &IndexConfig, fields: &Fields) -> SummaResult<DocumentParser> {
+        let dropped_fields_counter = global::meter("summa")
+            .u64_counter("dropped_fields_counter")
+            .with_description("Unknown fields dropped from indexed documents")
+            .init();
+        let vector_fields = index_config
+            .vector_fields
+            .iter()
+            .map(|(field_name, dimension)| {
+                let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+                validate_vector_field(fields, field, *dimension)?;
+                Ok((field, *dimension))
+            })
+            .collect::<SummaResult<_>>()?;
+        Ok(DocumentParser {
+            index_name: index_name.to_owned(),
+            fields: fields.clone(),
+            flatten_nested: index_config.flatten_nested,
+            ingest_pipeline: IngestPipeline::new(&index_config.ingest_pipeline, fields)?,
+            unknown_fields: index_config.unknown_fields,
+            vector_fields,
+            dropped_fields_counter,
+        })
+    }
+
+    /// Rejects or accounts keys of `json_object` that are absent in the schema
+    fn check_unknown_fields(&self, json_object: &JsonMap<String, JsonValue>) -> SummaResult<()> {
+        self.check_unknown_field_names(
+            json_object
+                .keys()
+                .filter(|field_name| self.fields.get_field(field_name).is_none())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Rejects or accounts `unknown_fields` of the document
+    fn check_unknown_field_names(&self, unknown_fields: Vec<String>) -> SummaResult<()> {
+        if unknown_fields.is_empty() {
+            return Ok(());
+        }
+        match self.unknown_fields {
+            UnknownFields::Reject => return Err(DocumentParsingError::UnknownFields(unknown_fields).into()),
+            UnknownFields::Warn => warn!(action = "dropped_unknown_fields", unknown_fields = ?unknown_fields),
+            UnknownFields::Ignore => (),
+        }
+        self.dropped_fields_counter
+            .add(unknown_fields.len() as u64, &[KeyValue::new("index_name", self.index_name.to_owned())]);
+        Ok(())
+    }
+
+    /// Parses bytes encoded in `format` into JSON object ready for conversion into `tantivy::Document`
+    pub fn json_object(&self, format: proto::DocumentFormat, bytes: &[u8]) -> SummaResult<JsonMap<String, JsonValue>> {
+        let mut json_object = SummaDocument::parse_json_object_with_format(format, bytes)?;
+        if self.flatten_nested {
+            json_object = flatten_json_object(&self.fields, json_object);
+        }
+        self.ingest_pipeline.apply(&mut json_object)?;
+        Ok(json_object)
+    }
+
+    /// Converts the part of JSON document used for partial updates into `tantivy::Document`
+    ///
+    /// It is parsed like whole documents except for ingest steps that are skipped by `IngestPipeline::apply_partial`
+    pub fn parse_partial(&self, bytes: &[u8]) -> SummaResult<Document> {
+        let mut json_object = SummaDocument::parse_json_object_with_format(proto::DocumentFormat::Json, bytes)?;
+        if self.flatten_nested {
+            json_object = flatten_json_object(&self.fields, json_object);
+        }
+        self.ingest_pipeline.apply_partial(&mut json_object)?;
+        self.check_unknown_fields(&json_object)?;
+        SummaDocument::UnboundJsonBytes(bytes).json_object_to_doc(&self.fields, &self.vector_fields, json_object)
+    }
+
+    /// Converts `SummaDocument` into `tantivy::Document`
+    pub fn parse(&self, document: SummaDocument<'_>) -> SummaResult<Document> {
+        match document {
+            SummaDocument::UnboundBytes((format @ (proto::DocumentFormat::Cbor | proto::DocumentFormat::MessagePack), bytes))
+                if !self.flatten_nested && self.ingest_pipeline.is_empty() =>
+            {
+                let (parsed_document, unknown_fields) = document.parse_binary_document(&self.fields, &self.vector_fields, format, bytes)?;
+                self.check_unknown_field_names(unknown_fields)?;
+                Ok(parsed_document)
+            }
+            SummaDocument::BoundJsonBytes((_, bytes)) | SummaDocument::UnboundJsonBytes(bytes) | SummaDocument::UnboundBytes((_, bytes)) => {
+                let format = match document {
+                    SummaDocument::UnboundBytes((format, _)) => format,
+                    _ => proto::DocumentFormat::Json,
+                };
+                let json_object = self.json_object(format, bytes)?;
+                self.check_unknown_fields(&json_object)?;
+                document.json_object_to_doc(&self.fields, &self.vector_fields, json_object)
+            }
+            SummaDocument::TantivyDocument(document) => Ok(document),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::{IndexConfigBuilder, IndexEngine, IngestStep};
+    use crate::errors::Error;
+    use crate::search_engine::queries::encode_vector;
+    use crate::search_engine::ValueParsingError;
+    use serde_json::json;
+    use tantivy::schema::{BytesOptions, Value, STORED, TEXT};
+
+    #[test]
+    fn test_flatten_json_object() {
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_text_field("author.name", TEXT | STORED);
+        fields_builder.add_json_field("metadata", STORED);
+        let fields = fields_builder.build();
+
+        let json_object = json!({
+            "title": "Title",
+            "author": {"name": "Alice", "affiliation": {"country": "UK"}},
+            "references": [{"doi": "10.1/1", "tags": ["a", "b"]}, {"doi": "10.1/2", "tags": ["c"]}, "10.1/3"],
+            "metadata": {"nested": {"key": 1}},
+        });
+        assert_eq!(
+            JsonValue::Object(flatten_json_object(&fields, json_object.as_object().unwrap().clone())),
+            json!({
+                "title": "Title",
+                "author.name": "Alice",
+                "author.affiliation.country": "UK",
+                "references.doi": ["10.1/1", "10.1/2"],
+                "references.tags": ["a", "b", "c"],
+                "references": "10.1/3",
+                "metadata": {"nested": {"key": 1}},
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_text_field("title", TEXT | STORED);
+        fields_builder.add_text_field("author.name", TEXT | STORED);
+        let fields = fields_builder.build();
+        let document_parser = |unknown_fields: UnknownFields| {
+            let index_config = IndexConfigBuilder::default()
+                .index_engine(IndexEngine::Memory(fields.clone()))
+                .flatten_nested(true)
+                .unknown_fields(unknown_fields)
+                .build()
+                .unwrap();
+            DocumentParser::new("test_index", &index_config, &fields).unwrap()
+        };
+        let document = br#"{"title": "Title", "author": {"name": "Alice", "email": "alice@example.com"}, "year": 2022}"#;
+
+        for unknown_fields in [UnknownFields::Ignore, UnknownFields::Warn] {
+            let parsed_document = document_parser(unknown_fields).parse(SummaDocument::UnboundJsonBytes(document)).unwrap();
+            assert_eq!(parsed_document.len(), 2);
+        }
+        match document_parser(UnknownFields::Reject).parse(SummaDocument::UnboundJsonBytes(document)) {
+            Err(Error::DocumentParsing(DocumentParsingError::UnknownFields(unknown_fields))) => {
+                assert_eq!(unknown_fields, vec!["author.email".to_owned(), "year".to_owned()])
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        assert!(document_parser(UnknownFields::Reject)
+            .parse(SummaDocument::UnboundJsonBytes(br#"{"title": "Title"}"#))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_document_formats() {
+        let mut fields_builder = Fields::builder();
+        let title = fields_builder.add_text_field("title", TEXT | STORED);
+        let year = fields_builder.add_i64_field("year", STORED);
+        let hash = fields_builder.add_bytes_field("hash", STORED);
+        let fields = fields_builder.build();
+        let index_config = IndexConfigBuilder::default().index_engine(IndexEngine::Memory(fields.clone())).build().unwrap();
+        let document_parser = DocumentParser::new("test_index", &index_config, &fields).unwrap();
+
+        let binary_document = ciborium::value::Value::Map(vec![
+            ("title".into(), "Title".into()),
+            ("year".into(), 2022.into()),
+            ("hash".into(), ciborium::value::Value::Bytes(vec![1, 2, 3])),
+        ]);
+        let mut cbor_document = vec![];
+        ciborium::ser::into_writer(&binary_document, &mut cbor_document).unwrap();
+        let message_pack_document = rmp_serde::to_vec(&binary_document).unwrap();
+        let protobuf_struct_document = prost::Message::encode_to_vec(&prost_types::Struct {
+            fields: [
+                ("title", prost_types::value::Kind::StringValue("Title".to_owned())),
+                ("year", prost_types::value::Kind::NumberValue(2022.0)),
+                ("hash", prost_types::value::Kind::StringValue(base64::encode([1, 2, 3]))),
+            ]
+            .into_iter()
+            .map(|(key, kind)| (key.to_owned(), prost_types::Value { kind: Some(kind) }))
+            .collect(),
+        });
+        let json_document = format!(r#"{{"title": "Title", "year": 2022, "hash": "{}"}}"#, base64::encode([1, 2, 3]));
+
+        for (format, document) in [
+            (proto::DocumentFormat::Json, json_document.as_bytes()),
+            (proto::DocumentFormat::Cbor, &cbor_document),
+            (proto::DocumentFormat::MessagePack, &message_pack_document),
+            (proto::DocumentFormat::ProtobufStruct, &protobuf_struct_document),
+        ] {
+            let parsed_document = document_parser.parse(SummaDocument::UnboundBytes((format, document))).unwrap();
+            assert_eq!(parsed_document.get_first(title), Some(&Value::Str("Title".to_owned())), "{:?}", format);
+            assert_eq!(parsed_document.get_first(year), Some(&Value::I64(2022)), "{:?}", format);
+            assert_eq!(parsed_document.get_first(hash), Some(&Value::Bytes(vec![1, 2, 3])), "{:?}", format);
+        }
+        assert!(matches!(
+            document_parser.parse(SummaDocument::UnboundBytes((proto::DocumentFormat::Cbor, b"\xff"))),
+            Err(Error::DocumentParsing(DocumentParsingError::InvalidEncoding { .. }))
+        ));
+
+        // Byte strings are accepted only by bytes fields
+        let binary_title_document = rmp_serde::to_vec(&ciborium::value::Value::Map(vec![(
+            "title".into(),
+            ciborium::value::Value::Bytes(b"Title".to_vec()),
+        )]))
+        .unwrap();
+        assert!(matches!(
+            document_parser.parse(SummaDocument::UnboundBytes((proto::DocumentFormat::MessagePack, &binary_title_document))),
+            Err(Error::DocumentParsing(DocumentParsingError::ValueError(
+                _,
+                ValueParsingError::UnexpectedBytes { .. }
+            )))
+        ));
+
+        // Documents transformed by the ingest pipeline are converted through JSON with base64 strings for byte strings
+        let index_config = IndexConfigBuilder::default()
+            .index_engine(IndexEngine::Memory(fields.clone()))
+            .ingest_pipeline(vec![IngestStep::Trim { field: "title".to_owned() }])
+            .build()
+            .unwrap();
+        let document_parser = DocumentParser::new("test_index", &index_config, &fields).unwrap();
+        let parsed_document = document_parser
+            .parse(SummaDocument::UnboundBytes((proto::DocumentFormat::Cbor, &cbor_document)))
+            .unwrap();
+        assert_eq!(parsed_document.get_first(hash), Some(&Value::Bytes(vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn test_vector_fields() {
+        let mut fields_builder = Fields::builder();
+        let embedding = fields_builder.add_bytes_field("embedding", BytesOptions::default().set_fast().set_stored());
+        fields_builder.add_bytes_field("hash", STORED);
+        let fields = fields_builder.build();
+        let index_config = IndexConfigBuilder::default()
+            .index_engine(IndexEngine::Memory(fields.clone()))
+            .vector_fields(HashMap::from([("embedding".to_owned(), 2)]))
+            .build()
+            .unwrap();
+        let document_parser = DocumentParser::new("test_index", &index_config, &fields).unwrap();
+        let encoded_vector = Value::Bytes(encode_vector(&[0.5, 1.0]));
+
+        let binary_document = ciborium::value::Value::Map(vec![("embedding".into(), ciborium::value::Value::Array(vec![0.5.into(), 1.into()]))]);
+        let mut cbor_document = vec![];
+        ciborium::ser::into_writer(&binary_document, &mut cbor_document).unwrap();
+        let stored_document = format!(r#"{{"embedding": ["{}"]}}"#, base64::encode(encode_vector(&[0.5, 1.0])));
+        for (format, document) in [
+            (proto::DocumentFormat::Json, br#"{"embedding": [0.5, 1]}"#.as_slice()),
+            (proto::DocumentFormat::Json, stored_document.as_bytes()),
+            (proto::DocumentFormat::Cbor, &cbor_document),
+        ] {
+            let parsed_document = document_parser.parse(SummaDocument::UnboundBytes((format, document))).unwrap();
+            assert_eq!(parsed_document.get_first(embedding), Some(&encoded_vector), "{:?}", format);
+        }
+
+        assert!(matches!(
+            document_parser.parse(SummaDocument::UnboundJsonBytes(br#"{"embedding": [0.5, 1.0, 2.0]}"#)),
+            Err(Error::DocumentParsing(DocumentParsingError::ValueError(
+                _,
+                ValueParsingError::DimensionError { expected: 2, got: 3 }
+            )))
+        ));
+        assert!(document_parser
+            .parse(SummaDocument::UnboundJsonBytes(br#"{"embedding": [0.5, "1.0"]}"#))
+            .is_err());
+        // Arrays of numbers are not vectors for other bytes fields
+        assert!(document_parser.parse(SummaDocument::UnboundJsonBytes(br#"{"hash": [0.5, 1.0]}"#)).is_err());
+
+        for (field_name, dimension) in [("hash", 2), ("embedding", 0)] {
+            let index_config = IndexConfigBuilder::default()
+                .index_engine(IndexEngine::Memory(fields.clone()))
+                .vector_fields(HashMap::from([(field_name.to_owned(), dimension)]))
+                .build()
+                .unwrap();
+            assert!(DocumentParser::new("test_index", &index_config, &fields).is_err());
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::index_holder::delete_matching_documents;
+use super::IndexUpdater;
+use crate::configs::ExpirationConfig;
+use crate::errors::{Error, SummaResult, ValidationError};
+use crate::utils::sync::Handler;
+use crate::utils::thread_handler::ThreadHandler;
+use parking_lot::RwLock;
+use std::ops::Bound;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tantivy::query::RangeQuery;
+use tantivy::schema::{Field, FieldType, Schema as Fields, Type};
+use tantivy::{DateTime, IndexReader, Searcher, Term};
+use tokio::time;
+use tracing::{info, info_span, warn, Instrument};
+
+/// Checks that documents of the index may be expired according to `expiration`
+///
+/// Expired documents are deleted by values of the primary key, so the primary key must be stored.
+/// `date_field` must be indexed date field.
+pub(crate) fn validate_expiration(fields: &Fields, primary_key: &Option<String>, expiration: &ExpirationConfig) -> SummaResult<()> {
+    let primary_key = primary_key
+        .as_ref()
+        .and_then(|primary_key| fields.get_field(primary_key))
+        .ok_or(ValidationError::MissingPrimaryKey(None))?;
+    if !fields.get_field_entry(primary_key).is_stored() {
+        return Err(ValidationError::NotStoredField(fields.get_field_name(primary_key).to_owned()).into());
+    }
+    let date_field = fields
+        .get_field(&expiration.date_field)
+        .ok_or_else(|| Error::FieldDoesNotExist(expiration.date_field.to_owned()))?;
+    let date_field_entry = fields.get_field_entry(date_field);
+    match date_field_entry.field_type() {
+        FieldType::Date(_) if date_field_entry.is_indexed() => (),
+        field_type => return Err(Error::InvalidFieldType(expiration.date_field.to_owned(), field_type.to_owned())),
+    }
+    if expiration.interval_ms == 0 {
+        return Err(ValidationError::EmptyArgument("interval_ms".to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Query matching documents expired at `now`
+pub(super) fn expired_documents_query(date_field: Field, ttl_secs: Option<u64>, now: SystemTime) -> RangeQuery {
+    let now = now.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default() as i64;
+    let expiration_bound = now.saturating_sub(ttl_secs.unwrap_or_default().try_into().unwrap_or(i64::MAX));
+    RangeQuery::new_term_bounds(
+        date_field,
+        Type::Date,
+        &Bound::Unbounded,
+        &Bound::Included(Term::from_field_date(date_field, DateTime::from_unix_timestamp(expiration_bound))),
+    )
+}
+
+/// Deletes committed documents expired at `now` and returns their number
+///
+/// Documents whose primary keys have been written since the last commit are kept, see `delete_matching_documents`,
+/// so documents reindexed after their expiration are not deleted along with their expired committed versions
+pub(super) fn delete_expired_documents(
+    searcher: &Searcher,
+    index_updater: &IndexUpdater,
+    date_field: Field,
+    primary_key: Field,
+    ttl_secs: Option<u64>,
+    now: SystemTime,
+) -> SummaResult<u64> {
+    let query = expired_documents_query(date_field, ttl_secs, now);
+    delete_matching_documents(searcher, &query, index_updater, primary_key)
+}
+
+/// Spawns the thread periodically deleting expired documents and committing the index
+///
+/// Like the autocommit thread, it skips the commit if `IndexUpdater` is locked and holds the acquired lock during the commit.
+/// Failed passes are logged and retried on the next tick
+#[allow(clippy::await_holding_lock)]
+pub(super) fn spawn_expiration_thread(
+    index_name: &str,
+    index_reader: IndexReader,
+    index_updater: Handler<RwLock<IndexUpdater>>,
+    expiration: &ExpirationConfig,
+) -> SummaResult<ThreadHandler> {
+    let fields = index_reader.searcher().schema().clone();
+    let date_field = fields
+        .get_field(&expiration.date_field)
+        .ok_or_else(|| Error::FieldDoesNotExist(expiration.date_field.to_owned()))?;
+    let primary_key = index_updater.read().primary_key().ok_or(ValidationError::MissingPrimaryKey(None))?;
+    let ttl_secs = expiration.ttl_secs;
+    let interval_ms = expiration.interval_ms;
+    let (shutdown_trigger, mut shutdown_tripwire) = async_broadcast::broadcast(1);
+    let mut tick_task = time::interval(Duration::from_millis(interval_ms));
+    Ok(ThreadHandler::new(
+        tokio::spawn(
+            async move {
+                info!(action = "spawning_expiration_thread", interval_ms = interval_ms);
+                loop {
+                    tokio::select! {
+                        _ = tick_task.tick() => {
+                            info!(action = "expiration_thread_tick");
+                            let searcher = index_reader.searcher();
+                            let deletion_index_updater = index_updater.clone();
+                            let deleted_documents = tokio::task::spawn_blocking(move || {
+                                delete_expired_documents(&searcher, &deletion_index_updater.read(), date_field, primary_key, ttl_secs, SystemTime::now())
+                            }).await.map_err(Error::from).and_then(|deleted_documents| deleted_documents);
+                            match deleted_documents {
+                                Ok(0) => (),
+                                Ok(deleted_documents) => {
+                                    info!(action = "deleted_expired_documents", deleted_documents = deleted_documents);
+                                    if let Some(mut index_updater) = index_updater.try_write() {
+                                        if let Err(error) = index_updater.commit().await {
+                                            warn!(error = ?error);
+                                        }
+                                    }
+                                }
+                                Err(error) => warn!(error = ?error),
+                            }
+                        }
+                        _ = &mut shutdown_tripwire.recv() => {
+                            info!(action = "shutdown_expiration_thread");
+                            break;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            .instrument(info_span!(parent: None, "expiration_thread", index_name = ?index_name)),
+        ),
+        shutdown_trigger,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::collector::Count;
+    use tantivy::schema::{INDEXED, STORED};
+    use tantivy::{doc, Index};
+
+    #[test]
+    fn test_expired_documents_query() {
+        let mut schema_builder = Fields::builder();
+        let issued_at = schema_builder.add_date_field("issued_at", INDEXED | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer(3_000_000).unwrap();
+        for timestamp in [100, 200, 300] {
+            index_writer.add_document(doc!(issued_at => DateTime::from_unix_timestamp(timestamp))).unwrap();
+        }
+        index_writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let expired_documents = |ttl_secs: Option<u64>, now: u64| {
+            let query = expired_documents_query(issued_at, ttl_secs, UNIX_EPOCH + Duration::from_secs(now));
+            searcher.search(&query, &Count).unwrap()
+        };
+        assert_eq!(expired_documents(None, 50), 0);
+        assert_eq!(expired_documents(None, 200), 2);
+        assert_eq!(expired_documents(Some(100), 250), 1);
+        assert_eq!(expired_documents(Some(1000), 1000), 0);
+        assert_eq!(expired_documents(Some(u64::MAX), 1000), 0);
+    }
+}
@@ -1,17 +1,29 @@
 use crate::errors::ValidationError::InvalidAggregation;
-use crate::errors::{Error, SummaResult};
+use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
+use crate::search_engine::collectors::SignificanceHeuristic;
 use crate::search_engine::custom_serializer::NamedFieldDocument;
-use crate::search_engine::scorers::EvalScorer;
-use std::collections::{HashMap, HashSet};
+use crate::search_engine::diversification::{Diversifier, DocumentSimilarity};
+use crate::search_engine::query_parser::QueryParser;
+use crate::search_engine::rank_fusion::RankFusion;
+use crate::search_engine::scorers::{DocumentEvalScorer, EvalScorer, ModelScorer, RandomScorer, RankingModels, SafeIntoF64};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
 use tantivy::aggregation::agg_result::AggregationResults;
-use tantivy::collector::{FacetCounts, FruitHandle, MultiCollector, MultiFruit};
-use tantivy::schema::{Field, Schema as Fields};
-use tantivy::{DocAddress, DocId, LeasedItem, Score, Searcher, SegmentReader};
+use tantivy::collector::{FruitHandle, MultiCollector, MultiFruit};
+use tantivy::query::Query;
+use tantivy::schema::{Cardinality, Field, FieldType, Schema as Fields};
+use tantivy::{DocAddress, DocId, Document, Index, LeasedItem, Score, Searcher, SegmentReader, Term};
 
 /// Extracts data from `MultiFruit` and moving it to the `proto::CollectorOutput`
 pub trait FruitExtractor: Sync + Send {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, searcher: &LeasedItem<Searcher>, multi_fields: &HashSet<Field>) -> proto::CollectorOutput;
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        searcher: &LeasedItem<Searcher>,
+        multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput>;
 }
 
 pub fn parse_aggregations(aggregations: HashMap<String, proto::Aggregation>) -> SummaResult<HashMap<String, tantivy::aggregation::agg_req::Aggregation>> {
@@ -37,154 +49,634 @@ fn parse_aggregation_results(
         .collect()
 }
 
-pub fn build_fruit_extractor(collector_proto: proto::Collector, fields: &Fields, multi_collector: &mut MultiCollector) -> SummaResult<Box<dyn FruitExtractor>> {
-    match collector_proto.collector {
-        Some(proto::collector::Collector::TopDocs(top_docs_collector_proto)) => Ok(match top_docs_collector_proto.scorer {
-            None | Some(proto::Scorer { scorer: None }) => Box::new(TopDocs::new(
-                multi_collector.add_collector(
-                    tantivy::collector::TopDocs::with_limit(top_docs_collector_proto.limit.try_into().unwrap())
-                        .and_offset(top_docs_collector_proto.offset.try_into().unwrap()),
-                ),
-                top_docs_collector_proto.limit.try_into().unwrap(),
-            )) as Box<dyn FruitExtractor>,
-            Some(proto::Scorer {
-                scorer: Some(proto::scorer::Scorer::EvalExpr(ref eval_expr)),
-            }) => {
-                let eval_scorer_seed = EvalScorer::new(eval_expr, fields)?;
-                let top_docs_collector = tantivy::collector::TopDocs::with_limit(top_docs_collector_proto.limit.try_into().unwrap())
-                    .and_offset(top_docs_collector_proto.offset.try_into().unwrap())
-                    .tweak_score(move |segment_reader: &SegmentReader| {
-                        let mut eval_scorer = eval_scorer_seed.get_for_segment_reader(segment_reader).unwrap();
-                        move |doc_id: DocId, original_score: Score| eval_scorer.score(doc_id, original_score)
-                    });
-                Box::new(TopDocs::new(
-                    multi_collector.add_collector(top_docs_collector),
-                    top_docs_collector_proto.limit.try_into().unwrap(),
-                )) as Box<dyn FruitExtractor>
+fn build_diversifier(diversification_proto: proto::Diversification, index: &Index, fields: &Fields) -> SummaResult<Diversifier> {
+    let document_similarity = match diversification_proto.similarity {
+        Some(proto::diversification::Similarity::TermOverlapField(field_name)) => {
+            let field = fields.get_field(&field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.clone()))?;
+            let field_entry = fields.get_field_entry(field);
+            match field_entry.field_type() {
+                FieldType::Str(_) if field_entry.is_stored() => (),
+                FieldType::Str(_) => return Err(ValidationError::NotStoredField(field_name).into()),
+                field_type => return Err(Error::InvalidFieldType(field_name, field_type.to_owned())),
+            }
+            DocumentSimilarity::TermOverlap {
+                field,
+                text_analyzer: index.tokenizer_for_field(field)?,
             }
-            Some(proto::Scorer {
-                scorer: Some(proto::scorer::Scorer::OrderBy(ref field_name)),
-            }) => {
-                let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
-                let top_docs_collector = tantivy::collector::TopDocs::with_limit(top_docs_collector_proto.limit.try_into().unwrap())
-                    .and_offset(top_docs_collector_proto.offset.try_into().unwrap())
-                    .order_by_u64_field(field);
-                Box::new(TopDocs::new(
-                    multi_collector.add_collector(top_docs_collector),
-                    top_docs_collector_proto.limit.try_into().unwrap(),
-                )) as Box<dyn FruitExtractor>
+        }
+        Some(proto::diversification::Similarity::FastField(field_name)) => {
+            let field = fields.get_field(&field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.clone()))?;
+            match fields.get_field_entry(field).field_type() {
+                FieldType::U64(options) | FieldType::I64(options) | FieldType::F64(options) | FieldType::Date(options)
+                    if options.get_fastfield_cardinality() == Some(Cardinality::SingleValue) => {}
+                field_type => {
+                    return Err(ValidationError::InvalidFastFieldType {
+                        field: field_name,
+                        field_type: field_type.to_owned(),
+                    }
+                    .into())
+                }
             }
-        }),
+            DocumentSimilarity::FastFieldEquality { field_name }
+        }
+        None => return Err(ValidationError::EmptyArgument("similarity".to_owned()).into()),
+    };
+    let lambda = diversification_proto.lambda.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&lambda) {
+        return Err(ValidationError::InvalidLambda(lambda).into());
+    }
+    Ok(Diversifier::new(diversification_proto.window_size as usize, lambda.into(), document_similarity))
+}
+
+pub fn build_fruit_extractor(
+    collector_proto: proto::Collector,
+    index: &Index,
+    fields: &Fields,
+    query: &dyn Query,
+    query_parser: &QueryParser,
+    ranking_models: &RankingModels,
+    multi_collector: &mut MultiCollector,
+) -> SummaResult<Box<dyn FruitExtractor>> {
+    match collector_proto.collector {
+        Some(proto::collector::Collector::TopDocs(top_docs_collector_proto)) => {
+            let limit: usize = top_docs_collector_proto.limit.try_into().unwrap();
+            let offset: usize = top_docs_collector_proto.offset.try_into().unwrap();
+            let rescorer = match (top_docs_collector_proto.rescorer, &top_docs_collector_proto.scorer) {
+                (
+                    Some(_),
+                    Some(proto::Scorer {
+                        scorer: Some(proto::scorer::Scorer::Model(_)),
+                        ..
+                    }),
+                ) => return Err(Error::InvalidSyntax("`rescorer` cannot be combined with `model` scorer".to_owned())),
+                (Some(rescorer_proto), _) => Some(Rescorer {
+                    window_size: rescorer_proto.window_size.try_into().unwrap(),
+                    second_pass_scorer: SecondPassScorer::EvalExpr(Box::new(DocumentEvalScorer::new(&rescorer_proto.eval_expr, fields)?)),
+                }),
+                (
+                    None,
+                    Some(proto::Scorer {
+                        scorer: Some(proto::scorer::Scorer::Model(model_scorer_proto)),
+                        params,
+                    }),
+                ) => Some(Rescorer {
+                    window_size: model_scorer_proto.window_size.try_into().unwrap(),
+                    second_pass_scorer: SecondPassScorer::Model(ModelScorer::new(ranking_models.get(&model_scorer_proto.model_name)?, fields, query, params)?),
+                }),
+                (None, _) => None,
+            };
+            let diversifier = top_docs_collector_proto
+                .diversification
+                .map(|diversification_proto| build_diversifier(diversification_proto, index, fields))
+                .transpose()?;
+            // Offset is applied after rescoring and diversification so the first pass has to collect whole windows
+            let (first_pass_limit, first_pass_offset, offset) = if rescorer.is_some() || diversifier.is_some() {
+                let window_size = rescorer
+                    .as_ref()
+                    .map(|rescorer| rescorer.window_size)
+                    .max(diversifier.as_ref().map(|diversifier| diversifier.window_size()))
+                    .unwrap_or_default();
+                (window_size.max(offset + limit + 1), 0, offset)
+            } else {
+                (limit, offset, 0)
+            };
+            Ok(match top_docs_collector_proto.scorer {
+                // Documents are rescored by the model after the first pass with BM25
+                None
+                | Some(proto::Scorer {
+                    scorer: None | Some(proto::scorer::Scorer::Model(_)),
+                    ..
+                }) => Box::new(TopDocs::new(
+                    multi_collector.add_collector(tantivy::collector::TopDocs::with_limit(first_pass_limit).and_offset(first_pass_offset)),
+                    limit,
+                    offset,
+                    rescorer,
+                    diversifier,
+                )) as Box<dyn FruitExtractor>,
+                Some(proto::Scorer {
+                    scorer: Some(proto::scorer::Scorer::EvalExpr(ref eval_expr)),
+                    params,
+                }) => {
+                    let eval_scorer_seed = EvalScorer::with_params(eval_expr, fields, params)?;
+                    let top_docs_collector =
+                        crate::search_engine::collectors::TweakedTopDocs::new(first_pass_limit, first_pass_offset, move |segment_reader: &SegmentReader| {
+                            let mut eval_scorer = eval_scorer_seed.get_for_segment_reader(segment_reader)?;
+                            Ok(move |doc_id: DocId, original_score: Score| eval_scorer.score(doc_id, original_score))
+                        });
+                    Box::new(TopDocs::new(
+                        multi_collector.add_collector(top_docs_collector),
+                        limit,
+                        offset,
+                        rescorer,
+                        diversifier,
+                    )) as Box<dyn FruitExtractor>
+                }
+                Some(proto::Scorer {
+                    scorer: Some(proto::scorer::Scorer::Random(ref random_scorer_proto)),
+                    ..
+                }) => {
+                    let random_scorer = RandomScorer::new(random_scorer_proto.seed, &random_scorer_proto.field, fields)?;
+                    let top_docs_collector =
+                        crate::search_engine::collectors::TweakedTopDocs::new(first_pass_limit, first_pass_offset, move |segment_reader: &SegmentReader| {
+                            let mut segment_random_scorer = random_scorer.get_for_segment_reader(segment_reader)?;
+                            Ok(move |doc_id: DocId, _original_score: Score| segment_random_scorer.score(doc_id))
+                        });
+                    Box::new(TopDocs::new(
+                        multi_collector.add_collector(top_docs_collector),
+                        limit,
+                        offset,
+                        rescorer,
+                        diversifier,
+                    )) as Box<dyn FruitExtractor>
+                }
+                Some(proto::Scorer {
+                    scorer: Some(proto::scorer::Scorer::OrderBy(ref field_name)),
+                    ..
+                }) => {
+                    let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+                    let top_docs_collector = tantivy::collector::TopDocs::with_limit(first_pass_limit)
+                        .and_offset(first_pass_offset)
+                        .order_by_u64_field(field);
+                    Box::new(TopDocs::new(
+                        multi_collector.add_collector(top_docs_collector),
+                        limit,
+                        offset,
+                        rescorer,
+                        diversifier,
+                    )) as Box<dyn FruitExtractor>
+                }
+            })
+        }
         Some(proto::collector::Collector::ReservoirSampling(reservoir_sampling_collector_proto)) => {
-            let reservoir_sampling_collector: crate::search_engine::collectors::ReservoirSampling = reservoir_sampling_collector_proto.into();
-            Ok(Box::new(ReservoirSampling(multi_collector.add_collector(reservoir_sampling_collector))) as Box<dyn FruitExtractor>)
+            let handle = match reservoir_sampling_collector_proto.weight_field {
+                Some(ref weight_field) => {
+                    let weighted_reservoir_sampling = crate::search_engine::collectors::WeightedReservoirSampling::new(
+                        reservoir_sampling_collector_proto.limit.try_into().unwrap(),
+                        EvalScorer::new(weight_field, fields)?,
+                    );
+                    multi_collector.add_collector(match reservoir_sampling_collector_proto.seed {
+                        Some(seed) => weighted_reservoir_sampling.with_seed(seed),
+                        None => weighted_reservoir_sampling,
+                    })
+                }
+                None => {
+                    let reservoir_sampling_collector: crate::search_engine::collectors::ReservoirSampling = reservoir_sampling_collector_proto.into();
+                    multi_collector.add_collector(reservoir_sampling_collector)
+                }
+            };
+            Ok(Box::new(ReservoirSampling(handle)) as Box<dyn FruitExtractor>)
         }
         Some(proto::collector::Collector::Count(_)) => Ok(Box::new(Count(multi_collector.add_collector(tantivy::collector::Count))) as Box<dyn FruitExtractor>),
         Some(proto::collector::Collector::Facet(facet_collector_proto)) => {
             let field = fields
                 .get_field(&facet_collector_proto.field)
                 .ok_or_else(|| Error::FieldDoesNotExist(facet_collector_proto.field.to_owned()))?;
-            let mut facet_collector = tantivy::collector::FacetCollector::for_field(field);
-            for facet in &facet_collector_proto.facets {
-                facet_collector.add_facet(facet);
+            let mut facets = facet_collector_proto
+                .facets
+                .iter()
+                .map(|facet| tantivy::schema::Facet::from_text(facet).map_err(|_| Error::InvalidSyntax(format!("cannot parse {} as facet", facet))))
+                .collect::<SummaResult<Vec<_>>>()?;
+            if facets.is_empty() {
+                facets.push(tantivy::schema::Facet::root());
             }
-            Ok(Box::new(Facet(multi_collector.add_collector(facet_collector))) as Box<dyn FruitExtractor>)
+            let depth = facet_collector_proto.depth.unwrap_or(1).try_into().unwrap();
+            let facet_collector = crate::search_engine::collectors::FacetTree::for_field(field, facets.clone(), depth);
+            Ok(Box::new(Facet {
+                handle: multi_collector.add_collector(facet_collector),
+                facets,
+                top_k: facet_collector_proto.top_k.map(|top_k| top_k.try_into().unwrap()),
+                depth,
+            }) as Box<dyn FruitExtractor>)
         }
         Some(proto::collector::Collector::Aggregation(aggregation_collector_proto)) => {
             let aggregation_collector = tantivy::aggregation::AggregationCollector::from_aggs(parse_aggregations(aggregation_collector_proto.aggregations)?);
             Ok(Box::new(Aggregation(multi_collector.add_collector(aggregation_collector))) as Box<dyn FruitExtractor>)
         }
+        Some(proto::collector::Collector::SignificantTerms(significant_terms_collector_proto)) => {
+            let field = fields
+                .get_field(&significant_terms_collector_proto.field)
+                .ok_or_else(|| Error::FieldDoesNotExist(significant_terms_collector_proto.field.to_owned()))?;
+            let field_entry = fields.get_field_entry(field);
+            match field_entry.field_type() {
+                FieldType::Str(_) if field_entry.is_stored() => (),
+                FieldType::Str(_) => return Err(ValidationError::NotStoredField(significant_terms_collector_proto.field.to_owned()).into()),
+                field_type => {
+                    return Err(Error::InvalidFieldType(
+                        significant_terms_collector_proto.field.to_owned(),
+                        field_type.to_owned(),
+                    ))
+                }
+            }
+            let significant_terms_collector = crate::search_engine::collectors::SignificantTerms::for_field(
+                field,
+                index.tokenizer_for_field(field)?,
+                significant_terms_collector_proto.segment_size.unwrap_or(1000).try_into().unwrap(),
+            );
+            Ok(Box::new(SignificantTerms {
+                handle: multi_collector.add_collector(significant_terms_collector),
+                field,
+                heuristic: proto::SignificanceHeuristic::from_i32(significant_terms_collector_proto.heuristic)
+                    .unwrap_or(proto::SignificanceHeuristic::Jlh)
+                    .into(),
+                size: significant_terms_collector_proto.size.unwrap_or(10).try_into().unwrap(),
+                min_doc_count: significant_terms_collector_proto.min_doc_count.unwrap_or(3),
+            }) as Box<dyn FruitExtractor>)
+        }
+        Some(proto::collector::Collector::Hybrid(hybrid_collector_proto)) => {
+            // `window_size` is kept in `u32` as it becomes `k` of the vector query
+            let window_size = hybrid_collector_proto
+                .offset
+                .checked_add(hybrid_collector_proto.limit)
+                .ok_or_else(|| Error::InvalidSyntax("`offset + limit` of hybrid collector is too large".to_owned()))?
+                .max(hybrid_collector_proto.window_size);
+            let mut vector_query_proto = hybrid_collector_proto
+                .vector_query
+                .ok_or_else(|| ValidationError::EmptyArgument("vector_query".to_owned()))?;
+            if vector_query_proto.k == 0 {
+                vector_query_proto.k = window_size;
+            }
+            let vector_query = query_parser.parse_query(&proto::Query {
+                query: Some(proto::query::Query::Vector(Box::new(vector_query_proto))),
+            })?;
+            Ok(Box::new(Hybrid {
+                handle: multi_collector.add_collector(tantivy::collector::TopDocs::with_limit(window_size as usize)),
+                vector_query,
+                window_size: window_size as usize,
+                limit: hybrid_collector_proto.limit as usize,
+                offset: hybrid_collector_proto.offset as usize,
+                rank_fusion: hybrid_collector_proto
+                    .fusion
+                    .map(RankFusion::try_from)
+                    .transpose()?
+                    .unwrap_or(RankFusion::Reciprocal { k: 60.0 }),
+            }) as Box<dyn FruitExtractor>)
+        }
         None => Ok(Box::new(Count(multi_collector.add_collector(tantivy::collector::Count))) as Box<dyn FruitExtractor>),
     }
 }
 
-pub struct TopDocs<T: 'static + Copy + Into<proto::Score> + Sync + Send> {
+pub enum SecondPassScorer {
+    EvalExpr(Box<DocumentEvalScorer>),
+    Model(ModelScorer),
+}
+
+/// Second pass of `TopDocs` rescoring `window_size` top documents of the first pass with `DocumentEvalScorer` or `ModelScorer`
+pub struct Rescorer {
+    window_size: usize,
+    second_pass_scorer: SecondPassScorer,
+}
+
+impl Rescorer {
+    /// Rescores top `window_size` documents, documents outside of the window keep their first pass order and are placed after the rescored ones
+    fn rescore<T: SafeIntoF64 + Copy>(&self, searcher: &Searcher, fruit: &[(T, DocAddress)]) -> SummaResult<Vec<(f64, Option<Document>, DocAddress)>> {
+        let window_size = std::cmp::min(self.window_size, fruit.len());
+        let mut rescored_documents: Vec<_> = match self.second_pass_scorer {
+            SecondPassScorer::EvalExpr(ref document_eval_scorer) => fruit[..window_size]
+                .iter()
+                .map(|(score, doc_address)| {
+                    let document = searcher.doc(*doc_address)?;
+                    Ok((document_eval_scorer.score(&document, score.safe_into_f64())?, Some(document), *doc_address))
+                })
+                .collect::<SummaResult<_>>()?,
+            SecondPassScorer::Model(ref model_scorer) => {
+                let first_pass_documents: Vec<_> = fruit[..window_size]
+                    .iter()
+                    .map(|(score, doc_address)| (score.safe_into_f64(), *doc_address))
+                    .collect();
+                model_scorer
+                    .score(searcher, &first_pass_documents)?
+                    .into_iter()
+                    .zip(first_pass_documents)
+                    .map(|(score, (_, doc_address))| (score, None, doc_address))
+                    .collect()
+            }
+        };
+        rescored_documents.sort_by(|left, right| right.0.partial_cmp(&left.0).unwrap_or(Ordering::Equal));
+        rescored_documents.extend(
+            fruit[window_size..]
+                .iter()
+                .map(|(score, doc_address)| (score.safe_into_f64(), None, *doc_address)),
+        );
+        Ok(rescored_documents)
+    }
+}
+
+pub struct TopDocs<T: 'static + Copy + Into<proto::Score> + SafeIntoF64 + Sync + Send> {
     handle: FruitHandle<Vec<(T, DocAddress)>>,
     limit: usize,
+    offset: usize,
+    rescorer: Option<Rescorer>,
+    diversifier: Option<Diversifier>,
 }
 
-impl<T: 'static + Copy + Into<proto::Score> + Sync + Send> TopDocs<T> {
-    pub fn new(handle: FruitHandle<Vec<(T, DocAddress)>>, limit: usize) -> TopDocs<T> {
-        TopDocs { handle, limit }
+impl<T: 'static + Copy + Into<proto::Score> + SafeIntoF64 + Sync + Send> TopDocs<T> {
+    /// `offset` is applied after rescoring and diversification, so it should be zero if the first pass has already applied it
+    pub fn new(
+        handle: FruitHandle<Vec<(T, DocAddress)>>,
+        limit: usize,
+        offset: usize,
+        rescorer: Option<Rescorer>,
+        diversifier: Option<Diversifier>,
+    ) -> TopDocs<T> {
+        TopDocs {
+            handle,
+            limit,
+            offset,
+            rescorer,
+            diversifier,
+        }
     }
 }
 
-impl<T: 'static + Copy + Into<proto::Score> + Sync + Send> FruitExtractor for TopDocs<T> {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, searcher: &LeasedItem<Searcher>, multi_fields: &HashSet<Field>) -> proto::CollectorOutput {
+impl<T: 'static + Copy + Into<proto::Score> + SafeIntoF64 + Sync + Send> FruitExtractor for TopDocs<T> {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        searcher: &LeasedItem<Searcher>,
+        multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
         let fields = searcher.schema();
         let fruit = self.handle.extract(multi_fruit);
-        let scored_documents_iter = fruit.iter().enumerate().map(|(position, (score, doc_address))| {
-            let document = searcher.doc(*doc_address).unwrap();
-            proto::ScoredDocument {
-                document: NamedFieldDocument::from_document(fields, multi_fields, &document).to_json(),
-                score: Some((*score).into()),
-                position: position.try_into().unwrap(),
+        let (scored_documents, has_next) = if self.rescorer.is_none() && self.diversifier.is_none() {
+            let scored_documents = fruit
+                .iter()
+                .take(self.limit)
+                .enumerate()
+                .map(|(position, (score, doc_address))| {
+                    let document = searcher.doc(*doc_address)?;
+                    Ok(proto::ScoredDocument {
+                        document: NamedFieldDocument::from_document(fields, multi_fields, &document).to_json(),
+                        score: Some((*score).into()),
+                        position: position.try_into().unwrap(),
+                    })
+                })
+                .collect::<SummaResult<_>>()?;
+            (scored_documents, fruit.len() > self.limit)
+        } else {
+            let mut ranked_documents = match self.rescorer {
+                Some(ref rescorer) => rescorer.rescore(searcher, &fruit)?,
+                None => fruit.iter().map(|(score, doc_address)| (score.safe_into_f64(), None, *doc_address)).collect(),
+            };
+            if let Some(ref diversifier) = self.diversifier {
+                diversifier.diversify(searcher, &mut ranked_documents)?;
             }
-        });
-        let len = scored_documents_iter.len();
-        let scored_documents = scored_documents_iter.take(std::cmp::min(self.limit, len)).collect();
-        let has_next = len > self.limit;
-        proto::CollectorOutput {
+            let has_next = ranked_documents.len() > self.offset + self.limit;
+            let scored_documents = ranked_documents
+                .into_iter()
+                .skip(self.offset)
+                .take(self.limit)
+                .enumerate()
+                .map(|(position, (score, document, doc_address))| {
+                    let document = match document {
+                        Some(document) => document,
+                        None => searcher.doc(doc_address)?,
+                    };
+                    Ok(proto::ScoredDocument {
+                        document: NamedFieldDocument::from_document(fields, multi_fields, &document).to_json(),
+                        score: Some(score.into()),
+                        position: position.try_into().unwrap(),
+                    })
+                })
+                .collect::<SummaResult<_>>()?;
+            (scored_documents, has_next)
+        };
+        Ok(proto::CollectorOutput {
             collector_output: Some(proto::collector_output::CollectorOutput::TopDocs(proto::TopDocsCollectorOutput {
                 scored_documents,
                 has_next,
             })),
-        }
+        })
+    }
+}
+
+/// Fuses top documents of the query collected with `handle` and top documents of `vector_query` executed at extraction
+pub struct Hybrid {
+    handle: FruitHandle<Vec<(Score, DocAddress)>>,
+    vector_query: Box<dyn Query>,
+    window_size: usize,
+    limit: usize,
+    offset: usize,
+    rank_fusion: RankFusion,
+}
+
+impl FruitExtractor for Hybrid {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        searcher: &LeasedItem<Searcher>,
+        multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
+        let fields = searcher.schema();
+        let lexical_documents = self.handle.extract(multi_fruit);
+        let vector_documents = searcher.search(&self.vector_query, &tantivy::collector::TopDocs::with_limit(self.window_size))?;
+        let to_f64 = |documents: Vec<(Score, DocAddress)>| -> Vec<(f64, DocAddress)> {
+            documents.into_iter().map(|(score, doc_address)| (score.safe_into_f64(), doc_address)).collect()
+        };
+        let fused_documents = self.rank_fusion.fuse(&[to_f64(lexical_documents), to_f64(vector_documents)]);
+        let scored_documents = fused_documents
+            .iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .enumerate()
+            .map(|(position, (score, doc_address))| {
+                let document = searcher.doc(*doc_address)?;
+                Ok(proto::ScoredDocument {
+                    document: NamedFieldDocument::from_document(fields, multi_fields, &document).to_json(),
+                    score: Some((*score).into()),
+                    // `position` is less than `limit` which is `u32`
+                    position: position as u32,
+                })
+            })
+            .collect::<SummaResult<_>>()?;
+        Ok(proto::CollectorOutput {
+            collector_output: Some(proto::collector_output::CollectorOutput::TopDocs(proto::TopDocsCollectorOutput {
+                scored_documents,
+                has_next: fused_documents.len() > self.offset + self.limit,
+            })),
+        })
     }
 }
 
 pub struct ReservoirSampling(pub FruitHandle<Vec<DocAddress>>);
 
 impl FruitExtractor for ReservoirSampling {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, searcher: &LeasedItem<Searcher>, multi_fields: &HashSet<Field>) -> proto::CollectorOutput {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        searcher: &LeasedItem<Searcher>,
+        multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
         let fields = searcher.schema();
-        proto::CollectorOutput {
+        let documents = self
+            .0
+            .extract(multi_fruit)
+            .iter()
+            .map(|doc_address| Ok(NamedFieldDocument::from_document(fields, multi_fields, &searcher.doc(*doc_address)?).to_json()))
+            .collect::<SummaResult<_>>()?;
+        Ok(proto::CollectorOutput {
             collector_output: Some(proto::collector_output::CollectorOutput::ReservoirSampling(
-                proto::ReservoirSamplingCollectorOutput {
-                    documents: self
-                        .0
-                        .extract(multi_fruit)
-                        .iter()
-                        .map(|doc_address| NamedFieldDocument::from_document(fields, multi_fields, &searcher.doc(*doc_address).unwrap()).to_json())
-                        .collect(),
-                },
+                proto::ReservoirSamplingCollectorOutput { documents },
             )),
-        }
+        })
     }
 }
 
 pub struct Count(pub FruitHandle<usize>);
 
 impl FruitExtractor for Count {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, _searcher: &LeasedItem<Searcher>, _multi_fields: &HashSet<Field>) -> proto::CollectorOutput {
-        proto::CollectorOutput {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        _searcher: &LeasedItem<Searcher>,
+        _multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
+        Ok(proto::CollectorOutput {
             collector_output: Some(proto::collector_output::CollectorOutput::Count(proto::CountCollectorOutput {
                 count: self.0.extract(multi_fruit) as u32,
             })),
-        }
+        })
     }
 }
 
-pub struct Facet(pub FruitHandle<FacetCounts>);
+pub struct Facet {
+    handle: FruitHandle<BTreeMap<tantivy::schema::Facet, u64>>,
+    facets: Vec<tantivy::schema::Facet>,
+    top_k: Option<usize>,
+    depth: usize,
+}
+
+/// Returns direct children of `facet` having non-zero counts
+fn facet_children<'a>(facet_counts: &'a BTreeMap<tantivy::schema::Facet, u64>, facet: &tantivy::schema::Facet) -> Vec<(&'a tantivy::schema::Facet, u64)> {
+    let right_bound = if facet.is_root() {
+        Bound::Unbounded
+    } else {
+        let mut facet_after = facet.encoded_str().as_bytes().to_vec();
+        facet_after.push(1);
+        Bound::Excluded(tantivy::schema::Facet::from_encoded(facet_after).unwrap())
+    };
+    let children_depth = if facet.is_root() { 1 } else { facet.to_path().len() + 1 };
+    facet_counts
+        .range((Bound::Excluded(facet.clone()), right_bound))
+        .filter(|(child, _)| child.to_path().len() == children_depth)
+        .map(|(child, count)| (child, *count))
+        .collect()
+}
+
+/// Builds a tree of facet counts rooted at `facet` keeping `top_k` most frequent children on every level
+fn build_facet_tree(
+    facet_counts: &BTreeMap<tantivy::schema::Facet, u64>,
+    facet: &tantivy::schema::Facet,
+    depth: usize,
+    top_k: Option<usize>,
+    flat_facet_counts: &mut HashMap<String, u64>,
+) -> proto::FacetTreeNode {
+    let count = facet_counts.get(facet).copied().unwrap_or(0);
+    flat_facet_counts.insert(facet.to_string(), count);
+    let children = if depth > 0 {
+        let mut children = facet_children(facet_counts, facet);
+        children.sort_by(|(left_facet, left_count), (right_facet, right_count)| right_count.cmp(left_count).then_with(|| left_facet.cmp(right_facet)));
+        if let Some(top_k) = top_k {
+            children.truncate(top_k);
+        }
+        children
+            .into_iter()
+            .map(|(child, _)| build_facet_tree(facet_counts, child, depth - 1, top_k, flat_facet_counts))
+            .collect()
+    } else {
+        vec![]
+    };
+    proto::FacetTreeNode {
+        facet: facet.to_string(),
+        count,
+        children,
+    }
+}
 
 impl FruitExtractor for Facet {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, _searcher: &LeasedItem<Searcher>, _multi_fields: &HashSet<Field>) -> proto::CollectorOutput {
-        proto::CollectorOutput {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        _searcher: &LeasedItem<Searcher>,
+        _multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
+        let facet_counts = self.handle.extract(multi_fruit);
+        let mut flat_facet_counts = HashMap::new();
+        let facet_trees = self
+            .facets
+            .iter()
+            .map(|facet| build_facet_tree(&facet_counts, facet, self.depth, self.top_k, &mut flat_facet_counts))
+            .collect();
+        Ok(proto::CollectorOutput {
             collector_output: Some(proto::collector_output::CollectorOutput::Facet(proto::FacetCollectorOutput {
-                facet_counts: self.0.extract(multi_fruit).get("").map(|(facet, count)| (facet.to_string(), count)).collect(),
+                facet_counts: flat_facet_counts,
+                facet_trees,
             })),
-        }
+        })
     }
 }
 
 pub struct Aggregation(pub FruitHandle<AggregationResults>);
 
 impl FruitExtractor for Aggregation {
-    fn extract(self: Box<Self>, multi_fruit: &mut MultiFruit, _searcher: &LeasedItem<Searcher>, _multi_fields: &HashSet<Field>) -> proto::CollectorOutput {
-        proto::CollectorOutput {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        _searcher: &LeasedItem<Searcher>,
+        _multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
+        Ok(proto::CollectorOutput {
             collector_output: Some(proto::collector_output::CollectorOutput::Aggregation(proto::AggregationCollectorOutput {
                 aggregation_results: parse_aggregation_results(self.0.extract(multi_fruit).0),
             })),
-        }
+        })
+    }
+}
+
+pub struct SignificantTerms {
+    handle: FruitHandle<(HashMap<String, u64>, u64)>,
+    field: Field,
+    heuristic: SignificanceHeuristic,
+    size: usize,
+    min_doc_count: u64,
+}
+
+impl FruitExtractor for SignificantTerms {
+    fn extract(
+        self: Box<Self>,
+        multi_fruit: &mut MultiFruit,
+        searcher: &LeasedItem<Searcher>,
+        _multi_fields: &HashSet<Field>,
+    ) -> SummaResult<proto::CollectorOutput> {
+        let (doc_counts, subset_size) = self.handle.extract(multi_fruit);
+        let superset_size = searcher.num_docs();
+        let mut buckets: Vec<_> = doc_counts
+            .into_iter()
+            .filter(|(_, doc_count)| *doc_count >= self.min_doc_count)
+            .filter_map(|(key, doc_count)| {
+                let bg_count = searcher.doc_freq(&Term::from_field_text(self.field, &key)).unwrap_or(0);
+                let score = self.heuristic.score(doc_count, subset_size, bg_count, superset_size);
+                if score > 0.0 {
+                    Some(proto::SignificantTermsBucket {
+                        key,
+                        doc_count,
+                        bg_count,
+                        score,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        buckets.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal).then_with(|| a.key.cmp(&b.key)));
+        buckets.truncate(self.size);
+        Ok(proto::CollectorOutput {
+            collector_output: Some(proto::collector_output::CollectorOutput::SignificantTerms(
+                proto::SignificantTermsCollectorOutput {
+                    buckets,
+                    doc_count: subset_size,
+                    bg_count: superset_size,
+                },
+            )),
+        })
     }
 }
@@ -1,35 +1,47 @@
 use super::default_tokenizers::default_tokenizers;
+use super::expiration::{spawn_expiration_thread, validate_expiration};
 use crate::configs::{ConsumerConfig, IndexConfig, IndexConfigProxy, IndexEngine};
 use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
 use crate::search_engine::fruit_extractors::{build_fruit_extractor, FruitExtractor};
 use crate::search_engine::query_parser::QueryParser;
+use crate::search_engine::scorers::RankingModels;
 use crate::search_engine::IndexUpdater;
 use crate::utils::sync::{Handler, OwningHandler};
 use crate::utils::thread_handler::ThreadHandler;
 use opentelemetry::metrics::{Unit, ValueRecorder};
 use opentelemetry::{global, KeyValue};
 use parking_lot::RwLock;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tantivy::collector::MultiCollector;
-use tantivy::schema::{Field, Schema as Fields};
-use tantivy::{Index, IndexReader, IndexSettings, Opstamp, ReloadPolicy};
+use tantivy::collector::{DocSetCollector, MultiCollector};
+use tantivy::directory::{Directory, ManagedDirectory, MmapDirectory, RamDirectory};
+use tantivy::query::Query;
+use tantivy::schema::{Field, FieldType, FieldValue, Schema as Fields, Value};
+use tantivy::{Document, Index, IndexReader, IndexSettings, Opstamp, ReloadPolicy, Searcher};
 use tokio::fs::remove_dir_all;
 use tokio::time;
 use tokio::time::Instant;
 use tracing::{info, info_span, instrument, warn, Instrument};
 
+/// Tantivy file keeping the schema and the list of segments
+const META_FILEPATH: &str = "meta.json";
+/// Tantivy file keeping the list of files of the index
+const MANAGED_FILEPATH: &str = ".managed.json";
+
 pub struct IndexHolder {
     index_name: String,
     index_config_proxy: IndexConfigProxy,
     cached_fields: Fields,
     index_reader: IndexReader,
     query_parser: QueryParser,
+    ranking_models: RankingModels,
     multi_fields: HashSet<Field>,
     /// All modifying operations are isolated inside `index_updater`
     index_updater: OwningHandler<RwLock<IndexUpdater>>,
     autocommit_thread: Option<ThreadHandler>,
+    expiration_thread: Option<ThreadHandler>,
     /// Counters
     search_times_meter: ValueRecorder<f64>,
 }
@@ -43,10 +55,96 @@ fn register_default_tokenizers(index: &Index, index_config: &IndexConfig) {
     }
 }
 
+/// Applies values of fields of `partial_document` to `document` according to `update_modes`
+fn merge_documents(document: Document, partial_document: Document, update_modes: &HashMap<Field, proto::UpdateMode>) -> Document {
+    let mut updated_fields: HashMap<Field, Vec<Value>> = HashMap::new();
+    for field_value in partial_document.field_values() {
+        updated_fields.entry(field_value.field()).or_default().push(field_value.value().clone());
+    }
+    let update_mode = |field: &Field| update_modes.get(field).copied().unwrap_or(proto::UpdateMode::Set);
+    let mut field_values: Vec<FieldValue> = document
+        .field_values()
+        .iter()
+        .filter(|field_value| match updated_fields.get(&field_value.field()) {
+            None => true,
+            Some(values) => match update_mode(&field_value.field()) {
+                proto::UpdateMode::Set => false,
+                proto::UpdateMode::Append => true,
+                proto::UpdateMode::Remove => !values.contains(field_value.value()),
+            },
+        })
+        .cloned()
+        .collect();
+    for (field, values) in updated_fields {
+        if update_mode(&field) != proto::UpdateMode::Remove {
+            field_values.extend(values.into_iter().map(|value| FieldValue::new(field, value)));
+        }
+    }
+    Document::from(field_values)
+}
+
+/// Deletes committed documents matching `query` by values of their stored `primary_key`
+///
+/// Documents without the stored primary key are skipped, as are documents whose primary key has been indexed, updated
+/// or deleted since the last commit, because deletion by the primary key would also delete their newer versions.
+/// Deletion becomes visible after commit. Returns the number of deleted documents
+pub(super) fn delete_matching_documents(searcher: &Searcher, query: &dyn Query, index_updater: &IndexUpdater, primary_key: Field) -> SummaResult<u64> {
+    let mut deleted_documents = 0;
+    for doc_address in searcher.search(query, &DocSetCollector)? {
+        if let Some(primary_key_value) = searcher.doc(doc_address)?.get_first(primary_key) {
+            if index_updater.delete_committed_document(primary_key_value)?.is_some() {
+                deleted_documents += 1;
+            }
+        }
+    }
+    Ok(deleted_documents)
+}
+
+/// Extends `fields` with `added_fields` that may be added to the existing index
+///
+/// Segments written before cannot be merged with fast fields, facets or fields with fieldnorms
+/// they lack, so such fields may be set only on the index creation. Text fields are added only with fieldnorms disabled
+pub(crate) fn extend_fields(fields: &Fields, added_fields: &Fields) -> SummaResult<Fields> {
+    let mut fields_builder = Fields::builder();
+    for (_, field_entry) in fields.fields() {
+        fields_builder.add_field(field_entry.clone());
+    }
+    for (_, field_entry) in added_fields.fields() {
+        if fields.get_field(field_entry.name()).is_some() {
+            return Err(ValidationError::InvalidFields(format!("field {} already exists", field_entry.name())).into());
+        }
+        if field_entry.is_fast() || field_entry.has_fieldnorms() || matches!(field_entry.field_type(), FieldType::Facet(_)) {
+            return Err(ValidationError::InvalidFields(format!(
+                "field {} cannot be added to the existing index as it is fast, facet or has fieldnorms, disable fieldnorms of text fields",
+                field_entry.name()
+            ))
+            .into());
+        }
+        fields_builder.add_field(field_entry.clone());
+    }
+    Ok(fields_builder.build())
+}
+
+/// Copies files of the in-memory index into a new `RamDirectory`
+///
+/// `Index` exposes only its `ManagedDirectory` that cannot be wrapped again for reopening the index
+fn copy_into_ram_directory(directory: &ManagedDirectory) -> SummaResult<RamDirectory> {
+    let ram_directory = RamDirectory::create();
+    let mut paths = directory.list_managed_files();
+    paths.insert(PathBuf::from(META_FILEPATH));
+    paths.insert(PathBuf::from(MANAGED_FILEPATH));
+    for path in paths {
+        if directory.exists(&path).map_err(tantivy::TantivyError::from)? {
+            ram_directory.atomic_write(&path, &directory.atomic_read(&path).map_err(tantivy::TantivyError::from)?)?;
+        }
+    }
+    Ok(ram_directory)
+}
+
 impl IndexHolder {
     /// Sets up `IndexHolder`
     ///
-    /// Creates the auto committing thread and consumers
+    /// Creates the auto committing thread, the thread deleting expired documents and consumers
     async fn setup(index_name: &str, index: Index, index_config_proxy: IndexConfigProxy) -> SummaResult<IndexHolder> {
         let index_config = index_config_proxy.read().get().clone();
         register_default_tokenizers(&index, &index_config);
@@ -56,7 +154,15 @@ impl IndexHolder {
             index_name,
             &index,
             index_config.default_fields.iter().map(|x| cached_fields.get_field(x).unwrap()).collect(),
+        )
+        .with_similarities(
+            index_config
+                .similarities
+                .iter()
+                .map(|(field_name, similarity)| (cached_fields.get_field(field_name).unwrap(), similarity.clone()))
+                .collect(),
         );
+        let ranking_models = RankingModels::new(&index_config_proxy.read().application_config().get_path_for_ranking_models());
         let index_reader = index.reader_builder().reload_policy(ReloadPolicy::OnCommit).try_into()?;
         let index_updater = OwningHandler::new(RwLock::new(IndexUpdater::new(index, index_name, index_config_proxy.clone())?));
 
@@ -97,6 +203,13 @@ impl IndexHolder {
             }
             None => None,
         };
+        let expiration_thread = match index_config.expiration {
+            Some(ref expiration) => {
+                validate_expiration(&cached_fields, &index_config.primary_key, expiration)?;
+                Some(spawn_expiration_thread(index_name, index_reader.clone(), index_updater.handler(), expiration)?)
+            }
+            None => None,
+        };
 
         let search_times_meter = global::meter("summa")
             .f64_value_recorder("search_times")
@@ -107,7 +220,9 @@ impl IndexHolder {
         Ok(IndexHolder {
             index_name: String::from(index_name),
             autocommit_thread,
+            expiration_thread,
             query_parser,
+            ranking_models,
             multi_fields: index_config.multi_fields.iter().map(|x| cached_fields.get_field(x).unwrap()).collect(),
             cached_fields,
             index_reader,
@@ -152,6 +267,75 @@ impl IndexHolder {
         IndexHolder::setup(index_name, index, index_config_proxy).await
     }
 
+    /// Replaces the schema of the index with `fields` produced by `extend_fields`
+    ///
+    /// `IndexHolder` is stopped, the schema is written into the index metadata and then the index is set up again, so
+    /// cached fields, `QueryParser` and `IndexUpdater` use the new schema. `IndexConfig` of in-memory index is updated
+    /// only after the altered index is set up.
+    ///
+    /// In-memory index is altered in the copy of its files, so its data takes twice as much memory until the original
+    /// index is released. The copy keeps the original index intact, and the original metadata of the file index is
+    /// written back, so if altering fails then the original index is set up again and returned along with the error.
+    #[instrument(skip_all, fields(index_name = %self.index_name))]
+    pub(crate) async fn alter_fields(self, fields: Fields) -> Result<IndexHolder, (Error, Option<IndexHolder>)> {
+        let index_name = self.index_name.clone();
+        let index_config_proxy = self.index_config_proxy.clone();
+        let index = self.index_updater.read().index().clone();
+        let prepared_metas = index.load_metas().map_err(Error::from).and_then(|mut index_meta| {
+            let original_meta = index.directory().atomic_read(Path::new(META_FILEPATH)).map_err(tantivy::TantivyError::from)?;
+            index_meta.schema = fields.clone();
+            Ok((original_meta, serde_json::to_vec(&index_meta).map_err(std::io::Error::from)?))
+        });
+        let (original_meta, altered_meta) = match prepared_metas {
+            Ok(prepared_metas) => prepared_metas,
+            Err(error) => return Err((error, Some(self))),
+        };
+
+        let altered_index_holder = match self.stop().await {
+            Ok(_) => IndexHolder::open_altered(&index_name, &index, &altered_meta, index_config_proxy.clone()).await,
+            Err(error) => Err(error),
+        };
+        match altered_index_holder {
+            Ok(altered_index_holder) => {
+                if let IndexEngine::Memory(ref mut memory_fields) = index_config_proxy.write().autosave().get_mut().index_engine {
+                    *memory_fields = fields;
+                }
+                info!(action = "altered_fields");
+                Ok(altered_index_holder)
+            }
+            Err(error) => {
+                warn!(action = "restore_index", error = ?error);
+                match IndexHolder::restore(&index_name, index, &original_meta, index_config_proxy).await {
+                    Ok(index_holder) => Err((error, Some(index_holder))),
+                    Err(restore_error) => {
+                        warn!(action = "failed_to_restore_index", error = ?restore_error);
+                        Err((error, None))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens the index with `altered_meta` written into the copy of in-memory index or into the directory of file index
+    async fn open_altered(index_name: &str, index: &Index, altered_meta: &[u8], index_config_proxy: IndexConfigProxy) -> SummaResult<IndexHolder> {
+        let directory: Box<dyn Directory> = match index_config_proxy.read().get().index_engine {
+            IndexEngine::Memory(_) => Box::new(copy_into_ram_directory(index.directory())?),
+            IndexEngine::File(ref index_path) => Box::new(MmapDirectory::open(index_path).map_err(tantivy::TantivyError::from)?),
+        };
+        directory.atomic_write(Path::new(META_FILEPATH), altered_meta)?;
+        IndexHolder::setup(index_name, Index::open(directory)?, index_config_proxy).await
+    }
+
+    /// Sets up the original `index` after failed altering, `original_meta` is written back into the directory of file index
+    async fn restore(index_name: &str, index: Index, original_meta: &[u8], index_config_proxy: IndexConfigProxy) -> SummaResult<IndexHolder> {
+        if let IndexEngine::File(ref index_path) = index_config_proxy.read().get().index_engine {
+            MmapDirectory::open(index_path)
+                .map_err(tantivy::TantivyError::from)?
+                .atomic_write(Path::new(META_FILEPATH), original_meta)?;
+        }
+        IndexHolder::setup(index_name, index, index_config_proxy).await
+    }
+
     /// Index name
     pub(crate) fn index_name(&self) -> &str {
         &self.index_name
@@ -186,24 +370,30 @@ impl IndexHolder {
 
     /// Stops `IndexHolder` instance
     ///
-    /// Both autocommitting thread and consumers are stopped
+    /// Autocommitting thread, expiration thread and consumers are stopped
     #[instrument(skip(self), fields(index_name = %self.index_name))]
     pub(crate) async fn stop(self) -> SummaResult<Opstamp> {
         if let Some(autocommit_thread) = self.autocommit_thread {
             autocommit_thread.stop().await?;
         }
+        if let Some(expiration_thread) = self.expiration_thread {
+            expiration_thread.stop().await?;
+        }
         self.index_updater.into_inner().into_inner().stop_consumers_and_commit().await
     }
 
     /// Delete `IndexHolder` instance
     ///
-    /// Both autocommitting thread and consumers are stopped, then `IndexConfig` is removed from `ApplicationConfig`
+    /// Autocommitting thread, expiration thread and consumers are stopped, then `IndexConfig` is removed from `ApplicationConfig`
     /// and then directory with the index is deleted.
     #[instrument(skip(self), fields(index_name = %self.index_name))]
     pub(crate) async fn delete(self) -> SummaResult<()> {
         if let Some(autocommit_thread) = self.autocommit_thread {
             autocommit_thread.stop().await?;
         };
+        if let Some(expiration_thread) = self.expiration_thread {
+            expiration_thread.stop().await?;
+        };
         self.index_updater.into_inner().into_inner().stop().await?;
         match self.index_config_proxy.delete().index_engine {
             IndexEngine::Memory(_) => (),
@@ -215,6 +405,78 @@ impl IndexHolder {
         Ok(())
     }
 
+    /// Partially updates the document with the primary key
+    ///
+    /// Fields of `partial_document` are set, appended to or removed from values of the current version of the document
+    /// according to `update_modes`. The updated document is reindexed, so every field of the index must be stored.
+    /// `partial_document` is parsed by `DocumentParser::parse_partial` of the index.
+    /// Returns `None` if the update is skipped due to its outdated version
+    pub(crate) async fn update_document(
+        &self,
+        primary_key_value: Value,
+        partial_document: Vec<u8>,
+        update_modes: &HashMap<String, proto::UpdateMode>,
+    ) -> SummaResult<Option<Opstamp>> {
+        if let Some((_, field_entry)) = self.cached_fields.fields().find(|(_, field_entry)| !field_entry.is_stored()) {
+            return Err(ValidationError::NotStoredField(field_entry.name().to_owned()).into());
+        }
+        let update_modes = update_modes
+            .iter()
+            .map(|(field_name, update_mode)| {
+                let field = self
+                    .cached_fields
+                    .get_field(field_name)
+                    .ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+                if *update_mode != proto::UpdateMode::Set && !self.multi_fields.contains(&field) {
+                    return Err(ValidationError::MissingMultiField(field_name.to_owned()).into());
+                }
+                Ok((field, *update_mode))
+            })
+            .collect::<SummaResult<HashMap<_, _>>>()?;
+        let index_updater = self.index_updater();
+        let index_reader = self.index_reader.clone();
+        tokio::task::spawn_blocking(move || -> SummaResult<Option<Opstamp>> {
+            // Holding `index_updater` prevents commits until the update is done, so the reloaded reader sees the last commit
+            let index_updater = index_updater.read();
+            let partial_document = index_updater.parse_partial_document(&partial_document)?;
+            index_reader.reload()?;
+            index_updater.update_document(&index_reader.searcher(), &primary_key_value, |document| {
+                Ok(merge_documents(document, partial_document, &update_modes))
+            })
+        })
+        .await?
+    }
+
+    /// Deletes committed documents matching `query`
+    ///
+    /// Documents are deleted by values of the stored primary key, so the index must have one.
+    /// See `delete_matching_documents` for skipped documents. `IndexUpdater` is locked during the deletion and the reader is
+    /// reloaded under the lock, so no documents may be committed between the search and the deletion.
+    /// Deletion becomes visible after commit. Returns the number of deleted documents
+    pub(crate) async fn delete_by_query(&self, query: &proto::Query) -> SummaResult<u64> {
+        let primary_key = self.index_updater.read().primary_key().ok_or(ValidationError::MissingPrimaryKey(None))?;
+        let primary_key_entry = self.cached_fields.get_field_entry(primary_key);
+        if !primary_key_entry.is_stored() {
+            return Err(ValidationError::NotStoredField(primary_key_entry.name().to_owned()).into());
+        }
+        let index_reader = self.index_reader.clone();
+        let parsed_query = self.query_parser.parse_query(query)?;
+        let index_updater = self.index_updater();
+        tokio::task::spawn_blocking(move || -> SummaResult<u64> {
+            let index_updater = index_updater.write();
+            index_reader.reload()?;
+            let deleted_documents = delete_matching_documents(&index_reader.searcher(), &parsed_query, &index_updater, primary_key)?;
+            info!(action = "deleted_by_query", deleted_documents = deleted_documents);
+            Ok(deleted_documents)
+        })
+        .await?
+    }
+
+    /// Casts `query` into `tantivy::query::Query` by `QueryParser` of the index
+    pub(crate) fn parse_query(&self, query: &proto::Query) -> SummaResult<Box<dyn Query>> {
+        self.query_parser.parse_query(query)
+    }
+
     /// Search `query` in the `IndexHolder` and collecting `Fruit` with a list of `collectors`
     pub(crate) async fn search(&self, query: &proto::Query, collectors: Vec<proto::Collector>) -> SummaResult<Vec<proto::CollectorOutput>> {
         let searcher = self.index_reader.searcher();
@@ -222,7 +484,17 @@ impl IndexHolder {
         let mut multi_collector = MultiCollector::new();
         let mut extractors: Vec<Box<dyn FruitExtractor>> = collectors
             .into_iter()
-            .map(|collector_proto| build_fruit_extractor(collector_proto, &self.cached_fields, &mut multi_collector))
+            .map(|collector_proto| {
+                build_fruit_extractor(
+                    collector_proto,
+                    searcher.index(),
+                    &self.cached_fields,
+                    &parsed_query,
+                    &self.query_parser,
+                    &self.ranking_models,
+                    &mut multi_collector,
+                )
+            })
             .collect::<SummaResult<_>>()?;
         info!(target: "query", index_name = ?self.index_name);
         let multi_fields = self.multi_fields.clone();
@@ -233,7 +505,7 @@ impl IndexHolder {
             let start_time = Instant::now();
             let mut multi_fruit = searcher.search(&parsed_query, &multi_collector)?;
             search_times_meter.record(start_time.elapsed().as_secs_f64(), &[KeyValue::new("index_name", index_name)]);
-            Ok(extractors.drain(..).map(|e| e.extract(&mut multi_fruit, &searcher, &multi_fields)).collect())
+            extractors.drain(..).map(|e| e.extract(&mut multi_fruit, &searcher, &multi_fields)).collect()
         })
         .await?
     }
@@ -262,15 +534,24 @@ impl From<&IndexHolder> for proto::Index {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use crate::configs::{ExpirationConfig, Similarity, VersionConflict};
     use crate::logging;
-    use crate::proto_traits::collector::shortcuts::{scored_doc, top_docs_collector, top_docs_collector_output, top_docs_collector_with_eval_expr};
-    use crate::proto_traits::query::shortcuts::match_query;
-    use crate::requests::CreateIndexRequestBuilder;
-    use crate::search_engine::SummaDocument;
+    use crate::proto_traits::collector::shortcuts::{
+        facet_collector, hybrid_collector, scored_doc, top_docs_collector, top_docs_collector_output, top_docs_collector_with_diversification,
+        top_docs_collector_with_eval_expr, top_docs_collector_with_eval_expr_and_params, top_docs_collector_with_model, top_docs_collector_with_random,
+        top_docs_collector_with_rescorer,
+    };
+    use crate::proto_traits::query::shortcuts::{match_query, term_query, vector_query};
+    use crate::requests::{AlterIndexRequestBuilder, CreateIndexRequestBuilder, ReindexRequestBuilder};
+    use crate::search_engine::expiration::delete_expired_documents;
+    use crate::search_engine::{IndexBulkResult, SummaDocument};
     use crate::services::index_service::tests::create_test_index_service;
     use crate::services::IndexService;
+    use std::collections::HashMap;
+    use std::time::SystemTime;
     use tantivy::doc;
-    use tantivy::schema::{IndexRecordOption, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED};
+    use tantivy::schema::Value;
+    use tantivy::schema::{BytesOptions, FacetOptions, IndexRecordOption, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED, STRING, TEXT};
 
     pub(crate) async fn create_test_index_holder(index_service: &IndexService, fields: &Fields) -> SummaResult<Handler<IndexHolder>> {
         index_service
@@ -311,6 +592,30 @@ pub(crate) mod tests {
         fields_builder.build()
     }
 
+    /// Returns ids and scores of documents in the first `TopDocsCollectorOutput`
+    fn scored_ids(output: Vec<proto::CollectorOutput>) -> Vec<(i64, f64)> {
+        match &output[0].collector_output {
+            Some(proto::collector_output::CollectorOutput::TopDocs(top_docs)) => top_docs
+                .scored_documents
+                .iter()
+                .map(|scored_document| {
+                    let id = serde_json::from_str::<serde_json::Value>(&scored_document.document).unwrap()["id"]
+                        .as_i64()
+                        .unwrap();
+                    match scored_document.score.as_ref().and_then(|score| score.score.as_ref()) {
+                        Some(proto::score::Score::F64Score(score)) => (id, *score),
+                        _ => unreachable!(),
+                    }
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn ids(output: Vec<proto::CollectorOutput>) -> Vec<i64> {
+        scored_ids(output).into_iter().map(|(id, _)| id).collect()
+    }
+
     #[tokio::test]
     async fn test_search() -> SummaResult<()> {
         logging::tests::initialize_default_once();
@@ -425,6 +730,1172 @@ pub(crate) mod tests {
                 false
             )]
         );
+        assert_eq!(
+            index_holder
+                .search(
+                    &match_query("term1"),
+                    vec![top_docs_collector_with_eval_expr_and_params(
+                        1,
+                        "abs(issued_at - user_year_pref)",
+                        &[("user_year_pref", 90.0)]
+                    )]
+                )
+                .await?,
+            vec![top_docs_collector_output(
+                vec![scored_doc(
+                    "{\"body\":\"term1 term7 term8 term9 term10\",\"id\":2,\"issued_at\":110,\"title\":\"term2 term3\"}",
+                    20.0,
+                    0
+                )],
+                false
+            )]
+        );
+        assert_eq!(
+            index_holder
+                .search(
+                    &match_query("term1"),
+                    vec![top_docs_collector_with_eval_expr(
+                        10,
+                        "if(gt(issued_at, 105), 1, 0) + gauss_decay(issued_at, 110, 10)"
+                    )]
+                )
+                .await?,
+            vec![top_docs_collector_output(
+                vec![
+                    scored_doc(
+                        "{\"body\":\"term1 term7 term8 term9 term10\",\"id\":2,\"issued_at\":110,\"title\":\"term2 term3\"}",
+                        2.0,
+                        0
+                    ),
+                    scored_doc(
+                        "{\"body\":\"term3 term4 term5 term6\",\"id\":1,\"issued_at\":100,\"title\":\"term1 term2\"}",
+                        0.5,
+                        1
+                    )
+                ],
+                false
+            )]
+        );
+        assert!(index_holder
+            .search(
+                &match_query("term1"),
+                vec![top_docs_collector_with_eval_expr_and_params(1, "now", &[("now", 1.0)])]
+            )
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rescoring() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = create_test_index_holder(&index_service, &fields).await?;
+
+        for (id, title, issued_at) in [(1i64, "term1", 100i64), (2, "term1 term2 term3", 120), (3, "term1 term2", 110)] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                fields.get_field("id").unwrap() => id,
+                fields.get_field("title").unwrap() => title,
+                fields.get_field("issued_at").unwrap() => issued_at
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(
+            index_holder
+                .search(
+                    &match_query("term1"),
+                    vec![top_docs_collector_with_rescorer(2, 0, 10, "issued_at + 0 * original_score")]
+                )
+                .await?,
+            vec![top_docs_collector_output(
+                vec![
+                    scored_doc("{\"id\":2,\"issued_at\":120,\"title\":\"term1 term2 term3\"}", 120.0, 0),
+                    scored_doc("{\"id\":3,\"issued_at\":110,\"title\":\"term1 term2\"}", 110.0, 1)
+                ],
+                true
+            )]
+        );
+        assert_eq!(
+            index_holder
+                .search(&match_query("term1"), vec![top_docs_collector_with_rescorer(2, 1, 2, "issued_at")])
+                .await?,
+            vec![top_docs_collector_output(
+                vec![scored_doc("{\"id\":1,\"issued_at\":100,\"title\":\"term1\"}", 100.0, 0), {
+                    // The least relevant document is outside of the window and keeps its BM25 score
+                    let output = index_holder.search(&match_query("term1"), vec![top_docs_collector(3)]).await?;
+                    match &output[0].collector_output {
+                        Some(proto::collector_output::CollectorOutput::TopDocs(top_docs)) => proto::ScoredDocument {
+                            position: 1,
+                            ..top_docs.scored_documents[2].clone()
+                        },
+                        _ => unreachable!(),
+                    }
+                }],
+                false
+            )]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_similarities() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = create_test_index_holder(&index_service, &fields).await?;
+        let similarities_index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index_with_similarities".to_owned())
+                    .default_fields(vec!["title".to_owned(), "body".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .similarities(HashMap::from_iter([
+                        ("title".to_owned(), Similarity::Bm25 { k1: 1.2, b: 0.0 }),
+                        ("body".to_owned(), Similarity::Bm25 { k1: 1.2, b: 0.75 }),
+                    ]))
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        for holder in [&index_holder, &similarities_index_holder] {
+            for (id, text) in [(1i64, "term1"), (2, "term1 term1 term2 term3 term4 term5 term6 term7")] {
+                holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                    fields.get_field("id").unwrap() => id,
+                    fields.get_field("title").unwrap() => text,
+                    fields.get_field("body").unwrap() => text
+                )))?;
+            }
+            holder.index_updater().write().commit().await?;
+            holder.index_reader().reload()?;
+        }
+
+        // Length normalization makes the short title more relevant by default while `b = 0` prefers the higher term frequency
+        assert_eq!(
+            ids(index_holder.search(&term_query("title", "term1"), vec![top_docs_collector(10)]).await?),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(similarities_index_holder
+                .search(&term_query("title", "term1"), vec![top_docs_collector(10)])
+                .await?),
+            vec![2, 1]
+        );
+        // Similarity having default parameters scores in the same way as Tantivy does
+        let default_scored_ids = scored_ids(index_holder.search(&term_query("body", "term1"), vec![top_docs_collector(10)]).await?);
+        let similarity_scored_ids = scored_ids(
+            similarities_index_holder
+                .search(&term_query("body", "term1"), vec![top_docs_collector(10)])
+                .await?,
+        );
+        assert_eq!(default_scored_ids.len(), 2);
+        for ((default_id, default_score), (id, score)) in default_scored_ids.into_iter().zip(similarity_scored_ids) {
+            assert_eq!(default_id, id);
+            assert!((default_score - score).abs() < 1e-6);
+        }
+
+        // Unset parameters of BM25 are replaced with defaults and negative ones are rejected
+        assert_eq!(
+            Similarity::try_from(proto::Similarity {
+                similarity: Some(proto::similarity::Similarity::Bm25(proto::Bm25Similarity { k1: 0.0, b: 0.0 }))
+            })?,
+            Similarity::Bm25 { k1: 1.2, b: 0.75 }
+        );
+        assert!(index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index_with_invalid_similarities".to_owned())
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .similarities(HashMap::from_iter([("title".to_owned(), Similarity::Bm25 { k1: -1.0, b: 0.75 })]))
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diversification() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = create_test_index_holder(&index_service, &fields).await?;
+
+        for (id, title, issued_at) in [(1i64, "term1", 100i64), (2, "term1 term2", 100), (3, "term1 term3 term4", 200)] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                fields.get_field("id").unwrap() => id,
+                fields.get_field("title").unwrap() => title,
+                fields.get_field("issued_at").unwrap() => issued_at
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+
+        assert_eq!(
+            ids(index_holder.search(&match_query("term1"), vec![top_docs_collector(3)]).await?),
+            vec![1, 2, 3]
+        );
+        let same_issued_at = proto::diversification::Similarity::FastField("issued_at".to_owned());
+        assert_eq!(
+            ids(index_holder
+                .search(
+                    &match_query("term1"),
+                    vec![top_docs_collector_with_diversification(3, 3, 0.3, same_issued_at.clone())]
+                )
+                .await?),
+            vec![1, 3, 2]
+        );
+        // Documents keep their scores and `lambda = 1` keeps the original order
+        assert_eq!(
+            index_holder
+                .search(&match_query("term1"), vec![top_docs_collector_with_diversification(3, 3, 1.0, same_issued_at)])
+                .await?,
+            index_holder.search(&match_query("term1"), vec![top_docs_collector(3)]).await?
+        );
+        let title_overlap = proto::diversification::Similarity::TermOverlapField("title".to_owned());
+        assert_eq!(
+            ids(index_holder
+                .search(&match_query("term1"), vec![top_docs_collector_with_diversification(3, 3, 0.1, title_overlap)])
+                .await?),
+            vec![1, 3, 2]
+        );
+        assert!(index_holder
+            .search(
+                &match_query("term1"),
+                vec![top_docs_collector_with_diversification(
+                    3,
+                    3,
+                    0.5,
+                    proto::diversification::Similarity::FastField("title".to_owned())
+                )]
+            )
+            .await
+            .is_err());
+        assert!(index_holder
+            .search(
+                &match_query("term1"),
+                vec![top_docs_collector_with_diversification(
+                    3,
+                    3,
+                    1.5,
+                    proto::diversification::Similarity::FastField("issued_at".to_owned())
+                )]
+            )
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_random_scoring() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = create_test_index_holder(&index_service, &fields).await?;
+
+        for id in 0..20i64 {
+            let title = if id % 2 == 0 { "term1" } else { "term2" };
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                fields.get_field("id").unwrap() => id,
+                fields.get_field("title").unwrap() => title
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+
+        let all_ids = ids(index_holder
+            .search(&match_query("term1"), vec![top_docs_collector_with_random(20, 0, 42, "id")])
+            .await?);
+        // Scores are combined with the query and pages of the same seed are consistent
+        assert_eq!(all_ids.len(), 10);
+        assert!(all_ids.iter().all(|id| id % 2 == 0));
+        let mut paginated_ids = vec![];
+        for offset in [0, 4, 8] {
+            paginated_ids.extend(ids(index_holder
+                .search(&match_query("term1"), vec![top_docs_collector_with_random(4, offset, 42, "id")])
+                .await?));
+        }
+        assert_eq!(paginated_ids, all_ids);
+        assert_ne!(
+            ids(index_holder
+                .search(&match_query("term1"), vec![top_docs_collector_with_random(20, 0, 43, "id")])
+                .await?),
+            all_ids
+        );
+        assert!(index_holder
+            .search(&match_query("term1"), vec![top_docs_collector_with_random(20, 0, 42, "title")])
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deletion() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .default_fields(vec!["title".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .primary_key(Some("id".to_owned()))
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        for (id, title) in [(1i64, "term1"), (2, "term1 term2"), (3, "term2"), (4, "term3")] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                fields.get_field("id").unwrap() => id,
+                fields.get_field("title").unwrap() => title
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+
+        index_holder.index_updater().read().delete_document(&Value::I64(1))?;
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(ids(index_holder.search(&match_query("term1"), vec![top_docs_collector(10)]).await?), vec![2]);
+
+        // Document reindexed after the last commit is not deleted along with its committed version
+        index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+            fields.get_field("id").unwrap() => 3i64,
+            fields.get_field("title").unwrap() => "term4"
+        )))?;
+        assert_eq!(index_holder.delete_by_query(&match_query("term2")).await?, 1);
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(index_holder.index_reader().searcher().num_docs(), 2);
+        assert_eq!(ids(index_holder.search(&match_query("term3"), vec![top_docs_collector(10)]).await?), vec![4]);
+        assert_eq!(ids(index_holder.search(&match_query("term4"), vec![top_docs_collector(10)]).await?), vec![3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_update() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", FAST | INDEXED | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        fields_builder.add_text_field("tags", TEXT | STORED);
+        fields_builder.add_i64_field("citations", INDEXED | STORED);
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .default_fields(vec!["title".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .primary_key(Some("id".to_owned()))
+                    .multi_fields(vec!["tags".to_owned()])
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+        index_holder
+            .index_updater()
+            .read()
+            .index_document(SummaDocument::UnboundJsonBytes(br#"{"id": 1, "title": "title1", "tags": ["tag1", "tag2"], "citations": 1}"#))?;
+        index_holder.index_updater().write().commit().await?;
+
+        let update_modes = HashMap::from([("tags".to_owned(), proto::UpdateMode::Append)]);
+        index_holder
+            .update_document(Value::I64(1), br#"{"citations": 10, "tags": "tag3"}"#.to_vec(), &update_modes)
+            .await?;
+        // The second update sees the first one before commit
+        let update_modes = HashMap::from([("tags".to_owned(), proto::UpdateMode::Remove)]);
+        index_holder
+            .update_document(Value::I64(1), br#"{"tags": "tag1"}"#.to_vec(), &update_modes)
+            .await?;
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(
+            index_holder.search(&match_query("title1"), vec![top_docs_collector(10)]).await?,
+            vec![top_docs_collector_output(
+                vec![scored_doc(
+                    "{\"citations\":10,\"id\":1,\"tags\":[\"tag2\",\"tag3\"],\"title\":\"title1\"}",
+                    0.18232159316539764,
+                    0
+                )],
+                false
+            )]
+        );
+
+        assert!(index_holder
+            .update_document(Value::I64(2), br#"{"citations": 10}"#.to_vec(), &HashMap::new())
+            .await
+            .is_err());
+        let update_modes = HashMap::from([("citations".to_owned(), proto::UpdateMode::Append)]);
+        assert!(index_holder
+            .update_document(Value::I64(1), br#"{"citations": 10}"#.to_vec(), &update_modes)
+            .await
+            .is_err());
+        // Documents indexed after the last commit cannot be updated until the commit
+        index_holder
+            .index_updater()
+            .read()
+            .index_document(SummaDocument::UnboundJsonBytes(br#"{"id": 2, "title": "title2", "citations": 1}"#))?;
+        assert!(index_holder
+            .update_document(Value::I64(2), br#"{"citations": 10}"#.to_vec(), &HashMap::new())
+            .await
+            .is_err());
+        index_holder.index_updater().write().commit().await?;
+        index_holder
+            .update_document(Value::I64(2), br#"{"citations": 10}"#.to_vec(), &HashMap::new())
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_string_primary_key() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_text_field("doi", STRING | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let create_index_request = |index_name: &str, primary_key: &str| {
+            CreateIndexRequestBuilder::default()
+                .index_name(index_name.to_owned())
+                .default_fields(vec!["title".to_owned()])
+                .index_engine(proto::IndexEngine::Memory)
+                .fields(fields.clone())
+                .primary_key(Some(primary_key.to_owned()))
+                .build()
+                .unwrap()
+        };
+        // Tokenized text fields cannot be primary keys
+        assert!(index_service
+            .create_index(create_index_request("test_index_with_text_primary_key", "title"))
+            .await
+            .is_err());
+        let index_holder = index_service.create_index(create_index_request("test_index", "doi")).await?;
+
+        for document in [
+            r#"{"doi": "10.1000/1", "title": "old title"}"#,
+            r#"{"doi": "10.1000/2", "title": "another title"}"#,
+            r#"{"doi": "10.1000/1", "title": "new title"}"#,
+        ] {
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(document.as_bytes()))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(index_holder.index_reader().searcher().num_docs(), 2);
+        assert_eq!(
+            index_holder.search(&match_query("new"), vec![top_docs_collector(10)]).await?,
+            vec![top_docs_collector_output(
+                vec![scored_doc("{\"doi\":\"10.1000/1\",\"title\":\"new title\"}", 0.9808291792869568, 0)],
+                false
+            )]
+        );
+
+        index_holder
+            .update_document(Value::Str("10.1000/2".to_owned()), br#"{"title": "updated title"}"#.to_vec(), &HashMap::new())
+            .await?;
+        index_holder.index_updater().read().delete_document(&Value::Str("10.1000/1".to_owned()))?;
+        assert!(index_holder.index_updater().read().delete_document(&Value::I64(1)).is_err());
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(index_holder.index_reader().searcher().num_docs(), 1);
+        assert_eq!(
+            index_holder.search(&match_query("updated"), vec![top_docs_collector(10)]).await?,
+            vec![top_docs_collector_output(
+                vec![scored_doc("{\"doi\":\"10.1000/2\",\"title\":\"updated title\"}", 0.28768211603164673, 0)],
+                false
+            )]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_versioning() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", INDEXED | STORED);
+        fields_builder.add_i64_field("version", FAST | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let create_index_request = |index_name: &str, version_conflict: VersionConflict| {
+            CreateIndexRequestBuilder::default()
+                .index_name(index_name.to_owned())
+                .default_fields(vec!["title".to_owned()])
+                .index_engine(proto::IndexEngine::Memory)
+                .fields(fields.clone())
+                .primary_key(Some("id".to_owned()))
+                .version_field(Some("version".to_owned()))
+                .version_conflict(version_conflict)
+                .build()
+                .unwrap()
+        };
+        let index_document = |index_holder: &IndexHolder, document: &str| {
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(document.as_bytes()))
+        };
+
+        let index_holder = index_service.create_index(create_index_request("test_index", VersionConflict::Skip)).await?;
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 2, "title": "second title"}"#)?.is_some());
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 1, "title": "first title"}"#)?.is_none());
+        assert!(index_document(&index_holder, r#"{"id": 1, "title": "unversioned title"}"#).is_err());
+        index_holder.index_updater().write().commit().await?;
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 2, "title": "first title"}"#)?.is_none());
+        index_holder.index_reader().reload()?;
+        assert_eq!(ids(index_holder.search(&match_query("title"), vec![top_docs_collector(10)]).await?), vec![1]);
+        assert!(ids(index_holder.search(&match_query("first"), vec![top_docs_collector(10)]).await?).is_empty());
+
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 3, "title": "third title"}"#)?.is_some());
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(ids(index_holder.search(&match_query("third"), vec![top_docs_collector(10)]).await?), vec![1]);
+        assert_eq!(index_holder.index_reader().searcher().num_docs(), 1);
+
+        let update_modes = HashMap::new();
+        let update_document = |version: i64| {
+            index_holder.update_document(
+                Value::I64(1),
+                format!(r#"{{"version": {}, "title": "updated title"}}"#, version).into_bytes(),
+                &update_modes,
+            )
+        };
+        assert!(update_document(3).await?.is_none());
+        assert!(update_document(4).await?.is_some());
+        index_holder.index_updater().read().delete_document(&Value::I64(1))?;
+        index_holder.index_updater().write().commit().await?;
+        // The version of the deleted document is kept, so its outdated versions are not indexed again
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 4, "title": "replayed title"}"#)?.is_none());
+        assert!(index_document(&index_holder, r#"{"id": 1, "version": 5, "title": "fifth title"}"#)?.is_some());
+
+        let index_holder = index_service
+            .create_index(create_index_request("test_index_with_rejection", VersionConflict::Reject))
+            .await?;
+        index_document(&index_holder, r#"{"id": 1, "version": 2, "title": "second title"}"#)?;
+        index_holder.index_updater().write().commit().await?;
+        assert!(matches!(
+            index_document(&index_holder, r#"{"id": 1, "version": 2, "title": "first title"}"#),
+            Err(Error::Validation(ValidationError::OutdatedVersion {
+                version: 2,
+                indexed_version: 2
+            }))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_errors() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .default_fields(vec!["title".to_owned(), "body".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(create_test_fields())
+                    .primary_key(Some("id".to_owned()))
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        let mut result = IndexBulkResult::default();
+        let documents = [
+            r#"{"id": 1, "title": "first title"}"#,
+            r#"{"id": 2, "issued_at": "yesterday"}"#,
+            r#"{"id": 3, "title": "#,
+            r#"{"id": 4, "title": "fourth title"}"#,
+        ];
+        index_holder.index_updater().read().index_bulk(
+            &documents.map(|document| document.as_bytes().to_vec()),
+            proto::DocumentFormat::Json,
+            &mut result,
+        );
+        index_holder
+            .index_updater()
+            .read()
+            .index_bulk(&[br#"{"id": "5"}"#.to_vec()], proto::DocumentFormat::Json, &mut result);
+        assert_eq!((result.success_docs, result.skipped_docs, result.failed_docs), (2, 0, 3));
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(|error| (error.position, error.primary_key.clone(), error.field.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    1,
+                    Some(proto::PrimaryKey {
+                        value: Some(proto::primary_key::Value::I64(2))
+                    }),
+                    Some("issued_at".to_owned())
+                ),
+                (2, None, None),
+                (4, None, Some("id".to_owned())),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expiration() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        let id = fields_builder.add_i64_field("id", INDEXED | STORED);
+        let issued_at = fields_builder.add_date_field("issued_at", INDEXED | STORED);
+        let title = fields_builder.add_text_field("title", TEXT | STORED);
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let create_index_request = |index_name: &str, date_field: &str| {
+            CreateIndexRequestBuilder::default()
+                .index_name(index_name.to_owned())
+                .default_fields(vec!["title".to_owned()])
+                .index_engine(proto::IndexEngine::Memory)
+                .fields(fields.clone())
+                .primary_key(Some("id".to_owned()))
+                .expiration(Some(ExpirationConfig {
+                    date_field: date_field.to_owned(),
+                    ttl_secs: Some(3600),
+                    interval_ms: 3_600_000,
+                }))
+                .build()
+                .unwrap()
+        };
+        assert!(index_service
+            .create_index(create_index_request("test_index_with_text_expiration", "title"))
+            .await
+            .is_err());
+        let index_holder = index_service.create_index(create_index_request("test_index", "issued_at")).await?;
+
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+        for (id_value, issued_at_value) in [(1i64, now - 7200), (2, now), (3, now - 3000)] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                id => id_value,
+                issued_at => tantivy::DateTime::from_unix_timestamp(issued_at_value),
+                title => "title"
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        let expire_documents = || {
+            delete_expired_documents(
+                &index_holder.index_reader().searcher(),
+                &index_holder.index_updater().read(),
+                issued_at,
+                id,
+                Some(3600),
+                SystemTime::now(),
+            )
+        };
+        expire_documents()?;
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(ids(index_holder.search(&match_query("title"), vec![top_docs_collector(10)]).await?), vec![2, 3]);
+
+        // Expired document reindexed after the last commit is not deleted along with its committed version
+        for issued_at_value in [now - 7200, now] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                id => 4i64,
+                issued_at => tantivy::DateTime::from_unix_timestamp(issued_at_value),
+                title => "title"
+            )))?;
+            if issued_at_value < now {
+                index_holder.index_updater().write().commit().await?;
+                index_holder.index_reader().reload()?;
+            }
+        }
+        assert_eq!(expire_documents()?, 0);
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(
+            ids(index_holder.search(&match_query("title"), vec![top_docs_collector(10)]).await?),
+            vec![2, 3, 4]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_added_fields() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", INDEXED | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        let fields = fields_builder.build();
+        let added_fields = |text_options: TextOptions| {
+            let mut fields_builder = Fields::builder();
+            fields_builder.add_text_field("tags", text_options);
+            fields_builder.build()
+        };
+        let tags_options = TextOptions::default()
+            .set_stored()
+            .set_indexing_options(TextFieldIndexing::default().set_fieldnorms(false));
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+        let index_service = create_test_index_service(&data_path).await;
+
+        for (index_name, index_engine) in [("test_index_memory", proto::IndexEngine::Memory), ("test_index_file", proto::IndexEngine::File)] {
+            let alter_index_request = |added_fields: Fields| {
+                AlterIndexRequestBuilder::default()
+                    .index_name(index_name.to_owned())
+                    .added_fields(Some(added_fields))
+                    .build()
+                    .unwrap()
+            };
+            let index_holder = index_service
+                .create_index(
+                    CreateIndexRequestBuilder::default()
+                        .index_name(index_name.to_owned())
+                        .default_fields(vec!["title".to_owned()])
+                        .index_engine(index_engine)
+                        .fields(fields.clone())
+                        .build()
+                        .unwrap(),
+                )
+                .await?;
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(br#"{"id": 1, "title": "title"}"#))?;
+            index_holder.index_updater().write().commit().await?;
+            drop(index_holder);
+
+            assert!(index_service.alter_index(alter_index_request(added_fields(TEXT))).await.is_err());
+            assert!(index_service.alter_index(alter_index_request(fields.clone())).await.is_err());
+            let index_holder = index_service.alter_index(alter_index_request(added_fields(tags_options.clone()))).await?;
+            assert!(index_holder.fields().get_field("tags").is_some());
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(br#"{"id": 2, "title": "title", "tags": "rust"}"#))?;
+            index_holder.index_updater().write().commit().await?;
+            // Old segments lacking the added field are merged with the new ones
+            let segment_ids = index_holder.index_updater().read().index().searchable_segment_ids()?;
+            index_holder.index_updater().write().merge(&segment_ids).await?;
+            index_holder.index_reader().reload()?;
+            assert_eq!(ids(index_holder.search(&match_query("title"), vec![top_docs_collector(10)]).await?).len(), 2);
+            assert_eq!(
+                ids(index_holder.search(&match_query("tags:rust"), vec![top_docs_collector(10)]).await?),
+                vec![2]
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reindex() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut source_fields_builder = Fields::builder();
+        source_fields_builder.add_i64_field("id", INDEXED | STORED);
+        source_fields_builder.add_text_field("title", TEXT | STORED);
+        let mut target_fields_builder = Fields::builder();
+        target_fields_builder.add_i64_field("id", INDEXED | STORED);
+        target_fields_builder.add_text_field("name", TEXT | STORED);
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+        let index_service = create_test_index_service(&data_path).await;
+        let create_index_request = |index_name: &str, default_field: &str, fields: Fields| {
+            CreateIndexRequestBuilder::default()
+                .index_name(index_name.to_owned())
+                .default_fields(vec![default_field.to_owned()])
+                .index_engine(proto::IndexEngine::Memory)
+                .fields(fields)
+                .build()
+                .unwrap()
+        };
+        let source_index_holder = index_service
+            .create_index(create_index_request("source_index", "title", source_fields_builder.build()))
+            .await?;
+        let target_index_holder = index_service
+            .create_index(create_index_request("target_index", "name", target_fields_builder.build()))
+            .await?;
+
+        for documents in [vec![(1, "rust book"), (2, "python book")], vec![(3, "rust guide")]] {
+            for (id, title) in documents {
+                source_index_holder
+                    .index_updater()
+                    .read()
+                    .index_document(SummaDocument::UnboundJsonBytes(format!(r#"{{"id": {}, "title": "{}"}}"#, id, title).as_bytes()))?;
+            }
+            source_index_holder.index_updater().write().commit().await?;
+        }
+        source_index_holder.index_reader().reload()?;
+
+        let reindex_request = |source_index_alias: &str| {
+            ReindexRequestBuilder::default()
+                .source_index_alias(source_index_alias.to_owned())
+                .target_index_alias("target_index".to_owned())
+                .query(Some(match_query("rust")))
+                .field_mapping(HashMap::from([("title".to_owned(), "name".to_owned())]))
+                .index_alias(Some("test_alias".to_owned()))
+                .build()
+                .unwrap()
+        };
+        assert!(index_service.reindex(reindex_request("target_index")).is_err());
+        let mut progress_receiver = index_service.reindex(reindex_request("source_index"))?;
+        let mut progress = vec![];
+        while let Some(reindex_progress) = progress_receiver.recv().await {
+            progress.push(reindex_progress?);
+        }
+        assert_eq!(
+            progress
+                .iter()
+                .map(|reindex_progress| (reindex_progress.processed_segments, reindex_progress.total_segments, reindex_progress.finished))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, false), (2, 2, false), (2, 2, true)]
+        );
+        assert_eq!(progress[2].success_docs, 2);
+        assert_eq!(progress[2].old_index_name, None);
+        assert_eq!(progress.iter().map(|reindex_progress| reindex_progress.failed_docs).sum::<u64>(), 0);
+
+        target_index_holder.index_reader().reload()?;
+        let mut reindexed_ids = ids(target_index_holder.search(&match_query("rust"), vec![top_docs_collector(10)]).await?);
+        reindexed_ids.sort();
+        assert_eq!(reindexed_ids, vec![1, 3]);
+        assert_eq!(index_service.get_index_holder("test_alias")?.index_name(), "target_index");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_model_scoring() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let fields = create_test_fields();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = create_test_index_holder(&index_service, &fields).await?;
+        std::fs::create_dir_all(data_path.join("models"))?;
+        std::fs::write(
+            data_path.join("models").join("test_model.json"),
+            r#"{"type": "linear", "weights": {"query_terms_matched": 1000.0, "issued_at": 1.0, "shift": 1.0}}"#,
+        )?;
+
+        for (id, title, issued_at) in [(1i64, "term1", 100i64), (2, "term1 term2", 110), (3, "term2", 120)] {
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(doc!(
+                fields.get_field("id").unwrap() => id,
+                fields.get_field("title").unwrap() => title,
+                fields.get_field("issued_at").unwrap() => issued_at
+            )))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(
+            index_holder
+                .search(
+                    &match_query("term1 term2"),
+                    vec![top_docs_collector_with_model(2, "test_model", 10, &[("shift", 5.0)])]
+                )
+                .await?,
+            vec![top_docs_collector_output(
+                vec![
+                    scored_doc("{\"id\":2,\"issued_at\":110,\"title\":\"term1 term2\"}", 2115.0, 0),
+                    scored_doc("{\"id\":3,\"issued_at\":120,\"title\":\"term2\"}", 1125.0, 1)
+                ],
+                true
+            )]
+        );
+        assert!(index_holder
+            .search(&match_query("term1"), vec![top_docs_collector_with_model(2, "unknown_model", 10, &[])])
+            .await
+            .is_err());
+        assert!(index_holder
+            .search(&match_query("term1"), vec![top_docs_collector_with_model(2, "../test_model", 10, &[])])
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_vector_search() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", FAST | INDEXED | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        fields_builder.add_bytes_field("embedding", BytesOptions::default().set_fast());
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .default_fields(vec!["title".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .vector_fields(HashMap::from([("embedding".to_owned(), 2)]))
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        for document in [
+            r#"{"id": 1, "title": "term1", "embedding": [1.0, 0.0]}"#,
+            r#"{"id": 2, "title": "term1", "embedding": [0.6, 0.8]}"#,
+            r#"{"id": 3, "title": "term2", "embedding": [0.0, 1.0]}"#,
+        ] {
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(document.as_bytes()))?;
+        }
+        // Vectors of other dimensions are rejected on indexing
+        assert!(index_holder
+            .index_updater()
+            .read()
+            .index_document(SummaDocument::UnboundJsonBytes(br#"{"id": 4, "title": "term1", "embedding": [0.0, 1.0, 0.0]}"#))
+            .is_err());
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+        assert_eq!(
+            index_holder
+                .search(&vector_query("embedding", &[0.0, 2.0], 2, None), vec![top_docs_collector(10)])
+                .await?,
+            vec![top_docs_collector_output(
+                vec![
+                    scored_doc("{\"id\":3,\"title\":\"term2\"}", 1.0, 0),
+                    scored_doc("{\"id\":2,\"title\":\"term1\"}", 0.800000011920929, 1)
+                ],
+                false
+            )]
+        );
+        // Documents not matching the filter are never returned
+        assert_eq!(
+            index_holder
+                .search(
+                    &vector_query("embedding", &[0.0, 2.0], 10, Some(term_query("title", "term1"))),
+                    vec![top_docs_collector(10)]
+                )
+                .await?,
+            vec![top_docs_collector_output(
+                vec![
+                    scored_doc("{\"id\":2,\"title\":\"term1\"}", 0.800000011920929, 0),
+                    scored_doc("{\"id\":1,\"title\":\"term1\"}", 0.0, 1)
+                ],
+                false
+            )]
+        );
+        assert!(index_holder
+            .search(&vector_query("title", &[0.0, 2.0], 2, None), vec![top_docs_collector(10)])
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", FAST | INDEXED | STORED);
+        fields_builder.add_text_field("title", TEXT | STORED);
+        fields_builder.add_bytes_field("embedding", BytesOptions::default().set_fast());
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .default_fields(vec!["title".to_owned()])
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .vector_fields(HashMap::from([("embedding".to_owned(), 2)]))
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        for document in [
+            r#"{"id": 1, "title": "term1 term1", "embedding": [1.0, 0.0]}"#,
+            r#"{"id": 2, "title": "term1", "embedding": [0.6, 0.8]}"#,
+            r#"{"id": 3, "title": "term2", "embedding": [0.0, 1.0]}"#,
+        ] {
+            index_holder
+                .index_updater()
+                .read()
+                .index_document(SummaDocument::UnboundJsonBytes(document.as_bytes()))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+
+        // Lexical ranking is [1, 2] and vector ranking is [3, 2, 1]
+        let rrf = proto::hybrid_collector::Fusion::Rrf(proto::ReciprocalRankFusion { k: None });
+        assert_eq!(
+            ids(index_holder
+                .search(&match_query("term1"), vec![hybrid_collector(2, 0, "embedding", &[0.0, 1.0], 3, rrf.clone())])
+                .await?),
+            vec![1, 2]
+        );
+        let output = index_holder
+            .search(&match_query("term1"), vec![hybrid_collector(2, 2, "embedding", &[0.0, 1.0], 3, rrf)])
+            .await?;
+        assert_eq!(
+            output,
+            vec![top_docs_collector_output(
+                vec![scored_doc("{\"id\":3,\"title\":\"term2\"}", 1.0 / 61.0, 0)],
+                false
+            )]
+        );
+        let weighted = proto::hybrid_collector::Fusion::Weighted(proto::WeightedScoreFusion {
+            lexical_weight: None,
+            vector_weight: Some(2.0),
+        });
+        assert_eq!(
+            scored_ids(
+                index_holder
+                    .search(&match_query("term1"), vec![hybrid_collector(3, 0, "embedding", &[0.0, 1.0], 3, weighted)])
+                    .await?
+            ),
+            vec![(3, 2.0), (2, 1.600000023841858), (1, 1.0)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_facet_drill_down() -> SummaResult<()> {
+        logging::tests::initialize_default_once();
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_i64_field("id", FAST | INDEXED | STORED);
+        fields_builder.add_facet_field("category", FacetOptions::default().set_stored());
+        let fields = fields_builder.build();
+
+        let root_path = tempdir::TempDir::new("summa_test").unwrap();
+        let data_path = root_path.path().join("data");
+
+        let index_service = create_test_index_service(&data_path).await;
+        let index_holder = index_service
+            .create_index(
+                CreateIndexRequestBuilder::default()
+                    .index_name("test_index".to_owned())
+                    .index_engine(proto::IndexEngine::Memory)
+                    .fields(fields.clone())
+                    .build()
+                    .unwrap(),
+            )
+            .await?;
+
+        let category = fields.get_field("category").unwrap();
+        for (id, facets) in [
+            (1i64, vec!["/science/biology/genetics"]),
+            (2i64, vec!["/science/biology/ecology", "/science/biology/genetics"]),
+            (3i64, vec!["/science/physics"]),
+            (4i64, vec!["/science/physics/optics"]),
+            (5i64, vec!["/science/chemistry"]),
+            (6i64, vec!["/fiction"]),
+        ] {
+            let mut document = doc!(fields.get_field("id").unwrap() => id);
+            for facet in facets {
+                document.add_facet(category, facet);
+            }
+            index_holder.index_updater().read().index_document(SummaDocument::TantivyDocument(document))?;
+        }
+        index_holder.index_updater().write().commit().await?;
+        index_holder.index_reader().reload()?;
+
+        let facet_node = |facet: &str, count: u64, children: Vec<proto::FacetTreeNode>| proto::FacetTreeNode {
+            facet: facet.to_owned(),
+            count,
+            children,
+        };
+        let facet_trees = |collector_outputs: Vec<proto::CollectorOutput>| match collector_outputs[0].collector_output {
+            Some(proto::collector_output::CollectorOutput::Facet(ref facet_collector_output)) => facet_collector_output.facet_trees.clone(),
+            _ => panic!("facet collector output expected"),
+        };
+
+        assert_eq!(
+            facet_trees(
+                index_holder
+                    .search(
+                        &proto::Query { query: None },
+                        vec![facet_collector("category", &["/science"], Some(2), Some(2))]
+                    )
+                    .await?
+            ),
+            vec![facet_node(
+                "/science",
+                5,
+                vec![
+                    facet_node(
+                        "/science/biology",
+                        2,
+                        vec![
+                            facet_node("/science/biology/genetics", 2, vec![]),
+                            facet_node("/science/biology/ecology", 1, vec![])
+                        ]
+                    ),
+                    facet_node("/science/physics", 2, vec![facet_node("/science/physics/optics", 1, vec![])]),
+                ]
+            )]
+        );
+        assert_eq!(
+            facet_trees(
+                index_holder
+                    .search(&term_query("category", "/science/biology"), vec![facet_collector("category", &[], None, None)])
+                    .await?
+            ),
+            vec![facet_node("/", 2, vec![facet_node("/science", 2, vec![])])]
+        );
         Ok(())
     }
 }
@@ -4,17 +4,17 @@ use crate::consumers::kafka::status::{KafkaConsumingError, KafkaConsumingStatus}
 use crate::consumers::kafka::Consumer;
 use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
-use crate::search_engine::SummaDocument;
+use crate::search_engine::{DocumentParser, DocumentParsingError, SummaDocument};
 use rdkafka::message::BorrowedMessage;
 use rdkafka::Message;
 use std::collections::hash_map::Entry;
 use std::sync::Arc;
-use tantivy::schema::Schema as Fields;
-use tantivy::{Index, Opstamp, SegmentId, SegmentMeta};
+use tantivy::schema::{Field, Value};
+use tantivy::{Document, Index, Opstamp, Searcher, SegmentId, SegmentMeta};
 use tracing::{instrument, warn};
 
 fn process_message(
-    fields: &Fields,
+    document_parser: &DocumentParser,
     index_writer_holder: &IndexWriterHolder,
     message: Result<BorrowedMessage<'_>, rdkafka::error::KafkaError>,
 ) -> Result<KafkaConsumingStatus, KafkaConsumingError> {
@@ -24,12 +24,44 @@ fn process_message(
     let index_operation = proto_message.operation.ok_or(KafkaConsumingError::EmptyOperation)?;
     match index_operation {
         proto::index_operation::Operation::IndexDocument(index_document_operation) => {
-            let parsed_document = SummaDocument::BoundJsonBytes((fields, &index_document_operation.document))
-                .try_into()
+            let parsed_document = document_parser
+                .parse(SummaDocument::UnboundBytes((
+                    proto::DocumentFormat::from_i32(index_document_operation.format).unwrap_or(proto::DocumentFormat::Json),
+                    &index_document_operation.document,
+                )))
                 .map_err(KafkaConsumingError::ParseDocument)?;
+            // Documents skipped due to outdated versions are consumed too
             index_writer_holder.index_document(parsed_document).map_err(KafkaConsumingError::Index)?;
             Ok(KafkaConsumingStatus::Consumed)
         }
+        proto::index_operation::Operation::DeleteDocument(delete_document_operation) => {
+            let primary_key_value = delete_document_operation
+                .primary_key
+                .ok_or_else(|| ValidationError::EmptyArgument("primary_key".to_owned()).into())
+                .and_then(Value::try_from)
+                .map_err(KafkaConsumingError::Index)?;
+            index_writer_holder.delete_document(&primary_key_value).map_err(KafkaConsumingError::Index)?;
+            Ok(KafkaConsumingStatus::Consumed)
+        }
+    }
+}
+
+/// Maximum number of failures returned for a bulk of documents, remaining ones are only counted
+const MAX_INDEX_BULK_ERRORS: usize = 100;
+
+/// Outcome of indexing multiple documents
+#[derive(Default)]
+pub(crate) struct IndexBulkResult {
+    pub success_docs: u64,
+    pub skipped_docs: u64,
+    pub failed_docs: u64,
+    /// First `MAX_INDEX_BULK_ERRORS` failures
+    pub errors: Vec<proto::IndexDocumentError>,
+}
+
+impl IndexBulkResult {
+    fn processed_docs(&self) -> u64 {
+        self.success_docs + self.skipped_docs + self.failed_docs
     }
 }
 
@@ -40,22 +72,24 @@ pub(crate) struct IndexUpdater {
     index: Index,
     index_name: String,
     index_writer_holder: Arc<IndexWriterHolder>,
+    document_parser: Arc<DocumentParser>,
 }
 
 impl IndexUpdater {
     /// Creates new `IndexUpdater`
     pub(super) fn new(index: Index, index_name: &str, index_config_proxy: IndexConfigProxy) -> SummaResult<IndexUpdater> {
         let index_config = index_config_proxy.read().get().clone();
+        let schema = index.schema();
         let index_writer_holder = Arc::new(IndexWriterHolder::new(
             index.writer_with_num_threads(
                 index_config.writer_threads.try_into().unwrap(),
                 index_config.writer_heap_size_bytes.try_into().unwrap(),
             )?,
-            match index_config.primary_key {
-                Some(ref primary_key) => index.schema().get_field(primary_key),
-                None => None,
-            },
+            index_config.primary_key.as_ref().and_then(|primary_key| schema.get_field(primary_key)),
+            index_config.version_field.as_ref().and_then(|version_field| schema.get_field(version_field)),
+            index_config.version_conflict,
         )?);
+        let document_parser = Arc::new(DocumentParser::new(index_name, &index_config, &schema)?);
         let consumers = index_config_proxy
             .read()
             .get()
@@ -70,6 +104,7 @@ impl IndexUpdater {
             index,
             index_name: index_name.to_owned(),
             index_writer_holder,
+            document_parser,
         };
         inner_index_updater.start_consumers()?;
         Ok(inner_index_updater)
@@ -97,8 +132,8 @@ impl IndexUpdater {
     fn start_consumers(&mut self) -> SummaResult<()> {
         for consumer in &self.consumers {
             let index_writer_holder = self.index_writer_holder.clone();
-            let fields = self.index.schema();
-            consumer.start(move |message| process_message(&fields, &index_writer_holder, message))?;
+            let document_parser = self.document_parser.clone();
+            consumer.start(move |message| process_message(&document_parser, &index_writer_holder, message))?;
         }
         Ok(())
     }
@@ -106,8 +141,8 @@ impl IndexUpdater {
     /// Add consumer and starts it
     pub(super) fn attach_consumer(&mut self, consumer: Consumer) -> SummaResult<()> {
         let index_writer_holder = self.index_writer_holder.clone();
-        let fields = self.index.schema();
-        consumer.start(move |message| process_message(&fields, &index_writer_holder, message))?;
+        let document_parser = self.document_parser.clone();
+        consumer.start(move |message| process_message(&document_parser, &index_writer_holder, message))?;
         self.consumers.push(consumer);
         Ok(())
     }
@@ -183,25 +218,85 @@ impl IndexUpdater {
 
     /// Index generic `SummaDocument`
     ///
-    /// `IndexUpdater` bounds unbounded `SummaDocument` inside
-    pub(crate) fn index_document(&self, document: SummaDocument<'_>) -> SummaResult<Opstamp> {
-        let document = document.bound_with(&self.index.schema()).try_into()?;
+    /// JSON documents are prepared by `DocumentParser` of the index. Returns `None` if the document is skipped due to its outdated version
+    pub(crate) fn index_document(&self, document: SummaDocument<'_>) -> SummaResult<Option<Opstamp>> {
+        let document = self.document_parser.parse(document)?;
         self.index_writer_holder.index_document(document)
     }
 
+    /// Primary key field if it is set for the index
+    pub(crate) fn primary_key(&self) -> Option<Field> {
+        self.index_writer_holder.primary_key()
+    }
+
+    /// Delete the document with the primary key, see `IndexWriterHolder::delete_document` for details
+    pub(crate) fn delete_document(&self, primary_key_value: &Value) -> SummaResult<Opstamp> {
+        self.index_writer_holder.delete_document(primary_key_value)
+    }
+
+    /// Delete the committed document with the primary key, see `IndexWriterHolder::delete_committed_document` for details
+    pub(crate) fn delete_committed_document(&self, primary_key_value: &Value) -> SummaResult<Option<Opstamp>> {
+        self.index_writer_holder.delete_committed_document(primary_key_value)
+    }
+
+    /// Parse the part of JSON document used for partial updates, see `DocumentParser::parse_partial` for details
+    pub(crate) fn parse_partial_document(&self, partial_document: &[u8]) -> SummaResult<Document> {
+        self.document_parser.parse_partial(partial_document)
+    }
+
+    /// Replace the document with the primary key by its updated version, see `IndexWriterHolder::update_document` for details
+    pub(crate) fn update_document(
+        &self,
+        searcher: &Searcher,
+        primary_key_value: &Value,
+        update: impl FnOnce(Document) -> SummaResult<Document>,
+    ) -> SummaResult<Option<Opstamp>> {
+        self.index_writer_holder.update_document(searcher, primary_key_value, update)
+    }
+
+    /// Extract the primary key from the document that failed to be parsed entirely
+    fn extract_primary_key(&self, format: proto::DocumentFormat, document: &[u8]) -> Option<proto::PrimaryKey> {
+        let schema = self.index.schema();
+        let primary_key = self.primary_key()?;
+        // The primary key may be set by the ingest pipeline, so the raw document is used only if the pipeline fails
+        let mut json_document = self
+            .document_parser
+            .json_object(format, document)
+            .or_else(|_| SummaDocument::parse_json_object_with_format(format, document))
+            .ok()?;
+        let json_value = json_document.remove(schema.get_field_name(primary_key))?;
+        let value = SummaDocument::UnboundBytes((format, document))
+            .value_from_json(schema.get_field_entry(primary_key).field_type(), json_value)
+            .ok()?;
+        value.try_into().ok()
+    }
+
     /// Index multiple documents at a time
-    pub(crate) fn index_bulk(&self, documents: &Vec<Vec<u8>>) -> (u64, u64) {
-        let (mut success_docs, mut failed_docs) = (0u64, 0u64);
+    ///
+    /// Positions of failed documents are counted from the documents already accounted in `result`, so the same
+    /// `result` may be passed for consecutive bulks of a stream
+    pub(crate) fn index_bulk(&self, documents: &[Vec<u8>], format: proto::DocumentFormat, result: &mut IndexBulkResult) {
         for document in documents {
-            match self.index_document(SummaDocument::UnboundJsonBytes(document)) {
-                Ok(_) => success_docs += 1,
+            match self.index_document(SummaDocument::UnboundBytes((format, document))) {
+                Ok(Some(_)) => result.success_docs += 1,
+                Ok(None) => result.skipped_docs += 1,
                 Err(error) => {
                     warn!(action = "error", error = ?error);
-                    failed_docs += 1
+                    if result.errors.len() < MAX_INDEX_BULK_ERRORS {
+                        result.errors.push(proto::IndexDocumentError {
+                            position: result.processed_docs(),
+                            primary_key: self.extract_primary_key(format, document),
+                            field: match &error {
+                                Error::DocumentParsing(DocumentParsingError::ValueError(field, _)) => Some(field.clone()),
+                                _ => None,
+                            },
+                            error: error.to_string(),
+                        });
+                    }
+                    result.failed_docs += 1
                 }
             }
         }
-        (success_docs, failed_docs)
     }
 
     /// Merges multiple segments, see `IndexWriterHolder::merge` for details
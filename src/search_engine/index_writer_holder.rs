@@ -1,14 +1,87 @@
+use crate::configs::VersionConflict;
 use crate::errors::SummaResult;
 use crate::errors::ValidationError;
 use crate::errors::ValidationError::MissingPrimaryKey;
-use tantivy::schema::{Field, FieldType};
-use tantivy::{Document, Index, IndexWriter, Opstamp, SegmentId, SegmentMeta, Term};
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use tantivy::collector::TopDocs;
+use tantivy::fastfield::FastFieldReader;
+use tantivy::query::TermQuery;
+use tantivy::schema::{Cardinality, Field, FieldType, IndexRecordOption, Schema as Fields, Value};
+use tantivy::{Document, Index, IndexReader, IndexWriter, Opstamp, ReloadPolicy, Searcher, SegmentId, SegmentMeta, Term};
 use tracing::info;
 
+/// Checks that the field may be used as a primary key
+///
+/// The primary key must be indexed and may have `I64`, `U64`, `Bytes` or `Str` type. `Str` primary keys must be
+/// indexed with `raw` tokenizer so their terms are equal to their values.
+pub(crate) fn validate_primary_key(fields: &Fields, primary_key: Field) -> SummaResult<()> {
+    let field_entry = fields.get_field_entry(primary_key);
+    match field_entry.field_type() {
+        FieldType::I64(_) | FieldType::U64(_) | FieldType::Bytes(_) if field_entry.is_indexed() => Ok(()),
+        FieldType::Str(text_options)
+            if text_options
+                .get_indexing_options()
+                .map(|text_indexing| text_indexing.tokenizer() == "raw")
+                .unwrap_or(false) =>
+        {
+            Ok(())
+        }
+        another_type => Err(ValidationError::InvalidPrimaryKeyType(another_type.to_owned()).into()),
+    }
+}
+
+/// Checks that the field may be used as a version of documents
+///
+/// The version must be single-valued fast field of `I64` or `U64` type.
+pub(crate) fn validate_version_field(fields: &Fields, version_field: Field) -> SummaResult<()> {
+    let field_entry = fields.get_field_entry(version_field);
+    match field_entry.field_type() {
+        FieldType::I64(options) | FieldType::U64(options) if options.get_fastfield_cardinality() == Some(Cardinality::SingleValue) => Ok(()),
+        another_type => Err(ValidationError::InvalidFastFieldType {
+            field: field_entry.name().to_owned(),
+            field_type: another_type.to_owned(),
+        }
+        .into()),
+    }
+}
+
+fn version_value(value: &Value) -> Option<i128> {
+    match value {
+        Value::I64(value) => Some(*value as i128),
+        Value::U64(value) => Some(*value as i128),
+        _ => None,
+    }
+}
+
+/// Versions of documents checked on indexing
+struct Versioning {
+    version_field: Field,
+    version_conflict: VersionConflict,
+    /// Reader reloaded on every commit, so it sees versions of all committed documents
+    index_reader: IndexReader,
+    /// Versions of deleted documents kept until their primary keys are written again, so replayed outdated documents are not
+    /// indexed after deletion. Tombstones live in memory only and are lost when the index is reopened
+    tombstones: Mutex<HashMap<Term, i128>>,
+}
+
+/// Changes made since the last commit by terms of primary keys
+#[derive(Default)]
+struct UncommittedChanges {
+    /// Updated and then indexed documents, `None` for deleted ones
+    updated_documents: HashMap<Term, Option<Document>>,
+    /// Versions of indexed, updated and deleted documents, `None` if there has been no version for the primary key
+    versions: HashMap<Term, Option<i128>>,
+    /// Primary keys of all documents indexed, updated or deleted
+    written_terms: HashSet<Term>,
+}
+
 /// Managing write operations to index
 pub(super) struct IndexWriterHolder {
     index_writer: IndexWriter,
     primary_key: Option<Field>,
+    versioning: Option<Versioning>,
+    uncommitted_changes: Mutex<UncommittedChanges>,
 }
 
 impl IndexWriterHolder {
@@ -16,15 +89,35 @@ impl IndexWriterHolder {
     ///
     /// `IndexWriterHolder` maintains invariant that the only document with the particular primary key exists in the index.
     /// It is reached by deletion of every document with the same primary key as indexing one.
-    /// The type of primary key is restricted to I64 but it is subjected to be changed in the future.
-    pub(super) fn new(index_writer: IndexWriter, primary_key: Option<Field>) -> SummaResult<IndexWriterHolder> {
-        if let Some(primary_key) = primary_key {
-            match index_writer.index().schema().get_field_entry(primary_key).field_type() {
-                FieldType::I64(_) => Ok(()),
-                another_type => Err(ValidationError::InvalidPrimaryKeyType(another_type.to_owned())),
-            }?
-        }
-        Ok(IndexWriterHolder { index_writer, primary_key })
+    /// The primary key is checked by `validate_primary_key` on the index creation only, so indices created before are still opened.
+    ///
+    /// If `version_field` is set then documents with the version not greater than the version of the already indexed
+    /// document with the same primary key are resolved according to `version_conflict`.
+    pub(super) fn new(
+        index_writer: IndexWriter,
+        primary_key: Option<Field>,
+        version_field: Option<Field>,
+        version_conflict: VersionConflict,
+    ) -> SummaResult<IndexWriterHolder> {
+        let versioning = match version_field {
+            Some(version_field) => {
+                primary_key.ok_or(MissingPrimaryKey(None))?;
+                validate_version_field(&index_writer.index().schema(), version_field)?;
+                Some(Versioning {
+                    version_field,
+                    version_conflict,
+                    index_reader: index_writer.index().reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?,
+                    tombstones: Mutex::new(HashMap::new()),
+                })
+            }
+            None => None,
+        };
+        Ok(IndexWriterHolder {
+            index_writer,
+            primary_key,
+            versioning,
+            uncommitted_changes: Mutex::new(UncommittedChanges::default()),
+        })
     }
 
     /// Tantivy `Index`
@@ -32,19 +125,171 @@ impl IndexWriterHolder {
         self.index_writer.index()
     }
 
+    /// Primary key field if it is set for the index
+    pub(super) fn primary_key(&self) -> Option<Field> {
+        self.primary_key
+    }
+
+    fn primary_key_term(&self, primary_key: Field, primary_key_value: &Value) -> SummaResult<Term> {
+        match (self.index().schema().get_field_entry(primary_key).field_type(), primary_key_value) {
+            (FieldType::I64(_), Value::I64(primary_key_value)) => Ok(Term::from_field_i64(primary_key, *primary_key_value)),
+            (FieldType::U64(_), Value::U64(primary_key_value)) => Ok(Term::from_field_u64(primary_key, *primary_key_value)),
+            (FieldType::Str(_), Value::Str(primary_key_value)) => Ok(Term::from_field_text(primary_key, primary_key_value)),
+            (FieldType::Bytes(_), Value::Bytes(primary_key_value)) => Ok(Term::from_field_bytes(primary_key, primary_key_value)),
+            (_, primary_key_value) => Err(ValidationError::InvalidPrimaryKeyValue(format!("{:?}", primary_key_value)).into()),
+        }
+    }
+
+    /// Version of the committed document with the primary key
+    fn committed_version(versioning: &Versioning, primary_key_term: &Term) -> SummaResult<Option<i128>> {
+        let searcher = versioning.index_reader.searcher();
+        let top_docs = searcher.search(&TermQuery::new(primary_key_term.clone(), IndexRecordOption::Basic), &TopDocs::with_limit(1))?;
+        let doc_address = match top_docs.first() {
+            Some((_, doc_address)) => doc_address,
+            None => return Ok(None),
+        };
+        let fast_fields = searcher.segment_reader(doc_address.segment_ord).fast_fields();
+        Ok(Some(match searcher.schema().get_field_entry(versioning.version_field).field_type() {
+            FieldType::I64(_) => fast_fields.i64(versioning.version_field)?.get(doc_address.doc_id) as i128,
+            _ => fast_fields.u64(versioning.version_field)?.get(doc_address.doc_id) as i128,
+        }))
+    }
+
+    /// Version of the last document written with the primary key
+    ///
+    /// Versions of uncommitted changes take precedence over committed documents and then tombstones of deleted documents
+    fn indexed_version(versioning: &Versioning, uncommitted_changes: &UncommittedChanges, primary_key_term: &Term) -> SummaResult<Option<i128>> {
+        if let Some(indexed_version) = uncommitted_changes.versions.get(primary_key_term) {
+            return Ok(*indexed_version);
+        }
+        Ok(Self::committed_version(versioning, primary_key_term)?.or_else(|| versioning.tombstones.lock().get(primary_key_term).copied()))
+    }
+
+    /// Checks that `document` has version greater than `indexed_version` and records it for the primary key
+    ///
+    /// Returns `false` if the document must be skipped due to its outdated version
+    fn check_version(
+        &self,
+        versioning: &Versioning,
+        uncommitted_changes: &mut UncommittedChanges,
+        primary_key_term: &Term,
+        document: &Document,
+    ) -> SummaResult<bool> {
+        let version = document.get_first(versioning.version_field).and_then(version_value).ok_or_else(|| {
+            let version_field_name = self.index().schema().get_field_name(versioning.version_field).to_owned();
+            ValidationError::EmptyArgument(version_field_name)
+        })?;
+        if let Some(indexed_version) = Self::indexed_version(versioning, uncommitted_changes, primary_key_term)? {
+            if version <= indexed_version {
+                return match versioning.version_conflict {
+                    VersionConflict::Reject => Err(ValidationError::OutdatedVersion { version, indexed_version }.into()),
+                    VersionConflict::Skip => Ok(false),
+                };
+            }
+        }
+        uncommitted_changes.versions.insert(primary_key_term.clone(), Some(version));
+        versioning.tombstones.lock().remove(primary_key_term);
+        Ok(true)
+    }
+
     /// Put document to the index. Before comes searchable it must be committed
-    pub(super) fn index_document(&self, document: Document) -> SummaResult<Opstamp> {
+    ///
+    /// Returns `None` if the document is skipped due to its outdated version
+    pub(super) fn index_document(&self, document: Document) -> SummaResult<Option<Opstamp>> {
         if let Some(primary_key) = self.primary_key {
-            self.index_writer.delete_term(Term::from_field_i64(
-                primary_key,
-                document
-                    .get_first(primary_key)
-                    .ok_or_else(|| MissingPrimaryKey(Some(format!("{:?}", self.index_writer.index().schema().to_named_doc(&document)))))?
-                    .as_i64()
-                    .unwrap(),
-            ));
+            let primary_key_value = document
+                .get_first(primary_key)
+                .ok_or_else(|| MissingPrimaryKey(Some(format!("{:?}", self.index_writer.index().schema().to_named_doc(&document)))))?;
+            let primary_key_term = self.primary_key_term(primary_key, primary_key_value)?;
+            let mut uncommitted_changes = self.uncommitted_changes.lock();
+            if let Some(versioning) = &self.versioning {
+                if !self.check_version(versioning, &mut uncommitted_changes, &primary_key_term, &document)? {
+                    return Ok(None);
+                }
+            }
+            if let Some(updated_document) = uncommitted_changes.updated_documents.get_mut(&primary_key_term) {
+                *updated_document = Some(document.clone());
+            }
+            uncommitted_changes.written_terms.insert(primary_key_term.clone());
+            self.index_writer.delete_term(primary_key_term);
+        }
+        Ok(Some(self.index_writer.add_document(document)?))
+    }
+
+    /// Delete the document with the primary key. Deletion becomes visible after commit
+    ///
+    /// The version of the deleted document is kept as a tombstone, so documents with outdated versions are still skipped or rejected
+    pub(super) fn delete_document(&self, primary_key_value: &Value) -> SummaResult<Opstamp> {
+        let primary_key = self.primary_key.ok_or(MissingPrimaryKey(None))?;
+        let primary_key_term = self.primary_key_term(primary_key, primary_key_value)?;
+        let mut uncommitted_changes = self.uncommitted_changes.lock();
+        uncommitted_changes.updated_documents.insert(primary_key_term.clone(), None);
+        if let Some(versioning) = &self.versioning {
+            if let Some(deleted_version) = Self::indexed_version(versioning, &uncommitted_changes, &primary_key_term)? {
+                versioning.tombstones.lock().insert(primary_key_term.clone(), deleted_version);
+                uncommitted_changes.versions.insert(primary_key_term.clone(), Some(deleted_version));
+            }
         }
-        Ok(self.index_writer.add_document(document)?)
+        uncommitted_changes.written_terms.insert(primary_key_term.clone());
+        Ok(self.index_writer.delete_term(primary_key_term))
+    }
+
+    /// Delete the committed document with the primary key unless the primary key has been written since the last commit
+    ///
+    /// Deletion by the primary key would also delete the document indexed or updated after the last commit, so such
+    /// documents are kept and `None` is returned. Deletion becomes visible after commit
+    pub(super) fn delete_committed_document(&self, primary_key_value: &Value) -> SummaResult<Option<Opstamp>> {
+        let primary_key = self.primary_key.ok_or(MissingPrimaryKey(None))?;
+        let primary_key_term = self.primary_key_term(primary_key, primary_key_value)?;
+        if self.uncommitted_changes.lock().written_terms.contains(&primary_key_term) {
+            return Ok(None);
+        }
+        self.delete_document(primary_key_value).map(Some)
+    }
+
+    /// Replace the document with the primary key by `update` applied to its current version
+    ///
+    /// The current version is taken from documents updated or deleted since the last commit or else is loaded from `searcher`
+    /// that must be reloaded after the last commit. Documents indexed by `index_document` since the last commit are not kept,
+    /// so their updates are rejected until the commit. Updates are serialized, so concurrent updates of the same document are not lost.
+    ///
+    /// If versioning is set then the updated document must have the version greater than the current one like indexed documents,
+    /// so `None` is returned if the update is skipped due to its outdated version
+    pub(super) fn update_document(
+        &self,
+        searcher: &Searcher,
+        primary_key_value: &Value,
+        update: impl FnOnce(Document) -> SummaResult<Document>,
+    ) -> SummaResult<Option<Opstamp>> {
+        let primary_key = self.primary_key.ok_or(MissingPrimaryKey(None))?;
+        let primary_key_term = self.primary_key_term(primary_key, primary_key_value)?;
+        let mut uncommitted_changes = self.uncommitted_changes.lock();
+        let current_document = match uncommitted_changes.updated_documents.get(&primary_key_term) {
+            Some(updated_document) => updated_document.clone(),
+            None if uncommitted_changes.written_terms.contains(&primary_key_term) => {
+                return Err(ValidationError::UncommittedDocument(format!("{:?}", primary_key_value)).into())
+            }
+            None => searcher
+                .search(&TermQuery::new(primary_key_term.clone(), IndexRecordOption::Basic), &TopDocs::with_limit(1))?
+                .first()
+                .map(|(_, doc_address)| searcher.doc(*doc_address))
+                .transpose()?,
+        }
+        .ok_or_else(|| ValidationError::MissingDocument(format!("{:?}", primary_key_value)))?;
+        let document = update(current_document)?;
+        if document.get_first(primary_key) != Some(primary_key_value) {
+            return Err(ValidationError::InvalidPrimaryKeyValue(format!("{:?}", document.get_first(primary_key))).into());
+        }
+        if let Some(versioning) = &self.versioning {
+            if !self.check_version(versioning, &mut uncommitted_changes, &primary_key_term, &document)? {
+                return Ok(None);
+            }
+        }
+        uncommitted_changes.written_terms.insert(primary_key_term.clone());
+        self.index_writer.delete_term(primary_key_term.clone());
+        let opstamp = self.index_writer.add_document(document.clone())?;
+        uncommitted_changes.updated_documents.insert(primary_key_term, Some(document));
+        Ok(Some(opstamp))
     }
 
     /// Merge segments into one.
@@ -60,12 +305,17 @@ impl IndexWriterHolder {
 
     /// Commits already indexed documents
     ///
-    /// Committing makes indexed documents visible
+    /// Committing makes indexed documents visible, uncommitted changes are kept for the next commit if this one fails
     /// It is heavy operation that also blocks on `.await` so should be spawned if non-blocking behaviour is required
     pub(super) async fn commit(&mut self) -> SummaResult<Opstamp> {
         info!(action = "commit_index");
         let result = self.index_writer.prepare_commit()?.commit_future().await;
         info!(action = "committed_index", result = ?result);
-        Ok(result?)
+        let opstamp = result?;
+        *self.uncommitted_changes.get_mut() = UncommittedChanges::default();
+        if let Some(versioning) = &self.versioning {
+            versioning.index_reader.reload()?;
+        }
+        Ok(opstamp)
     }
 }
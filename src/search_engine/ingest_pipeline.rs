@@ -0,0 +1,394 @@
+use super::scorers::{call_function, rewrite_function_named_variables, variable_source_name, FUNCTION_NAMES};
+use super::{DocumentParsingError, ValueParsingError};
+use crate::configs::IngestStep;
+use crate::errors::{Error, SummaResult, ValidationError};
+use fasteval2::{Compiler, Evaler, Instruction};
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tantivy::schema::{FieldType, Schema as Fields};
+use time::format_description::well_known::Rfc3339;
+use time::format_description::{Component, FormatItem};
+use time::{format_description, Date, OffsetDateTime, PrimitiveDateTime};
+
+/// Compiled expression of `IngestStep::Expression`
+struct Expression {
+    slab: fasteval2::Slab,
+    compiled: Instruction,
+    var_names: Vec<String>,
+    field_type: FieldType,
+}
+
+// `Instruction` may hold pointers to unsafe variables, `Expression` never registers them and resolves all variables through the namespace
+unsafe impl Send for Expression {}
+unsafe impl Sync for Expression {}
+
+fn json_to_f64(json_value: &JsonValue) -> Option<f64> {
+    match json_value {
+        JsonValue::Number(number) => number.as_f64(),
+        JsonValue::String(text) => text.parse().ok(),
+        JsonValue::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        JsonValue::Array(items) => items.first().and_then(json_to_f64),
+        _ => None,
+    }
+}
+
+impl Expression {
+    fn new(field_name: &str, expression: &str, fields: &Fields) -> SummaResult<Expression> {
+        let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+        let field_type = fields.get_field_entry(field).field_type().clone();
+        if !matches!(field_type, FieldType::I64(_) | FieldType::U64(_) | FieldType::F64(_)) {
+            return Err(Error::InvalidFieldType(field_name.to_owned(), field_type));
+        }
+        let expression = rewrite_function_named_variables(expression);
+        let parser = fasteval2::Parser::new();
+        let mut slab = fasteval2::Slab::new();
+        let parsed = parser.parse(&expression, &mut slab.ps)?.from(&slab.ps);
+        let var_names = parsed
+            .var_names(&slab)
+            .into_iter()
+            .filter(|var_name| var_name != "now" && !FUNCTION_NAMES.contains(&var_name.as_str()))
+            .collect();
+        let compiled = parser
+            .parse(&expression, &mut slab.ps)?
+            .from(&slab.ps)
+            .compile(&slab.ps, &mut slab.cs, &mut |name: &str, args: Vec<f64>| -> Option<f64> {
+                call_function(name, &args)
+            });
+        Ok(Expression {
+            slab,
+            compiled,
+            var_names,
+            field_type,
+        })
+    }
+
+    /// Evaluates the expression with variables bound to values of `json_object`, missing values are treated as zeroes
+    fn eval(&self, field_name: &str, json_object: &JsonMap<String, JsonValue>) -> SummaResult<JsonValue> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let mut namespace = |name: &str, args: Vec<f64>| -> Option<f64> {
+            match name {
+                "now" => Some(now),
+                name if self.var_names.iter().any(|var_name| var_name == name) => {
+                    Some(json_object.get(variable_source_name(name)).and_then(json_to_f64).unwrap_or(0.0))
+                }
+                name => call_function(name, &args),
+            }
+        };
+        let value = self.compiled.eval(&self.slab, &mut namespace)?;
+        let type_error = |expected: &'static str| {
+            DocumentParsingError::ValueError(
+                field_name.to_owned(),
+                ValueParsingError::TypeError {
+                    expected,
+                    json: JsonValue::String(value.to_string()),
+                },
+            )
+        };
+        // Casts of floats saturate and turn NaN into zero, so values out of range of integer fields are rejected instead
+        Ok(match self.field_type {
+            FieldType::I64(_) if value >= i64::MIN as f64 && value < i64::MAX as f64 => JsonValue::from(value as i64),
+            FieldType::I64(_) => return Err(type_error("a number in range of i64").into()),
+            FieldType::U64(_) if value > -1.0 && value < u64::MAX as f64 => JsonValue::from(value as u64),
+            FieldType::U64(_) => return Err(type_error("a number in range of u64").into()),
+            _ => JsonValue::Number(JsonNumber::from_f64(value).ok_or_else(|| type_error("a finite number"))?),
+        })
+    }
+}
+
+/// Item of `DateFormat`
+enum DateFormatItem {
+    Literal(Vec<u8>),
+    Component(Component),
+}
+
+/// Date format of `IngestStep::ParseDate` parsed once on creation of `IngestPipeline`
+///
+/// `format_description::parse` returns items borrowing the format string, so they are kept owned and borrowed back on parsing dates
+struct DateFormat(Vec<DateFormatItem>);
+
+impl DateFormat {
+    fn parse(format: &str) -> SummaResult<DateFormat> {
+        let invalid_format = || Error::InvalidSyntax(format!("invalid date format: {}", format));
+        Ok(DateFormat(
+            format_description::parse(format)
+                .map_err(|_| invalid_format())?
+                .into_iter()
+                .map(|format_item| match format_item {
+                    FormatItem::Literal(literal) => Ok(DateFormatItem::Literal(literal.to_vec())),
+                    FormatItem::Component(component) => Ok(DateFormatItem::Component(component)),
+                    _ => Err(invalid_format()),
+                })
+                .collect::<SummaResult<_>>()?,
+        ))
+    }
+
+    fn format_items(&self) -> Vec<FormatItem<'_>> {
+        self.0
+            .iter()
+            .map(|date_format_item| match date_format_item {
+                DateFormatItem::Literal(literal) => FormatItem::Literal(literal),
+                DateFormatItem::Component(component) => FormatItem::Component(*component),
+            })
+            .collect()
+    }
+}
+
+/// Parses `text` with the first matching of `formats`
+fn parse_date(text: &str, formats: &[DateFormat]) -> Option<OffsetDateTime> {
+    formats.iter().find_map(|format| {
+        let format = format.format_items();
+        OffsetDateTime::parse(text, &format)
+            .or_else(|_| PrimitiveDateTime::parse(text, &format).map(PrimitiveDateTime::assume_utc))
+            .or_else(|_| Date::parse(text, &format).map(|date| date.midnight().assume_utc()))
+            .ok()
+    })
+}
+
+/// Applies `transform` to the string or to every string of the array
+fn transform_strings(json_value: &mut JsonValue, transform: impl Fn(&str) -> String) {
+    match json_value {
+        JsonValue::String(text) => *text = transform(text),
+        JsonValue::Array(items) => {
+            for item in items {
+                if let JsonValue::String(text) = item {
+                    *text = transform(text)
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Prepared `IngestStep`
+enum Step {
+    Configured(IngestStep),
+    Expression { field: String, expression: Box<Expression> },
+    ParseDate { field: String, formats: Vec<DateFormat> },
+}
+
+/// Transforms JSON documents before their conversion into documents of the index
+///
+/// Steps are applied in order, and all steps except `SetDefault` skip documents that have no the field.
+pub struct IngestPipeline {
+    fields: Fields,
+    steps: Vec<Step>,
+}
+
+impl IngestPipeline {
+    /// Creates `IngestPipeline` parsing date formats, compiling expressions and validating separators of `ingest_steps`
+    pub fn new(ingest_steps: &[IngestStep], fields: &Fields) -> SummaResult<IngestPipeline> {
+        let steps = ingest_steps
+            .iter()
+            .map(|ingest_step| match ingest_step {
+                IngestStep::Expression { field, expression } => Ok(Step::Expression {
+                    field: field.to_owned(),
+                    expression: Box::new(Expression::new(field, expression, fields)?),
+                }),
+                IngestStep::ParseDate { field, formats } => Ok(Step::ParseDate {
+                    field: field.to_owned(),
+                    formats: formats.iter().map(|format| DateFormat::parse(format)).collect::<SummaResult<_>>()?,
+                }),
+                IngestStep::Split { separator, .. } if separator.is_empty() => Err(ValidationError::EmptyArgument("separator".to_owned()).into()),
+                ingest_step => Ok(Step::Configured(ingest_step.clone())),
+            })
+            .collect::<SummaResult<_>>()?;
+        Ok(IngestPipeline { fields: fields.clone(), steps })
+    }
+
+    /// Whether the pipeline has no steps, so documents may skip it
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Transforms `json_object` in place
+    pub fn apply(&self, json_object: &mut JsonMap<String, JsonValue>) -> SummaResult<()> {
+        self.apply_steps(json_object, false)
+    }
+
+    /// Transforms `json_object` that is a part of the document, i.e. of partial updates, in place
+    ///
+    /// `SetDefault` and `Expression` steps are skipped as they would overwrite values of fields absent in the part
+    pub fn apply_partial(&self, json_object: &mut JsonMap<String, JsonValue>) -> SummaResult<()> {
+        self.apply_steps(json_object, true)
+    }
+
+    fn apply_steps(&self, json_object: &mut JsonMap<String, JsonValue>, is_partial: bool) -> SummaResult<()> {
+        for step in &self.steps {
+            match step {
+                Step::Configured(IngestStep::SetDefault { .. }) | Step::Expression { .. } if is_partial => (),
+                Step::Configured(IngestStep::Copy { from, to }) => {
+                    if let Some(json_value) = json_object.get(from).cloned() {
+                        json_object.insert(to.to_owned(), json_value);
+                    }
+                }
+                Step::Configured(IngestStep::SetDefault { field, value }) => {
+                    if json_object.get(field).map(JsonValue::is_null).unwrap_or(true) {
+                        json_object.insert(field.to_owned(), value.clone());
+                    }
+                }
+                Step::Configured(IngestStep::Rename { from, to }) => {
+                    if let Some(json_value) = json_object.remove(from) {
+                        json_object.insert(to.to_owned(), json_value);
+                    }
+                }
+                Step::ParseDate { field, formats } => {
+                    if let Some(JsonValue::String(text)) = json_object.get_mut(field) {
+                        let date = parse_date(text, formats).ok_or_else(|| {
+                            DocumentParsingError::ValueError(
+                                field.to_owned(),
+                                ValueParsingError::TypeError {
+                                    expected: "a date in one of configured formats",
+                                    json: JsonValue::String(text.to_owned()),
+                                },
+                            )
+                        })?;
+                        *text = date.format(&Rfc3339).map_err(|_| Error::Internal)?;
+                    }
+                }
+                Step::Configured(IngestStep::Lowercase { field }) => {
+                    if let Some(json_value) = json_object.get_mut(field) {
+                        transform_strings(json_value, str::to_lowercase)
+                    }
+                }
+                Step::Configured(IngestStep::Trim { field }) => {
+                    if let Some(json_value) = json_object.get_mut(field) {
+                        transform_strings(json_value, |text| text.trim().to_owned())
+                    }
+                }
+                Step::Configured(IngestStep::Split { field, separator }) => {
+                    if let Some(json_value) = json_object.get_mut(field) {
+                        if let JsonValue::String(text) = json_value {
+                            *json_value = text
+                                .split(separator.as_str())
+                                .filter(|part| !part.is_empty())
+                                .map(|part| JsonValue::String(part.to_owned()))
+                                .collect();
+                        }
+                    }
+                }
+                Step::Configured(IngestStep::DropUnknown) => json_object.retain(|field_name, _| self.fields.get_field(field_name).is_some()),
+                Step::Configured(IngestStep::Expression { .. } | IngestStep::ParseDate { .. }) => unreachable!(),
+                Step::Expression { field, expression } => {
+                    let json_value = expression.eval(field, json_object)?;
+                    json_object.insert(field.to_owned(), json_value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tantivy::schema::{FAST, STORED, TEXT};
+
+    #[test]
+    fn test_ingest_pipeline() {
+        let mut fields_builder = Fields::builder();
+        fields_builder.add_text_field("title", TEXT | STORED);
+        fields_builder.add_text_field("tags", TEXT | STORED);
+        fields_builder.add_text_field("language", TEXT | STORED);
+        fields_builder.add_date_field("issued_at", FAST | STORED);
+        fields_builder.add_i64_field("score", FAST | STORED);
+        let fields = fields_builder.build();
+
+        let ingest_pipeline = IngestPipeline::new(
+            &[
+                IngestStep::Rename {
+                    from: "name".to_owned(),
+                    to: "title".to_owned(),
+                },
+                IngestStep::Trim { field: "title".to_owned() },
+                IngestStep::Copy {
+                    from: "title".to_owned(),
+                    to: "tags".to_owned(),
+                },
+                IngestStep::Lowercase { field: "tags".to_owned() },
+                IngestStep::Split {
+                    field: "tags".to_owned(),
+                    separator: " ".to_owned(),
+                },
+                IngestStep::SetDefault {
+                    field: "language".to_owned(),
+                    value: json!("en"),
+                },
+                IngestStep::ParseDate {
+                    field: "issued_at".to_owned(),
+                    formats: vec!["[day].[month].[year] [hour]:[minute]".to_owned(), "[day].[month].[year]".to_owned()],
+                },
+                IngestStep::Expression {
+                    field: "score".to_owned(),
+                    expression: "citations * 2 + max(likes, 1)".to_owned(),
+                },
+                IngestStep::DropUnknown,
+            ],
+            &fields,
+        )
+        .unwrap();
+
+        let apply = |json_value: JsonValue| {
+            let mut json_object = json_value.as_object().unwrap().clone();
+            ingest_pipeline.apply(&mut json_object).map(|_| JsonValue::Object(json_object))
+        };
+        assert_eq!(
+            apply(json!({"name": " Quick Brown Fox ", "issued_at": "02.01.2022 10:30", "citations": "3", "extra": 1})).unwrap(),
+            json!({
+                "title": "Quick Brown Fox",
+                "tags": ["quick", "brown", "fox"],
+                "language": "en",
+                "issued_at": "2022-01-02T10:30:00Z",
+                "score": 7,
+            })
+        );
+        assert_eq!(
+            apply(json!({"language": "ru", "issued_at": "02.01.2022", "likes": 5})).unwrap(),
+            json!({"language": "ru", "issued_at": "2022-01-02T00:00:00Z", "score": 5})
+        );
+        assert!(apply(json!({"issued_at": "2022-01-02"})).is_err());
+
+        assert!(IngestPipeline::new(
+            &[IngestStep::ParseDate {
+                field: "issued_at".to_owned(),
+                formats: vec!["[unknown]".to_owned()],
+            }],
+            &fields
+        )
+        .is_err());
+        assert!(IngestPipeline::new(
+            &[IngestStep::Expression {
+                field: "title".to_owned(),
+                expression: "1".to_owned(),
+            }],
+            &fields
+        )
+        .is_err());
+        assert!(IngestPipeline::new(
+            &[IngestStep::Split {
+                field: "tags".to_owned(),
+                separator: "".to_owned(),
+            }],
+            &fields
+        )
+        .is_err());
+
+        let ingest_pipeline = IngestPipeline::new(
+            &[IngestStep::Expression {
+                field: "score".to_owned(),
+                expression: "if(gt(citations, 0), citations, gt) - 10".to_owned(),
+            }],
+            &fields,
+        )
+        .unwrap();
+        let apply = |json_value: JsonValue| {
+            let mut json_object = json_value.as_object().unwrap().clone();
+            ingest_pipeline.apply(&mut json_object)
+        };
+        assert!(apply(json!({"citations": 1})).is_ok());
+        assert!(apply(json!({"citations": 1e30})).is_err());
+        let mut json_object = json!({"gt": 15}).as_object().unwrap().clone();
+        ingest_pipeline.apply(&mut json_object).unwrap();
+        assert_eq!(json_object.get("score"), Some(&json!(5)));
+    }
+}
@@ -3,15 +3,28 @@
 pub mod collectors;
 mod custom_serializer;
 mod default_tokenizers;
+mod diversification;
+mod document_parser;
+mod expiration;
 mod fruit_extractors;
 pub(crate) mod index_holder;
 mod index_updater;
 mod index_writer_holder;
+mod ingest_pipeline;
+pub mod queries;
 mod query_parser;
+mod rank_fusion;
+mod reindex;
 pub mod scorers;
+pub mod similarity;
 mod summa_document;
 mod summa_tokenizer;
 
-pub(crate) use index_holder::IndexHolder;
-pub(crate) use index_updater::IndexUpdater;
-pub use summa_document::{DocumentParsingError, SummaDocument};
+pub(crate) use document_parser::{validate_vector_field, DocumentParser};
+pub(crate) use expiration::validate_expiration;
+pub(crate) use index_holder::{extend_fields, IndexHolder};
+pub(crate) use index_updater::{IndexBulkResult, IndexUpdater};
+pub(crate) use index_writer_holder::{validate_primary_key, validate_version_field};
+pub(crate) use ingest_pipeline::IngestPipeline;
+pub(crate) use reindex::reindex_segment;
+pub use summa_document::{DocumentParsingError, SummaDocument, ValueParsingError};
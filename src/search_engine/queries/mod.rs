@@ -0,0 +1,5 @@
+//! Custom queries that are not available in Tantivy
+
+mod vector_query;
+
+pub use vector_query::{encode_vector, VectorMetric, VectorQuery};
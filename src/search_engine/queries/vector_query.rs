@@ -0,0 +1,243 @@
+use crate::proto;
+use std::collections::HashMap;
+use tantivy::query::{Explanation, Query, Scorer, Weight};
+use tantivy::schema::Field;
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentId, SegmentReader, TERMINATED};
+
+/// Encodes the vector into bytes of the vector field as little-endian `f32` values
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VectorMetric {
+    /// Cosine of the angle between vectors
+    Cosine,
+    /// Dot product of vectors
+    Dot,
+    /// `1 / (1 + d^2)` where `d` is the Euclidean distance between vectors
+    L2,
+}
+
+impl From<proto::VectorMetric> for VectorMetric {
+    fn from(vector_metric: proto::VectorMetric) -> Self {
+        match vector_metric {
+            proto::VectorMetric::Cosine => VectorMetric::Cosine,
+            proto::VectorMetric::Dot => VectorMetric::Dot,
+            proto::VectorMetric::L2 => VectorMetric::L2,
+        }
+    }
+}
+
+impl VectorMetric {
+    /// Scores the encoded vector, returns `None` if it has a different dimension
+    fn score(&self, query_vector: &[f32], query_norm: f32, bytes: &[u8]) -> Option<Score> {
+        if bytes.len() != query_vector.len() * 4 {
+            return None;
+        }
+        let (mut dot, mut norm, mut squared_distance) = (0f32, 0f32, 0f32);
+        for (query_value, value) in query_vector.iter().zip(decode_vector(bytes)) {
+            dot += query_value * value;
+            norm += value * value;
+            squared_distance += (query_value - value) * (query_value - value);
+        }
+        Some(match self {
+            VectorMetric::Cosine if query_norm == 0.0 || norm == 0.0 => 0.0,
+            VectorMetric::Cosine => dot / (query_norm * norm.sqrt()),
+            VectorMetric::Dot => dot,
+            VectorMetric::L2 => 1.0 / (1.0 + squared_distance),
+        })
+    }
+}
+
+/// Matches `k` documents having vectors that are the most similar to the query vector
+///
+/// Vectors are stored in bytes fast field as little-endian `f32` values and scanned exhaustively.
+/// Only documents matching `filter` are considered, documents having vectors of other dimensions are skipped.
+///
+/// ```rust
+/// use summa::search_engine::queries::{encode_vector, VectorMetric, VectorQuery};
+/// use tantivy::collector::TopDocs;
+/// use tantivy::schema::{BytesOptions, Schema};
+/// use tantivy::{doc, DocAddress, Index};
+///
+/// let mut schema_builder = Schema::builder();
+/// let embedding = schema_builder.add_bytes_field("embedding", BytesOptions::default().set_fast());
+/// let schema = schema_builder.build();
+/// let index = Index::create_in_ram(schema);
+///
+/// let mut index_writer = index.writer(3_000_000).unwrap();
+/// index_writer.add_document(doc!(embedding => encode_vector(&[1.0, 0.0]))).unwrap();
+/// index_writer.add_document(doc!(embedding => encode_vector(&[0.6, 0.8]))).unwrap();
+/// index_writer.add_document(doc!(embedding => encode_vector(&[0.0, 1.0]))).unwrap();
+/// assert!(index_writer.commit().is_ok());
+///
+/// let reader = index.reader().unwrap();
+/// let searcher = reader.searcher();
+///
+/// // Here comes the important part
+/// let vector_query = VectorQuery::new(embedding, vec![0.0, 2.0], 2, VectorMetric::Cosine);
+/// let top_docs = searcher.search(&vector_query, &TopDocs::with_limit(10)).unwrap();
+///
+/// assert_eq!(top_docs, vec![(1.0, DocAddress::new(0, 2)), (0.8, DocAddress::new(0, 1))]);
+/// ```
+#[derive(Debug)]
+pub struct VectorQuery {
+    field: Field,
+    vector: Vec<f32>,
+    k: usize,
+    metric: VectorMetric,
+    filter: Option<Box<dyn Query>>,
+}
+
+impl Clone for VectorQuery {
+    fn clone(&self) -> Self {
+        VectorQuery {
+            field: self.field,
+            vector: self.vector.clone(),
+            k: self.k,
+            metric: self.metric,
+            filter: self.filter.as_ref().map(|filter| filter.box_clone()),
+        }
+    }
+}
+
+impl VectorQuery {
+    pub fn new(field: Field, vector: Vec<f32>, k: usize, metric: VectorMetric) -> VectorQuery {
+        VectorQuery {
+            field,
+            vector,
+            k,
+            metric,
+            filter: None,
+        }
+    }
+
+    /// Restricts nearest neighbours to documents matching `filter`
+    pub fn with_filter(self, filter: Box<dyn Query>) -> VectorQuery {
+        VectorQuery { filter: Some(filter), ..self }
+    }
+
+    /// Scores documents of the segment matching the filter and keeps at most `k` best of them in `candidates`
+    fn scan_segment(
+        &self,
+        segment_ord: usize,
+        segment_reader: &SegmentReader,
+        filter_weight: Option<&dyn Weight>,
+        query_norm: f32,
+        candidates: &mut Vec<(Score, usize, DocId)>,
+    ) -> tantivy::Result<()> {
+        let vectors = segment_reader.fast_fields().bytes(self.field)?;
+        let mut consider = |doc: DocId| {
+            if let Some(score) = self.metric.score(&self.vector, query_norm, vectors.get_bytes(doc)) {
+                candidates.push((score, segment_ord, doc));
+                if candidates.len() >= 2 * self.k {
+                    truncate_candidates(candidates, self.k);
+                }
+            }
+        };
+        match filter_weight {
+            Some(filter_weight) => {
+                let mut filter_scorer = filter_weight.scorer(segment_reader, 1.0)?;
+                let mut doc = filter_scorer.doc();
+                while doc != TERMINATED {
+                    if !segment_reader.is_deleted(doc) {
+                        consider(doc);
+                    }
+                    doc = filter_scorer.advance();
+                }
+            }
+            None => {
+                for doc in 0..segment_reader.max_doc() {
+                    if !segment_reader.is_deleted(doc) {
+                        consider(doc);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sorts candidates by descending score and keeps `k` best of them
+fn truncate_candidates(candidates: &mut Vec<(Score, usize, DocId)>, k: usize) {
+    candidates.sort_by(|left, right| right.0.total_cmp(&left.0).then_with(|| (left.1, left.2).cmp(&(right.1, right.2))));
+    candidates.truncate(k);
+}
+
+impl Query for VectorQuery {
+    /// Nearest neighbours are found across all segments here, so weight only replays them segment by segment
+    fn weight(&self, searcher: &Searcher, _scoring_enabled: bool) -> tantivy::Result<Box<dyn Weight>> {
+        let filter_weight = self.filter.as_ref().map(|filter| filter.weight(searcher, false)).transpose()?;
+        let query_norm = self.vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+        let mut candidates = vec![];
+        for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+            self.scan_segment(segment_ord, segment_reader, filter_weight.as_deref(), query_norm, &mut candidates)?;
+        }
+        truncate_candidates(&mut candidates, self.k);
+        let mut segment_docs: HashMap<SegmentId, Vec<(DocId, Score)>> = HashMap::new();
+        for (score, segment_ord, doc) in candidates {
+            segment_docs
+                .entry(searcher.segment_reader(segment_ord as u32).segment_id())
+                .or_default()
+                .push((doc, score));
+        }
+        for docs in segment_docs.values_mut() {
+            docs.sort_by_key(|(doc, _)| *doc);
+        }
+        Ok(Box::new(VectorWeight { segment_docs }))
+    }
+}
+
+struct VectorWeight {
+    segment_docs: HashMap<SegmentId, Vec<(DocId, Score)>>,
+}
+
+impl Weight for VectorWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(Box::new(VectorScorer {
+            docs: self.segment_docs.get(&reader.segment_id()).cloned().unwrap_or_default(),
+            cursor: 0,
+            boost,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!("Document #({}) does not match", doc)));
+        }
+        Ok(Explanation::new("VectorQuery", scorer.score()))
+    }
+}
+
+struct VectorScorer {
+    docs: Vec<(DocId, Score)>,
+    cursor: usize,
+    boost: Score,
+}
+
+impl DocSet for VectorScorer {
+    fn advance(&mut self) -> DocId {
+        self.cursor += 1;
+        self.doc()
+    }
+
+    fn doc(&self) -> DocId {
+        self.docs.get(self.cursor).map(|(doc, _)| *doc).unwrap_or(TERMINATED)
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.docs.len() as u32
+    }
+}
+
+impl Scorer for VectorScorer {
+    fn score(&mut self) -> Score {
+        self.boost * self.docs[self.cursor].1
+    }
+}
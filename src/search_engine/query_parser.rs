@@ -1,13 +1,17 @@
-use crate::errors::{Error, SummaResult};
+use crate::configs::Similarity;
+use crate::errors::{Error, SummaResult, ValidationError};
 use crate::metrics::ToLabel;
 use crate::proto;
+use crate::search_engine::queries::VectorQuery;
+use crate::search_engine::similarity::apply_similarities;
 use opentelemetry::metrics::Counter;
 use opentelemetry::{global, KeyValue};
+use std::collections::HashMap;
 use std::ops::Bound;
 use std::ops::Bound::Unbounded;
 use std::str::FromStr;
 use tantivy::query::{AllQuery, BooleanQuery, BoostQuery, MoreLikeThisQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery, TermQuery};
-use tantivy::schema::{Field, FieldEntry, FieldType, IndexRecordOption, Schema as Fields};
+use tantivy::schema::{Facet, Field, FieldEntry, FieldType, IndexRecordOption, Schema as Fields};
 use tantivy::{DateTime, Index, Term};
 
 /// Responsible for casting `crate::proto::Query` message to `tantivy::query::Query`
@@ -16,6 +20,7 @@ pub struct QueryParser {
     index: Index,
     index_name: String,
     nested_query_parser: tantivy::query::QueryParser,
+    similarities: HashMap<Field, Similarity>,
     // Counters
     query_counter: Counter<u64>,
     subquery_counter: Counter<u64>,
@@ -40,6 +45,10 @@ fn cast_value_to_term(field: Field, field_type: &FieldType, value: &str) -> Summ
             field,
             &base64::decode(value).map_err(|_e| Error::InvalidSyntax(format!("cannot parse {} as bytes", value)))?,
         ),
+        FieldType::Facet(_) => Term::from_facet(
+            field,
+            &Facet::from_text(value).map_err(|_e| Error::InvalidSyntax(format!("cannot parse {} as facet", value)))?,
+        ),
         FieldType::Date(_) => Term::from_field_date(
             field,
             DateTime::from_unix_timestamp(i64::from_str(value).map_err(|_e| Error::InvalidSyntax(format!("cannot parse {} as date", value)))?),
@@ -76,11 +85,17 @@ impl QueryParser {
             index: index.clone(),
             index_name: index_name.to_owned(),
             nested_query_parser,
+            similarities: HashMap::new(),
             query_counter,
             subquery_counter,
         }
     }
 
+    /// Scores `MatchQuery`, `TermQuery` and `PhraseQuery` over the fields with `similarities` instead of the default BM25
+    pub fn with_similarities(self, similarities: HashMap<Field, Similarity>) -> QueryParser {
+        QueryParser { similarities, ..self }
+    }
+
     #[inline]
     pub(crate) fn field_and_field_entry(&self, field_name: &str) -> SummaResult<(Field, &FieldEntry)> {
         let field = self
@@ -116,7 +131,7 @@ impl QueryParser {
                 Box::new(BooleanQuery::new(subqueries))
             }
             Some(proto::query::Query::Match(match_query_proto)) => match self.nested_query_parser.parse_query(&match_query_proto.value) {
-                Ok(parsed_query) => Ok(parsed_query),
+                Ok(parsed_query) => Ok(apply_similarities(parsed_query, &self.similarities)),
                 Err(tantivy::query::QueryParserError::FieldDoesNotExist(field)) => Err(Error::FieldDoesNotExist(field)),
                 Err(e) => Err(Error::InvalidTantivySyntax(e, match_query_proto.value.to_owned())),
             }?,
@@ -143,7 +158,7 @@ impl QueryParser {
                 while let Some(token) = token_stream.next() {
                     terms.push(cast_value_to_term(field, field_entry.field_type(), &token.text)?)
                 }
-                if terms.len() == 1 {
+                let query: Box<dyn Query> = if terms.len() == 1 {
                     Box::new(TermQuery::new(
                         terms[0].clone(),
                         field_entry.field_type().index_record_option().unwrap_or(IndexRecordOption::Basic),
@@ -152,14 +167,18 @@ impl QueryParser {
                     let mut phrase_query = PhraseQuery::new(terms);
                     phrase_query.set_slop(phrase_query_proto.slop);
                     Box::new(phrase_query)
-                }
+                };
+                apply_similarities(query, &self.similarities)
             }
             Some(proto::query::Query::Term(term_query_proto)) => {
                 let (field, field_entry) = self.field_and_field_entry(&term_query_proto.field)?;
-                Box::new(TermQuery::new(
-                    cast_value_to_term(field, field_entry.field_type(), &term_query_proto.value)?,
-                    field_entry.field_type().index_record_option().unwrap_or(IndexRecordOption::Basic),
-                ))
+                apply_similarities(
+                    Box::new(TermQuery::new(
+                        cast_value_to_term(field, field_entry.field_type(), &term_query_proto.value)?,
+                        field_entry.field_type().index_record_option().unwrap_or(IndexRecordOption::Basic),
+                    )),
+                    &self.similarities,
+                )
             }
             Some(proto::query::Query::MoreLikeThis(more_like_this_query_proto)) => {
                 let document = self
@@ -197,6 +216,38 @@ impl QueryParser {
                 query_builder = query_builder.with_stop_words(more_like_this_query_proto.stop_words.clone());
                 Box::new(query_builder.with_document_fields(field_values))
             }
+            Some(proto::query::Query::Vector(vector_query_proto)) => {
+                let (field, field_entry) = self.field_and_field_entry(&vector_query_proto.field)?;
+                match field_entry.field_type() {
+                    FieldType::Bytes(bytes_options) if bytes_options.is_fast() => (),
+                    field_type @ FieldType::Bytes(_) => {
+                        return Err(ValidationError::InvalidFastFieldType {
+                            field: vector_query_proto.field.to_owned(),
+                            field_type: field_type.to_owned(),
+                        }
+                        .into())
+                    }
+                    field_type => return Err(Error::InvalidFieldType(vector_query_proto.field.to_owned(), field_type.to_owned())),
+                }
+                if vector_query_proto.vector.is_empty() {
+                    return Err(ValidationError::EmptyArgument("vector".to_owned()).into());
+                }
+                if vector_query_proto.k == 0 {
+                    return Err(ValidationError::EmptyArgument("k".to_owned()).into());
+                }
+                let vector_query = VectorQuery::new(
+                    field,
+                    vector_query_proto.vector.clone(),
+                    vector_query_proto.k.try_into().unwrap(),
+                    proto::VectorMetric::from_i32(vector_query_proto.metric)
+                        .unwrap_or(proto::VectorMetric::Cosine)
+                        .into(),
+                );
+                match vector_query_proto.filter {
+                    Some(ref filter) => Box::new(vector_query.with_filter(self.parse_subquery(filter)?)),
+                    None => Box::new(vector_query),
+                }
+            }
         })
     }
 
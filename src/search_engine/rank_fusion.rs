@@ -0,0 +1,131 @@
+use crate::errors::{Error, SummaResult};
+use crate::proto;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use tantivy::DocAddress;
+
+/// Merges ranked lists of documents retrieved for the same request by different retrievers
+#[derive(Clone, Debug, PartialEq)]
+pub enum RankFusion {
+    /// Documents are scored by `sum(1 / (k + rank))` over lists where `rank` starts from 1
+    Reciprocal { k: f64 },
+    /// Scores of every list are min-max normalized and then summed with weights of lists
+    Weighted { weights: Vec<f64> },
+}
+
+impl TryFrom<proto::hybrid_collector::Fusion> for RankFusion {
+    type Error = Error;
+
+    fn try_from(fusion: proto::hybrid_collector::Fusion) -> SummaResult<Self> {
+        Ok(match fusion {
+            proto::hybrid_collector::Fusion::Rrf(reciprocal_rank_fusion) => RankFusion::reciprocal(reciprocal_rank_fusion.k.map(f64::from).unwrap_or(60.0))?,
+            proto::hybrid_collector::Fusion::Weighted(weighted_score_fusion) => {
+                let weights: Vec<f64> = [weighted_score_fusion.lexical_weight, weighted_score_fusion.vector_weight]
+                    .into_iter()
+                    .map(|weight| weight.map(f64::from).unwrap_or(1.0))
+                    .collect();
+                if weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) || weights.iter().all(|weight| *weight == 0.0) {
+                    return Err(Error::InvalidSyntax(format!("invalid weights of weighted fusion: {:?}", weights)));
+                }
+                RankFusion::Weighted { weights }
+            }
+        })
+    }
+}
+
+impl RankFusion {
+    /// Reciprocal rank fusion with the rank constant `k` that must be finite and non-negative
+    pub fn reciprocal(k: f64) -> SummaResult<RankFusion> {
+        if !k.is_finite() || k < 0.0 {
+            return Err(Error::InvalidSyntax(format!("invalid k of reciprocal rank fusion: {}", k)));
+        }
+        Ok(RankFusion::Reciprocal { k })
+    }
+
+    /// Fuses lists sorted by descending scores into one list sorted by descending fused scores
+    pub fn fuse(&self, ranked_lists: &[Vec<(f64, DocAddress)>]) -> Vec<(f64, DocAddress)> {
+        let mut fused_scores: HashMap<DocAddress, f64> = HashMap::new();
+        for (list_ord, ranked_list) in ranked_lists.iter().enumerate() {
+            match self {
+                RankFusion::Reciprocal { k } => {
+                    for (rank, (_, doc_address)) in ranked_list.iter().enumerate() {
+                        *fused_scores.entry(*doc_address).or_default() += 1.0 / (k + (rank + 1) as f64);
+                    }
+                }
+                RankFusion::Weighted { weights } => {
+                    let weight = weights.get(list_ord).copied().unwrap_or(1.0);
+                    let min_score = ranked_list.iter().map(|(score, _)| *score).fold(f64::INFINITY, f64::min);
+                    let max_score = ranked_list.iter().map(|(score, _)| *score).fold(f64::NEG_INFINITY, f64::max);
+                    for (score, doc_address) in ranked_list {
+                        let normalized_score = if max_score > min_score {
+                            (score - min_score) / (max_score - min_score)
+                        } else {
+                            1.0
+                        };
+                        *fused_scores.entry(*doc_address).or_default() += weight * normalized_score;
+                    }
+                }
+            }
+        }
+        let mut fused_list: Vec<_> = fused_scores.into_iter().map(|(doc_address, score)| (score, doc_address)).collect();
+        fused_list.sort_by(|left, right| right.0.partial_cmp(&left.0).unwrap_or(Ordering::Equal).then_with(|| left.1.cmp(&right.1)));
+        fused_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RankFusion;
+    use crate::proto;
+    use tantivy::DocAddress;
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let lexical = vec![(10.0, DocAddress::new(0, 1)), (5.0, DocAddress::new(0, 2))];
+        let vector = vec![(0.9, DocAddress::new(0, 2)), (0.8, DocAddress::new(0, 3))];
+        let fused = RankFusion::Reciprocal { k: 60.0 }.fuse(&[lexical, vector]);
+        assert_eq!(
+            fused,
+            vec![
+                (1.0 / 62.0 + 1.0 / 61.0, DocAddress::new(0, 2)),
+                (1.0 / 61.0, DocAddress::new(0, 1)),
+                (1.0 / 62.0, DocAddress::new(0, 3))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weighted_fusion() {
+        let lexical = vec![(10.0, DocAddress::new(0, 1)), (6.0, DocAddress::new(0, 2)), (2.0, DocAddress::new(0, 3))];
+        let vector = vec![(0.5, DocAddress::new(0, 3))];
+        let fused = RankFusion::Weighted { weights: vec![1.0, 2.0] }.fuse(&[lexical, vector]);
+        assert_eq!(
+            fused,
+            vec![(2.0, DocAddress::new(0, 3)), (1.0, DocAddress::new(0, 1)), (0.5, DocAddress::new(0, 2))]
+        );
+    }
+
+    #[test]
+    fn test_weighted_fusion_weights() {
+        let weighted = |lexical_weight, vector_weight| {
+            RankFusion::try_from(proto::hybrid_collector::Fusion::Weighted(proto::WeightedScoreFusion {
+                lexical_weight,
+                vector_weight,
+            }))
+        };
+        assert_eq!(weighted(None, Some(2.0)).unwrap(), RankFusion::Weighted { weights: vec![1.0, 2.0] });
+        assert!(weighted(Some(-1.0), None).is_err());
+        assert!(weighted(Some(f32::NAN), None).is_err());
+        assert!(weighted(Some(0.0), Some(0.0)).is_err());
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion_k() {
+        let reciprocal = |k| RankFusion::try_from(proto::hybrid_collector::Fusion::Rrf(proto::ReciprocalRankFusion { k }));
+        assert_eq!(reciprocal(None).unwrap(), RankFusion::Reciprocal { k: 60.0 });
+        assert_eq!(reciprocal(Some(0)).unwrap(), RankFusion::Reciprocal { k: 0.0 });
+        assert!(RankFusion::reciprocal(-1.0).is_err());
+        assert!(RankFusion::reciprocal(f64::NAN).is_err());
+        assert!(RankFusion::reciprocal(f64::INFINITY).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use super::{IndexBulkResult, IndexUpdater};
+use crate::errors::SummaResult;
+use crate::proto;
+use std::collections::{BTreeMap, HashMap};
+use tantivy::query::Weight;
+use tantivy::schema::{NamedFieldDocument, Schema as Fields};
+use tantivy::{DocId, DocSet, Document, SegmentReader, TERMINATED};
+
+/// Number of stored documents read from the segment before passing them into the target index
+const REINDEX_CHUNK_SIZE: usize = 1000;
+
+/// Encodes the stored document into CBOR document named according to `field_mapping`
+///
+/// CBOR keeps values of bytes fields as byte strings, so they are parsed back without base64 encoding.
+/// Fields mapped into empty names are dropped
+fn map_document(fields: &Fields, document: &Document, field_mapping: &HashMap<String, String>) -> SummaResult<Vec<u8>> {
+    let named_document = NamedFieldDocument(
+        fields
+            .to_named_doc(document)
+            .0
+            .into_iter()
+            .map(|(field_name, values)| (field_mapping.get(&field_name).cloned().unwrap_or(field_name), values))
+            .filter(|(field_name, _)| !field_name.is_empty())
+            .collect::<BTreeMap<_, _>>(),
+    );
+    let mut encoded_document = vec![];
+    ciborium::ser::into_writer(&named_document, &mut encoded_document)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error.to_string()))?;
+    Ok(encoded_document)
+}
+
+/// Alive documents of the segment matching `weight` or all alive documents if `weight` is not set
+fn matching_documents(segment_reader: &SegmentReader, weight: Option<&dyn Weight>) -> SummaResult<Vec<DocId>> {
+    Ok(match weight {
+        None => segment_reader.doc_ids_alive().collect(),
+        Some(weight) => {
+            let mut scorer = weight.scorer(segment_reader, 1.0)?;
+            let mut doc_ids = vec![];
+            while scorer.doc() != TERMINATED {
+                if segment_reader
+                    .alive_bitset()
+                    .map(|alive_bitset| alive_bitset.is_alive(scorer.doc()))
+                    .unwrap_or(true)
+                {
+                    doc_ids.push(scorer.doc());
+                }
+                scorer.advance();
+            }
+            doc_ids
+        }
+    })
+}
+
+/// Copies stored documents of the segment into the index of `target` through its `DocumentParser`
+///
+/// Results are accounted in `result` like for `IndexUpdater::index_bulk`
+pub(crate) fn reindex_segment(
+    fields: &Fields,
+    segment_reader: &SegmentReader,
+    weight: Option<&dyn Weight>,
+    field_mapping: &HashMap<String, String>,
+    target: &IndexUpdater,
+    result: &mut IndexBulkResult,
+) -> SummaResult<()> {
+    let store_reader = segment_reader.get_store_reader()?;
+    for doc_ids in matching_documents(segment_reader, weight)?.chunks(REINDEX_CHUNK_SIZE) {
+        let documents = doc_ids
+            .iter()
+            .map(|doc_id| map_document(fields, &store_reader.get(*doc_id)?, field_mapping))
+            .collect::<SummaResult<Vec<_>>>()?;
+        target.index_bulk(&documents, proto::DocumentFormat::Cbor, result);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::SummaDocument;
+    use tantivy::doc;
+    use tantivy::schema::{STORED, TEXT};
+
+    #[test]
+    fn test_map_document() {
+        let mut fields_builder = Fields::builder();
+        let title = fields_builder.add_text_field("title", TEXT | STORED);
+        let body = fields_builder.add_text_field("body", TEXT | STORED);
+        let hash = fields_builder.add_bytes_field("hash", STORED);
+        let fields = fields_builder.build();
+
+        let field_mapping = HashMap::from([("title".to_owned(), "name".to_owned()), ("body".to_owned(), "".to_owned())]);
+        let encoded_document = map_document(&fields, &doc!(title => "Title", body => "Body", hash => vec![1u8, 2, 3]), &field_mapping).unwrap();
+        let json_object = SummaDocument::parse_json_object_with_format(proto::DocumentFormat::Cbor, &encoded_document).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(json_object),
+            serde_json::json!({"name": ["Title"], "hash": [base64::encode([1, 2, 3])]})
+        );
+    }
+}
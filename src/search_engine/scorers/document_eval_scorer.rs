@@ -0,0 +1,133 @@
+use super::functions::{call_function, rewrite_function_named_variables, validate_function_calls, variable_source_name, FUNCTION_NAMES};
+use super::multi_value_aggregation::{rewrite_aggregations, MultiValueAggregation};
+use crate::errors::{Error, SummaResult, ValidationError};
+use fasteval2::{Compiler, Evaler, Instruction};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tantivy::schema::{Field, Schema as Fields, Value};
+use tantivy::Document;
+
+/// Evaluates `eval_expr` over stored fields of the document
+///
+/// It is much slower than `SegmentEvalScorer` and intended for rescoring a small number of top documents.
+/// Field variables are bound to the first value of the field, and `min(field)`, `max(field)`, `count(field)`
+/// and `sum(field)` aggregate all values of multi-valued fields.
+pub struct DocumentEvalScorer {
+    slab: fasteval2::Slab,
+    compiled: Instruction,
+    now: f64,
+    variables: Vec<(String, Field, Option<MultiValueAggregation>)>,
+}
+
+// `Instruction` may hold pointers to unsafe variables, `DocumentEvalScorer` never registers them and resolves all variables through the namespace
+unsafe impl Send for DocumentEvalScorer {}
+unsafe impl Sync for DocumentEvalScorer {}
+
+fn value_to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::U64(value) => Some(*value as f64),
+        Value::I64(value) => Some(*value as f64),
+        Value::F64(value) => Some(*value),
+        Value::Date(value) => Some(value.into_unix_timestamp() as f64),
+        Value::Str(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+impl DocumentEvalScorer {
+    pub fn new(eval_expr: &str, fields: &Fields) -> SummaResult<DocumentEvalScorer> {
+        let (eval_expr, aggregated_variables) = rewrite_aggregations(eval_expr, |field_name| fields.get_field(field_name).is_some());
+        let eval_expr = rewrite_function_named_variables(&eval_expr);
+        let parser = fasteval2::Parser::new();
+        let mut slab = fasteval2::Slab::new();
+        let parsed = parser.parse(&eval_expr, &mut slab.ps)?.from(&slab.ps);
+        let var_names = parsed.var_names(&slab);
+        validate_function_calls(&eval_expr, &var_names)?;
+
+        let mut variables = vec![];
+        for var_name in var_names {
+            if matches!(var_name.as_str(), "now" | "original_score") || FUNCTION_NAMES.contains(&var_name.as_str()) {
+                continue;
+            }
+            let (field_name, aggregation) = match aggregated_variables.iter().find(|aggregated_variable| aggregated_variable.var_name == var_name) {
+                Some(aggregated_variable) => (aggregated_variable.field_name.as_str(), Some(aggregated_variable.aggregation)),
+                None => (variable_source_name(&var_name), None),
+            };
+            let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+            if !fields.get_field_entry(field).is_stored() {
+                return Err(ValidationError::NotStoredField(field_name.to_owned()).into());
+            }
+            variables.push((var_name, field, aggregation));
+        }
+
+        let compiled = parser
+            .parse(&eval_expr, &mut slab.ps)?
+            .from(&slab.ps)
+            .compile(&slab.ps, &mut slab.cs, &mut |name: &str, args: Vec<f64>| -> Option<f64> {
+                call_function(name, &args)
+            });
+
+        Ok(DocumentEvalScorer {
+            slab,
+            compiled,
+            now: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64(),
+            variables,
+        })
+    }
+
+    /// Scores the document, missing fields are treated as zeroes
+    ///
+    /// Fails if the expression cannot be evaluated, i.e. because of invalid decay params taken from fields
+    pub fn score(&self, document: &Document, original_score: f64) -> SummaResult<f64> {
+        let values: HashMap<&str, f64> = self
+            .variables
+            .iter()
+            .map(|(var_name, field, aggregation)| {
+                let values = document.get_all(*field).filter_map(value_to_f64);
+                let value = match aggregation {
+                    Some(MultiValueAggregation::Count) => document.get_all(*field).count() as f64,
+                    Some(aggregation) => aggregation.aggregate(values),
+                    None => values.into_iter().next().unwrap_or(0.0),
+                };
+                (var_name.as_str(), value)
+            })
+            .collect();
+        let mut namespace = |name: &str, args: Vec<f64>| -> Option<f64> {
+            match name {
+                "original_score" => Some(original_score),
+                "now" => Some(self.now),
+                name => values.get(name).copied().or_else(|| call_function(name, &args)),
+            }
+        };
+        Ok(self.compiled.eval(&self.slab, &mut namespace)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DocumentEvalScorer;
+    use tantivy::doc;
+    use tantivy::schema::{Schema, FAST, STORED, TEXT};
+
+    #[test]
+    fn test_document_eval_scorer() {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        let citations = schema_builder.add_u64_field("citations", STORED);
+        schema_builder.add_u64_field("popularity", FAST);
+        let schema = schema_builder.build();
+
+        let document = doc!(title => "Title", citations => 2u64, citations => 5u64);
+        let scorer = DocumentEvalScorer::new("original_score * count(title) + max(citations) + sum(citations) + citations", &schema).unwrap();
+        assert_eq!(scorer.score(&document, 3.0).unwrap(), 3.0 + 5.0 + 7.0 + 2.0);
+        assert_eq!(scorer.score(&doc!(), 3.0).unwrap(), 0.0);
+        assert!(DocumentEvalScorer::new("exp_decay(citations, 0, 1, 0, 1)", &schema)
+            .unwrap()
+            .score(&document, 3.0)
+            .is_err());
+
+        assert!(DocumentEvalScorer::new("clamp(citations)", &schema).is_err());
+        assert!(DocumentEvalScorer::new("popularity", &schema).is_err());
+        assert!(DocumentEvalScorer::new("unknown_field", &schema).is_err());
+    }
+}
@@ -1,12 +1,14 @@
+use super::functions::{rewrite_function_named_variables, validate_function_calls, variable_source_name, FUNCTION_NAMES};
+use super::multi_value_aggregation::{rewrite_aggregations, AggregatedVariable};
 use crate::errors::{Error, SummaResult};
 use crate::search_engine::scorers::SegmentEvalScorer;
 use fasteval2::Evaler;
-use std::collections::HashSet;
-use tantivy::schema::Schema as Fields;
+use std::collections::{HashMap, HashSet};
+use tantivy::schema::{Cardinality, FieldType, Schema as Fields};
 use tantivy::SegmentReader;
 
 lazy_static! {
-    static ref RESERVED_WORDS: HashSet<&'static str> = HashSet::from(["now", "original_score", "fastsigm"]);
+    static ref RESERVED_WORDS: HashSet<&'static str> = HashSet::from_iter(["now", "original_score"]);
 }
 
 /// Instantiates `SegmentEvalScorer` for each segment
@@ -15,30 +17,157 @@ pub struct EvalScorer {
     fields: Fields,
     parser: fasteval2::Parser,
     var_names: Vec<String>,
+    aggregated_variables: Vec<AggregatedVariable>,
+    params: HashMap<String, f64>,
+}
+
+fn is_multi_valued_fast_field(fields: &Fields, field_name: &str) -> bool {
+    match fields.get_field(field_name).map(|field| fields.get_field_entry(field).field_type()) {
+        Some(FieldType::U64(options) | FieldType::I64(options) | FieldType::F64(options) | FieldType::Date(options)) => {
+            options.get_fastfield_cardinality() == Some(Cardinality::MultiValues)
+        }
+        _ => false,
+    }
 }
 
 impl EvalScorer {
     pub fn new(eval_expr: &str, fields: &Fields) -> SummaResult<EvalScorer> {
+        EvalScorer::with_params(eval_expr, fields, HashMap::new())
+    }
+
+    /// Creates `EvalScorer` with `params` bound as variables of `eval_expr`
+    pub fn with_params(eval_expr: &str, fields: &Fields, params: HashMap<String, f64>) -> SummaResult<EvalScorer> {
+        if let Some(reserved_word) = params.keys().find(|param_name| RESERVED_WORDS.contains(param_name.as_str())) {
+            return Err(Error::InvalidSyntax(format!("{} is reserved and cannot be used as a param", reserved_word)));
+        }
+        // `min(field)`, `max(field)`, `count(field)` and `sum(field)` over multi-valued fast fields are replaced with variables
+        let (eval_expr, aggregated_variables) = rewrite_aggregations(eval_expr, |field_name| is_multi_valued_fast_field(fields, field_name));
+        let eval_expr = rewrite_function_named_variables(&eval_expr);
         let parser = fasteval2::Parser::new();
 
         // Create `Slab` for extracting variable names
         let mut slab = fasteval2::Slab::new();
-        let parsed = parser.parse(eval_expr, &mut slab.ps)?.from(&slab.ps);
+        let parsed = parser.parse(&eval_expr, &mut slab.ps)?.from(&slab.ps);
+        let parsed_var_names = parsed.var_names(&slab);
+
+        validate_function_calls(&eval_expr, &parsed_var_names)?;
+
         let mut var_names = vec![];
-        for var_name in parsed.var_names(&slab).iter().filter(|var_name| !RESERVED_WORDS.contains((*var_name).as_str())) {
-            fields.get_field(var_name).ok_or_else(|| Error::FieldDoesNotExist(var_name.to_owned()))?;
+        for var_name in parsed_var_names
+            .iter()
+            .filter(|var_name| {
+                !RESERVED_WORDS.contains((*var_name).as_str())
+                    && !FUNCTION_NAMES.contains(&(*var_name).as_str())
+                    && !params.contains_key(variable_source_name(var_name))
+            })
+            .filter(|var_name| {
+                !aggregated_variables
+                    .iter()
+                    .any(|aggregated_variable| &aggregated_variable.var_name == *var_name)
+            })
+        {
+            let field_name = variable_source_name(var_name);
+            fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
             var_names.push(var_name.to_owned());
         }
 
         Ok(EvalScorer {
-            eval_expr: eval_expr.to_owned(),
+            eval_expr,
             fields: fields.clone(),
             parser,
             var_names,
+            aggregated_variables,
+            params,
         })
     }
 
     pub fn get_for_segment_reader(&self, segment_reader: &SegmentReader) -> SummaResult<SegmentEvalScorer> {
-        SegmentEvalScorer::for_segment(segment_reader, &self.fields, &self.parser, &self.eval_expr, &self.var_names)
+        SegmentEvalScorer::for_segment(
+            segment_reader,
+            &self.fields,
+            &self.parser,
+            &self.eval_expr,
+            &self.var_names,
+            &self.aggregated_variables,
+            &self.params,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvalScorer;
+    use std::collections::HashMap;
+    use tantivy::doc;
+    use tantivy::schema::{Cardinality, NumericOptions, Schema, FAST};
+    use tantivy::Index;
+
+    #[test]
+    fn test_multi_valued_fast_fields() {
+        let mut schema_builder = Schema::builder();
+        let citation_years = schema_builder.add_u64_field("citation_years", NumericOptions::default().set_fast(Cardinality::MultiValues));
+        let year = schema_builder.add_u64_field("year", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer(3_000_000).unwrap();
+        index_writer
+            .add_document(doc!(year => 2000u64, citation_years => 2005u64, citation_years => 2001u64, citation_years => 2010u64))
+            .unwrap();
+        index_writer.add_document(doc!(year => 2010u64)).unwrap();
+        index_writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let segment_reader = searcher.segment_reader(0);
+
+        let eval_scorer = EvalScorer::new("min(citation_years) - year + 10 * count(citation_years) + max(sum(citation_years), 1)", &schema).unwrap();
+        let mut segment_eval_scorer = eval_scorer.get_for_segment_reader(segment_reader).unwrap();
+        assert_eq!(segment_eval_scorer.score(0, 0.0), 2001.0 - 2000.0 + 30.0 + 6016.0);
+        assert_eq!(segment_eval_scorer.score(1, 0.0), 0.0 - 2010.0 + 0.0 + 1.0);
+
+        assert!(EvalScorer::new("max(year)", &schema).is_ok());
+        assert!(EvalScorer::new("if", &schema).is_err());
+        assert!(EvalScorer::new("count(year)", &schema).is_err());
+    }
+
+    #[test]
+    fn test_function_named_fields() {
+        let mut schema_builder = Schema::builder();
+        let if_field = schema_builder.add_u64_field("if", FAST);
+        let gt_field = schema_builder.add_u64_field("gt", FAST);
+        let schema = schema_builder.build();
+        let index = Index::create_in_ram(schema.clone());
+        let mut index_writer = index.writer(3_000_000).unwrap();
+        index_writer.add_document(doc!(if_field => 2u64, gt_field => 5u64)).unwrap();
+        index_writer.add_document(doc!(if_field => 0u64, gt_field => 5u64)).unwrap();
+        index_writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let segment_reader = searcher.segment_reader(0);
+
+        let params = HashMap::from([("lt".to_owned(), 100.0)]);
+        let eval_scorer = EvalScorer::with_params("if(gt(if, 1), if * 10, gt) + lt + lt(if, 1)", &schema, params).unwrap();
+        let mut segment_eval_scorer = eval_scorer.get_for_segment_reader(segment_reader).unwrap();
+        assert_eq!(segment_eval_scorer.score(0, 0.0), 20.0 + 100.0);
+        assert_eq!(segment_eval_scorer.score(1, 0.0), 5.0 + 100.0 + 1.0);
+        let mut segment_eval_scorer = EvalScorer::new("gauss_decay(if, 0, 0)", &schema)
+            .unwrap()
+            .get_for_segment_reader(segment_reader)
+            .unwrap();
+        assert_eq!(segment_eval_scorer.score(0, 0.0), 0.0);
+
+        assert!(EvalScorer::new("exp_decay(if)", &schema).is_err());
+        assert!(EvalScorer::new("exp_decay(if, 0)", &schema).is_err());
+        assert!(EvalScorer::new("exp_decay(if, 0, 1, 0, 0.5, 1)", &schema).is_err());
+        assert!(EvalScorer::new("gt(if(if, 1, 2), 1, 3)", &schema).is_err());
+        assert!(EvalScorer::new("gt(1, 2) + gt()", &schema).is_err());
+        assert!(EvalScorer::new("if(1, 2, 3)", &schema).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_functions() {
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_u64_field("year", FAST);
+        let schema = schema_builder.build();
+        assert!(EvalScorer::new("year(1)", &schema).is_err());
+        assert!(EvalScorer::new("decay(year)", &schema).is_err());
+        assert!(EvalScorer::new("abs(year) + max(year, 1)", &schema).is_ok());
     }
 }
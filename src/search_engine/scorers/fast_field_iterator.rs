@@ -1,5 +1,6 @@
+use super::multi_value_aggregation::MultiValueAggregation;
 use super::safe_into_f64::SafeIntoF64;
-use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue};
+use tantivy::fastfield::{DynamicFastFieldReader, FastFieldReader, FastValue, MultiValuedFastFieldReader};
 use tantivy::DocId;
 
 pub(crate) trait FastFieldIterator {
@@ -26,3 +27,32 @@ impl<T: FastValue + SafeIntoF64> FastFieldIterator for FastFieldIteratorImpl<T>
         &self.value
     }
 }
+
+/// Aggregates all values of the document in multi-valued fast field
+pub(crate) struct MultiValuedFastFieldIteratorImpl<T: FastValue + SafeIntoF64> {
+    value: f64,
+    ff: MultiValuedFastFieldReader<T>,
+    aggregation: MultiValueAggregation,
+    vals: Vec<T>,
+}
+
+impl<T: FastValue + SafeIntoF64> MultiValuedFastFieldIteratorImpl<T> {
+    pub fn from_fast_field_reader(ff: MultiValuedFastFieldReader<T>, aggregation: MultiValueAggregation) -> Box<dyn FastFieldIterator> {
+        Box::new(MultiValuedFastFieldIteratorImpl {
+            value: 0f64,
+            ff,
+            aggregation,
+            vals: vec![],
+        })
+    }
+}
+
+impl<T: FastValue + SafeIntoF64> FastFieldIterator for MultiValuedFastFieldIteratorImpl<T> {
+    fn advance(&mut self, doc_id: DocId) {
+        self.ff.get_vals(doc_id, &mut self.vals);
+        self.value = self.aggregation.aggregate(self.vals.iter().map(|value| value.safe_into_f64()));
+    }
+    fn value(&self) -> &f64 {
+        &self.value
+    }
+}
@@ -0,0 +1,313 @@
+use super::multi_value_aggregation::{is_varname_byte, read_identifier, skip_spaces};
+use crate::errors::{Error, SummaResult};
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+/// Names of functions that are available in eval expressions in addition to fasteval built-ins
+pub(crate) const FUNCTION_NAMES: [&str; 15] = [
+    "clamp",
+    "eq",
+    "exp_decay",
+    "fastsigm",
+    "gauss_decay",
+    "gt",
+    "gte",
+    "if",
+    "linear_decay",
+    "log1p",
+    "lt",
+    "lte",
+    "neq",
+    "saturation",
+    "sqrt",
+];
+
+/// Prefix of variables substituted instead of identifiers that are named like functions but are not called
+const FUNCTION_NAMED_VARIABLE_PREFIX: &str = "__var_";
+
+/// Replaces identifiers named like functions that are not followed by arguments with prefixed variables
+///
+/// It keeps fields and params such as `if` or `gt` usable as variables, while calls `if(...)` or `gt(...)` stay calls.
+/// Use `variable_name` and `variable_source_name` for mapping names of fields and params into variables and back.
+pub(crate) fn rewrite_function_named_variables(eval_expr: &str) -> String {
+    let bytes = eval_expr.as_bytes();
+    let mut rewritten_eval_expr = String::with_capacity(eval_expr.len());
+    let mut position = 0;
+    while position < bytes.len() {
+        let is_identifier_start = position == 0 || !(is_varname_byte(bytes[position - 1]) || bytes[position - 1] == b'.');
+        if let (true, Some((identifier, identifier_end))) = (is_identifier_start, read_identifier(bytes, position)) {
+            if FUNCTION_NAMES.contains(&identifier) && bytes.get(skip_spaces(bytes, identifier_end)) != Some(&b'(') {
+                rewritten_eval_expr.push_str(FUNCTION_NAMED_VARIABLE_PREFIX);
+            }
+            rewritten_eval_expr.push_str(identifier);
+            position = identifier_end;
+        } else {
+            let char_len = eval_expr[position..].chars().next().map(char::len_utf8).unwrap_or(1);
+            rewritten_eval_expr.push_str(&eval_expr[position..position + char_len]);
+            position += char_len;
+        }
+    }
+    rewritten_eval_expr
+}
+
+/// Calls of any functions in `eval_expr` with the number of passed arguments
+fn function_calls(eval_expr: &str) -> Vec<(&str, usize)> {
+    let bytes = eval_expr.as_bytes();
+    let mut calls = vec![];
+    let mut position = 0;
+    while position < bytes.len() {
+        let is_identifier_start = position == 0 || !(is_varname_byte(bytes[position - 1]) || bytes[position - 1] == b'.');
+        if let (true, Some((identifier, identifier_end))) = (is_identifier_start, read_identifier(bytes, position)) {
+            let arguments_start = skip_spaces(bytes, identifier_end);
+            if bytes.get(arguments_start) == Some(&b'(') {
+                calls.push((identifier, count_arguments(bytes, arguments_start)));
+            }
+            position = identifier_end;
+        } else {
+            position += 1;
+        }
+    }
+    calls
+}
+
+/// Counts top-level arguments between the parenthesis at `arguments_start` and the matching one
+fn count_arguments(bytes: &[u8], arguments_start: usize) -> usize {
+    let mut depth = 0;
+    let mut separators = 0;
+    let mut is_empty = true;
+    for &b in &bytes[arguments_start..] {
+        match b {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            b',' if depth == 1 => separators += 1,
+            _ => {}
+        }
+        if depth > 1 || (b != b'(' && !b.is_ascii_whitespace()) {
+            is_empty = false;
+        }
+    }
+    if is_empty {
+        0
+    } else {
+        separators + 1
+    }
+}
+
+/// Allowed numbers of arguments of functions from `FUNCTION_NAMES`
+fn function_arity(name: &str) -> Option<RangeInclusive<usize>> {
+    match name {
+        "fastsigm" => Some(1..=2),
+        "gauss_decay" | "exp_decay" | "linear_decay" => Some(3..=5),
+        "log1p" | "sqrt" => Some(1..=1),
+        "clamp" | "if" => Some(3..=3),
+        "saturation" | "eq" | "neq" | "gt" | "gte" | "lt" | "lte" => Some(2..=2),
+        _ => None,
+    }
+}
+
+/// Rejects calls of functions from `FUNCTION_NAMES` with a wrong number of arguments and calls of unknown functions
+///
+/// Such calls cannot be evaluated for any document, `var_names` are names collected from the parsed `eval_expr`
+/// where fasteval puts names of all custom functions.
+pub(crate) fn validate_function_calls(eval_expr: &str, var_names: &BTreeSet<String>) -> SummaResult<()> {
+    for (function_name, arguments_count) in function_calls(eval_expr) {
+        match function_arity(function_name) {
+            Some(arity) if !arity.contains(&arguments_count) => {
+                return Err(Error::InvalidSyntax(format!(
+                    "{} takes from {} to {} arguments but {} were given",
+                    function_name,
+                    arity.start(),
+                    arity.end(),
+                    arguments_count
+                )))
+            }
+            None if var_names.contains(function_name) => return Err(Error::InvalidSyntax(format!("unknown function {}", function_name))),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Name of the variable bound to the field or the param in expressions rewritten by `rewrite_function_named_variables`
+pub(crate) fn variable_name(name: &str) -> String {
+    if FUNCTION_NAMES.contains(&name) {
+        format!("{}{}", FUNCTION_NAMED_VARIABLE_PREFIX, name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Name of the field or the param bound to the variable, the inverse of `variable_name`
+pub(crate) fn variable_source_name(var_name: &str) -> &str {
+    match var_name.strip_prefix(FUNCTION_NAMED_VARIABLE_PREFIX) {
+        Some(name) if FUNCTION_NAMES.contains(&name) => name,
+        _ => var_name,
+    }
+}
+
+/// Distance from `origin` reduced by `offset`, values closer than `offset` to `origin` have zero distance
+fn decay_distance(value: f64, origin: f64, offset: f64) -> f64 {
+    ((value - origin).abs() - offset).max(0.0)
+}
+
+/// Checks that `scale` and `decay` define a decay, zero `scale` or `decay` outside of `(0, 1)` give infinite or NaN scores
+fn is_valid_decay(scale: f64, offset: f64, decay: f64) -> bool {
+    scale > 0.0 && offset >= 0.0 && decay > 0.0 && decay < 1.0
+}
+
+/// Calls the decay function with `value, origin, scale[, offset = 0[, decay = 0.5]]` taken from `args`
+fn call_decay(decay_function: fn(f64, f64, f64, f64, f64) -> f64, args: &[f64]) -> Option<f64> {
+    let (value, origin, scale) = (*args.first()?, *args.get(1)?, *args.get(2)?);
+    let (offset, decay) = (args.get(3).copied().unwrap_or(0.0), args.get(4).copied().unwrap_or(0.5));
+    is_valid_decay(scale, offset, decay).then(|| decay_function(value, origin, scale, offset, decay))
+}
+
+/// Gaussian decay, it equals to `decay` at `scale` distance from `origin` shifted by `offset`
+pub(crate) fn gauss_decay(value: f64, origin: f64, scale: f64, offset: f64, decay: f64) -> f64 {
+    let sigma_squared = -scale.powi(2) / (2.0 * decay.ln());
+    (-decay_distance(value, origin, offset).powi(2) / (2.0 * sigma_squared)).exp()
+}
+
+/// Exponential decay, it equals to `decay` at `scale` distance from `origin` shifted by `offset`
+pub(crate) fn exp_decay(value: f64, origin: f64, scale: f64, offset: f64, decay: f64) -> f64 {
+    (decay.ln() / scale * decay_distance(value, origin, offset)).exp()
+}
+
+/// Linear decay, it equals to `decay` at `scale` distance from `origin` shifted by `offset` and reaches zero at `scale / (1 - decay)`
+pub(crate) fn linear_decay(value: f64, origin: f64, scale: f64, offset: f64, decay: f64) -> f64 {
+    let s = scale / (1.0 - decay);
+    ((s - decay_distance(value, origin, offset)) / s).max(0.0)
+}
+
+fn bool_to_f64(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Evaluates functions that are available in eval expressions in addition to fasteval built-ins
+///
+/// Decay functions take `value, origin, scale[, offset = 0[, decay = 0.5]]`, comparisons return `1` or `0`
+/// and `if(condition, then, else)` treats any non-zero `condition` as true. Returns `None` for unknown functions
+/// and for functions called with not enough arguments. Decay functions also return `None` unless `scale` is positive,
+/// `offset` is non-negative and `decay` is in `(0, 1)`.
+pub(crate) fn call_function(name: &str, args: &[f64]) -> Option<f64> {
+    let arg = |i: usize| args.get(i).copied();
+    match name {
+        "fastsigm" => {
+            let x = arg(0)?.abs();
+            let a = arg(1).unwrap_or(1f64);
+            Some(x / (a + x))
+        }
+        "gauss_decay" => call_decay(gauss_decay, args),
+        "exp_decay" => call_decay(exp_decay, args),
+        "linear_decay" => call_decay(linear_decay, args),
+        "log1p" => Some(arg(0)?.ln_1p()),
+        "sqrt" => Some(arg(0)?.sqrt()),
+        "clamp" => Some(arg(0)?.max(arg(1)?).min(arg(2)?)),
+        "saturation" => {
+            let x = arg(0)?;
+            Some(x / (x + arg(1)?))
+        }
+        "if" => Some(if arg(0)? != 0.0 { arg(1)? } else { arg(2)? }),
+        "eq" => Some(bool_to_f64(arg(0)? == arg(1)?)),
+        "neq" => Some(bool_to_f64(arg(0)? != arg(1)?)),
+        "gt" => Some(bool_to_f64(arg(0)? > arg(1)?)),
+        "gte" => Some(bool_to_f64(arg(0)? >= arg(1)?)),
+        "lt" => Some(bool_to_f64(arg(0)? < arg(1)?)),
+        "lte" => Some(bool_to_f64(arg(0)? <= arg(1)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{call_function, function_arity, function_calls, rewrite_function_named_variables, variable_name, variable_source_name, FUNCTION_NAMES};
+
+    fn assert_close(name: &str, args: &[f64], expected: f64) {
+        let value = call_function(name, args).unwrap();
+        assert!((value - expected).abs() < 1e-9, "{}({:?}) = {}, expected {}", name, args, value, expected);
+    }
+
+    #[test]
+    fn test_decay_functions() {
+        // Every decay function is equal to `decay` at `scale` distance from `origin`
+        for name in ["gauss_decay", "exp_decay", "linear_decay"] {
+            assert_close(name, &[10.0, 0.0, 10.0], 0.5);
+            assert_close(name, &[-10.0, 0.0, 10.0], 0.5);
+            assert_close(name, &[15.0, 0.0, 10.0, 5.0], 0.5);
+            assert_close(name, &[3.0, 0.0, 10.0, 5.0], 1.0);
+            assert_close(name, &[110.0, 100.0, 10.0, 0.0, 0.3], 0.3);
+        }
+        assert_close("gauss_decay", &[20.0, 0.0, 10.0], 0.0625);
+        assert_close("gauss_decay", &[5.0, 0.0, 10.0], 0.840896415253714);
+        assert_close("exp_decay", &[20.0, 0.0, 10.0], 0.25);
+        assert_close("exp_decay", &[5.0, 0.0, 10.0], std::f64::consts::FRAC_1_SQRT_2);
+        assert_close("linear_decay", &[5.0, 0.0, 10.0], 0.75);
+        assert_close("linear_decay", &[30.0, 0.0, 10.0], 0.0);
+        assert_eq!(call_function("gauss_decay", &[1.0, 0.0]), None);
+        for name in ["gauss_decay", "exp_decay", "linear_decay"] {
+            assert_eq!(call_function(name, &[1.0, 0.0, 0.0]), None);
+            assert_eq!(call_function(name, &[1.0, 0.0, 10.0, -1.0]), None);
+            assert_eq!(call_function(name, &[1.0, 0.0, 10.0, 0.0, 1.0]), None);
+            assert_eq!(call_function(name, &[1.0, 0.0, 10.0, 0.0, 0.0]), None);
+        }
+    }
+
+    #[test]
+    fn test_math_functions() {
+        assert_close("fastsigm", &[-3.0], 0.75);
+        assert_close("fastsigm", &[3.0, 3.0], 0.5);
+        assert_close("log1p", &[std::f64::consts::E - 1.0], 1.0);
+        assert_close("log1p", &[0.0], 0.0);
+        assert_close("sqrt", &[16.0], 4.0);
+        assert_close("clamp", &[5.0, 0.0, 1.0], 1.0);
+        assert_close("clamp", &[-5.0, 0.0, 1.0], 0.0);
+        assert_close("clamp", &[0.5, 0.0, 1.0], 0.5);
+        assert_close("saturation", &[3.0, 1.0], 0.75);
+        assert_close("saturation", &[0.0, 1.0], 0.0);
+    }
+
+    #[test]
+    fn test_conditional_functions() {
+        assert_close("if", &[1.0, 2.0, 3.0], 2.0);
+        assert_close("if", &[0.0, 2.0, 3.0], 3.0);
+        assert_close("eq", &[1.0, 1.0], 1.0);
+        assert_close("neq", &[1.0, 1.0], 0.0);
+        assert_close("gt", &[2.0, 1.0], 1.0);
+        assert_close("gte", &[1.0, 1.0], 1.0);
+        assert_close("lt", &[2.0, 1.0], 0.0);
+        assert_close("lte", &[2.0, 1.0], 0.0);
+        assert_eq!(call_function("unknown", &[1.0]), None);
+        assert!(FUNCTION_NAMES.iter().all(|name| call_function(name, &[1.0, 1.0, 1.0]).is_some()));
+    }
+
+    #[test]
+    fn test_rewrite_function_named_variables() {
+        assert_eq!(
+            rewrite_function_named_variables("if(if > gt, gt (1, sqrt), 2.if) + if_a + a_if"),
+            "if(__var_if > __var_gt, gt (1, __var_sqrt), 2.if) + if_a + a_if"
+        );
+        assert_eq!(variable_name("if"), "__var_if");
+        assert_eq!(variable_name("year"), "year");
+        assert_eq!(variable_source_name("__var_if"), "if");
+        assert_eq!(variable_source_name("__var_year"), "__var_year");
+    }
+
+    #[test]
+    fn test_function_calls() {
+        assert_eq!(
+            function_calls("exp_decay(year, max(a, b), 10) + pi() + gt (if(x, (1, 2), 3), 1.5)"),
+            vec![("exp_decay", 3), ("max", 2), ("pi", 0), ("gt", 2), ("if", 3)]
+        );
+        assert!(FUNCTION_NAMES.iter().all(|name| function_arity(name).is_some()));
+        assert_eq!(function_arity("unknown"), None);
+    }
+}
@@ -1,7 +1,21 @@
+mod document_eval_scorer;
 mod eval_scorer;
 mod fast_field_iterator;
+mod functions;
+mod model_scorer;
+mod multi_value_aggregation;
+mod random_scorer;
+mod ranking_model;
 mod safe_into_f64;
 mod segment_eval_scorer;
 
+pub use document_eval_scorer::DocumentEvalScorer;
 pub use eval_scorer::EvalScorer;
+pub(crate) use fast_field_iterator::FastFieldIterator;
+pub(crate) use functions::{call_function, rewrite_function_named_variables, variable_source_name, FUNCTION_NAMES};
+pub use model_scorer::ModelScorer;
+pub use random_scorer::{RandomScorer, SegmentRandomScorer};
+pub use ranking_model::{RankingModel, RankingModels};
+pub(crate) use safe_into_f64::SafeIntoF64;
+pub(crate) use segment_eval_scorer::fast_field_to_iter;
 pub use segment_eval_scorer::SegmentEvalScorer;
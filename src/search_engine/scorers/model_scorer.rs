@@ -0,0 +1,182 @@
+use super::ranking_model::RankingModel;
+use super::segment_eval_scorer::fast_field_to_iter;
+use crate::errors::{Error, SummaResult, ValidationError};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tantivy::query::Query;
+use tantivy::schema::{Cardinality, FieldType, IndexRecordOption, Schema as Fields};
+use tantivy::{DocAddress, DocSet, Postings, Searcher, Term};
+
+enum Feature {
+    OriginalScore,
+    /// Number of query terms occurring in the document
+    QueryTermsMatched,
+    /// Fraction of query terms occurring in the document
+    QueryTermsCoverage,
+    /// Sum of frequencies of query terms in the document
+    QueryTermsTfSum,
+    /// Sum of frequencies of query terms multiplied by their IDF
+    QueryTermsTfIdfSum,
+    Param(f64),
+    FastField(String),
+}
+
+impl Feature {
+    fn depends_on_query_terms(&self) -> bool {
+        matches!(
+            self,
+            Feature::QueryTermsMatched | Feature::QueryTermsCoverage | Feature::QueryTermsTfSum | Feature::QueryTermsTfIdfSum
+        )
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct QueryTermsStatistics {
+    matched: f64,
+    tf_sum: f64,
+    tf_idf_sum: f64,
+}
+
+/// Scores top documents with `RankingModel`
+///
+/// Features of the model are resolved by their names to `original_score`, statistics of query terms
+/// (`query_terms_matched`, `query_terms_coverage`, `query_terms_tf_sum` and `query_terms_tf_idf_sum`),
+/// request params and fast fields, in this order.
+pub struct ModelScorer {
+    model: Arc<RankingModel>,
+    features: Vec<Feature>,
+    fields: Fields,
+    query_terms: Vec<Term>,
+}
+
+impl ModelScorer {
+    pub fn new(model: Arc<RankingModel>, fields: &Fields, query: &dyn Query, params: &HashMap<String, f64>) -> SummaResult<ModelScorer> {
+        let features = model
+            .features()
+            .iter()
+            .map(|feature_name| {
+                Ok(match feature_name.as_str() {
+                    "original_score" => Feature::OriginalScore,
+                    "query_terms_matched" => Feature::QueryTermsMatched,
+                    "query_terms_coverage" => Feature::QueryTermsCoverage,
+                    "query_terms_tf_sum" => Feature::QueryTermsTfSum,
+                    "query_terms_tf_idf_sum" => Feature::QueryTermsTfIdfSum,
+                    feature_name if params.contains_key(feature_name) => Feature::Param(params[feature_name]),
+                    feature_name => {
+                        let field = fields
+                            .get_field(feature_name)
+                            .ok_or_else(|| Error::FieldDoesNotExist(feature_name.to_owned()))?;
+                        match fields.get_field_entry(field).field_type() {
+                            FieldType::U64(options) | FieldType::I64(options) | FieldType::F64(options) | FieldType::Date(options)
+                                if options.get_fastfield_cardinality() == Some(Cardinality::SingleValue) =>
+                            {
+                                Feature::FastField(feature_name.to_owned())
+                            }
+                            field_type => {
+                                return Err(ValidationError::InvalidFastFieldType {
+                                    field: feature_name.to_owned(),
+                                    field_type: field_type.to_owned(),
+                                }
+                                .into())
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<SummaResult<_>>()?;
+        let mut query_terms = BTreeMap::new();
+        query.query_terms(&mut query_terms);
+        Ok(ModelScorer {
+            model,
+            features,
+            fields: fields.clone(),
+            query_terms: query_terms.into_keys().collect(),
+        })
+    }
+
+    /// Collects statistics of query terms for documents sorted by their addresses
+    fn query_terms_statistics(&self, searcher: &Searcher, doc_addresses: &[DocAddress]) -> tantivy::Result<Vec<QueryTermsStatistics>> {
+        let mut statistics = vec![QueryTermsStatistics::default(); doc_addresses.len()];
+        let num_docs = searcher.num_docs() as f64;
+        for term in &self.query_terms {
+            let doc_freq = searcher.doc_freq(term)? as f64;
+            let idf = (1.0 + (num_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln();
+            let mut current_segment_ord = None;
+            let mut postings = None;
+            for (doc_address, doc_statistics) in doc_addresses.iter().zip(statistics.iter_mut()) {
+                if current_segment_ord != Some(doc_address.segment_ord) {
+                    current_segment_ord = Some(doc_address.segment_ord);
+                    postings = searcher
+                        .segment_reader(doc_address.segment_ord)
+                        .inverted_index(term.field())?
+                        .read_postings(term, IndexRecordOption::WithFreqs)?;
+                }
+                if let Some(postings) = postings.as_mut() {
+                    // Postings may already be past the document if the previous document has been sought
+                    if postings.doc() < doc_address.doc_id {
+                        postings.seek(doc_address.doc_id);
+                    }
+                    if postings.doc() == doc_address.doc_id {
+                        let term_freq = postings.term_freq() as f64;
+                        doc_statistics.matched += 1.0;
+                        doc_statistics.tf_sum += term_freq;
+                        doc_statistics.tf_idf_sum += term_freq * idf;
+                    }
+                }
+            }
+        }
+        Ok(statistics)
+    }
+
+    /// Scores documents with their first pass scores
+    pub fn score(&self, searcher: &Searcher, documents: &[(f64, DocAddress)]) -> SummaResult<Vec<f64>> {
+        // Postings and fast fields are read segment by segment in the order of documents
+        let mut order: Vec<usize> = (0..documents.len()).collect();
+        order.sort_by_key(|position| documents[*position].1);
+        let doc_addresses: Vec<DocAddress> = order.iter().map(|position| documents[*position].1).collect();
+
+        let query_terms_statistics = if self.features.iter().any(Feature::depends_on_query_terms) {
+            self.query_terms_statistics(searcher, &doc_addresses)?
+        } else {
+            vec![QueryTermsStatistics::default(); doc_addresses.len()]
+        };
+
+        let mut scores = vec![0.0; documents.len()];
+        let mut fast_fields_iterators = vec![];
+        let mut current_segment_ord = None;
+        let mut feature_values = vec![0.0; self.features.len()];
+        for ((position, doc_address), doc_statistics) in order.iter().zip(doc_addresses.iter()).zip(query_terms_statistics.iter()) {
+            if current_segment_ord != Some(doc_address.segment_ord) {
+                current_segment_ord = Some(doc_address.segment_ord);
+                let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+                fast_fields_iterators = self
+                    .features
+                    .iter()
+                    .map(|feature| match feature {
+                        Feature::FastField(field_name) => fast_field_to_iter(&self.fields, segment_reader, field_name).map(Some),
+                        _ => Ok(None),
+                    })
+                    .collect::<SummaResult<_>>()?;
+            }
+            for ((feature, fast_field_iterator), feature_value) in self.features.iter().zip(fast_fields_iterators.iter_mut()).zip(feature_values.iter_mut()) {
+                *feature_value = match feature {
+                    Feature::OriginalScore => documents[*position].0,
+                    Feature::QueryTermsMatched => doc_statistics.matched,
+                    Feature::QueryTermsCoverage => doc_statistics.matched / self.query_terms.len().max(1) as f64,
+                    Feature::QueryTermsTfSum => doc_statistics.tf_sum,
+                    Feature::QueryTermsTfIdfSum => doc_statistics.tf_idf_sum,
+                    Feature::Param(value) => *value,
+                    Feature::FastField(_) => {
+                        let fast_field_iterator = fast_field_iterator
+                            .as_mut()
+                            .expect("fast field iterator is created for every fast field feature");
+                        fast_field_iterator.advance(doc_address.doc_id);
+                        *fast_field_iterator.value()
+                    }
+                };
+            }
+            scores[*position] = self.model.predict(&feature_values);
+        }
+        Ok(scores)
+    }
+}
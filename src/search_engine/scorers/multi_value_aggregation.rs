@@ -0,0 +1,126 @@
+/// Aggregation of all values of the multi-valued field into a single variable of eval expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MultiValueAggregation {
+    Min,
+    Max,
+    Count,
+    Sum,
+}
+
+impl MultiValueAggregation {
+    fn from_name(name: &str) -> Option<MultiValueAggregation> {
+        match name {
+            "min" => Some(MultiValueAggregation::Min),
+            "max" => Some(MultiValueAggregation::Max),
+            "count" => Some(MultiValueAggregation::Count),
+            "sum" => Some(MultiValueAggregation::Sum),
+            _ => None,
+        }
+    }
+
+    /// Aggregates `values`, `min` and `max` of the empty set are `0`
+    pub fn aggregate(&self, values: impl Iterator<Item = f64>) -> f64 {
+        match self {
+            MultiValueAggregation::Min => values.reduce(f64::min).unwrap_or(0.0),
+            MultiValueAggregation::Max => values.reduce(f64::max).unwrap_or(0.0),
+            MultiValueAggregation::Count => values.count() as f64,
+            MultiValueAggregation::Sum => values.sum(),
+        }
+    }
+}
+
+/// Variable of eval expression substituted instead of aggregation call
+pub(crate) struct AggregatedVariable {
+    pub var_name: String,
+    pub aggregation: MultiValueAggregation,
+    pub field_name: String,
+}
+
+pub(crate) fn is_varname_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Reads an identifier starting at `position` and returns it with the position after it
+pub(crate) fn read_identifier(bytes: &[u8], position: usize) -> Option<(&str, usize)> {
+    if position >= bytes.len() || !(bytes[position].is_ascii_alphabetic() || bytes[position] == b'_') {
+        return None;
+    }
+    let end = position + bytes[position..].iter().take_while(|b| is_varname_byte(**b)).count();
+    Some((std::str::from_utf8(&bytes[position..end]).ok()?, end))
+}
+
+pub(crate) fn skip_spaces(bytes: &[u8], position: usize) -> usize {
+    position + bytes[position..].iter().take_while(|b| b.is_ascii_whitespace()).count()
+}
+
+/// Replaces `min(field)`, `max(field)`, `count(field)` and `sum(field)` calls over fields accepted by `is_aggregated_field`
+/// with variables that should be bound to the aggregated values of the field
+pub(crate) fn rewrite_aggregations(eval_expr: &str, is_aggregated_field: impl Fn(&str) -> bool) -> (String, Vec<AggregatedVariable>) {
+    let bytes = eval_expr.as_bytes();
+    let mut rewritten_eval_expr = String::with_capacity(eval_expr.len());
+    let mut aggregated_variables: Vec<AggregatedVariable> = vec![];
+    let mut position = 0;
+    while position < bytes.len() {
+        let is_identifier_start = position == 0 || !(is_varname_byte(bytes[position - 1]) || bytes[position - 1] == b'.');
+        if let (true, Some((identifier, identifier_end))) = (is_identifier_start, read_identifier(bytes, position)) {
+            let aggregation_call = MultiValueAggregation::from_name(identifier).and_then(|aggregation| {
+                let argument_start = skip_spaces(bytes, identifier_end);
+                if bytes.get(argument_start) != Some(&b'(') {
+                    return None;
+                }
+                let (field_name, argument_end) = read_identifier(bytes, skip_spaces(bytes, argument_start + 1))?;
+                let call_end = skip_spaces(bytes, argument_end);
+                if bytes.get(call_end) != Some(&b')') || !is_aggregated_field(field_name) {
+                    return None;
+                }
+                Some((aggregation, field_name, call_end + 1))
+            });
+            match aggregation_call {
+                Some((aggregation, field_name, call_end)) => {
+                    let var_name = format!("__{}_{}", identifier, field_name);
+                    rewritten_eval_expr.push_str(&var_name);
+                    if !aggregated_variables.iter().any(|aggregated_variable| aggregated_variable.var_name == var_name) {
+                        aggregated_variables.push(AggregatedVariable {
+                            var_name,
+                            aggregation,
+                            field_name: field_name.to_owned(),
+                        });
+                    }
+                    position = call_end;
+                }
+                None => {
+                    rewritten_eval_expr.push_str(identifier);
+                    position = identifier_end;
+                }
+            }
+        } else {
+            let char_len = eval_expr[position..].chars().next().map(char::len_utf8).unwrap_or(1);
+            rewritten_eval_expr.push_str(&eval_expr[position..position + char_len]);
+            position += char_len;
+        }
+    }
+    (rewritten_eval_expr, aggregated_variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite_aggregations, MultiValueAggregation};
+
+    #[test]
+    fn test_rewrite_aggregations() {
+        let (eval_expr, aggregated_variables) = rewrite_aggregations("max(a, 2) + count( tags ) * sum(tags) - min(year2)", |field_name| field_name != "year2");
+        assert_eq!(eval_expr, "max(a, 2) + __count_tags * __sum_tags - min(year2)");
+        assert_eq!(aggregated_variables.len(), 2);
+        assert_eq!(aggregated_variables[0].aggregation, MultiValueAggregation::Count);
+        assert_eq!(aggregated_variables[1].field_name, "tags");
+    }
+
+    #[test]
+    fn test_aggregate() {
+        assert_eq!(MultiValueAggregation::Min.aggregate([3.0, 1.0, 2.0].into_iter()), 1.0);
+        assert_eq!(MultiValueAggregation::Max.aggregate([3.0, 1.0, 2.0].into_iter()), 3.0);
+        assert_eq!(MultiValueAggregation::Count.aggregate([3.0, 1.0, 2.0].into_iter()), 3.0);
+        assert_eq!(MultiValueAggregation::Sum.aggregate([3.0, 1.0, 2.0].into_iter()), 6.0);
+        assert_eq!(MultiValueAggregation::Max.aggregate(std::iter::empty()), 0.0);
+    }
+}
@@ -0,0 +1,114 @@
+use super::fast_field_iterator::FastFieldIterator;
+use super::segment_eval_scorer::fast_field_to_iter;
+use crate::errors::{Error, SummaResult, ValidationError};
+use tantivy::schema::{Cardinality, FieldType, Schema as Fields};
+use tantivy::{DocId, SegmentReader};
+
+/// Finalizer of SplitMix64, spreads close values of the fast field over the whole range
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Scores documents by the pseudo-random number in `[0, 1)` derived from `seed` and the value of the fast field
+///
+/// The score depends only on `seed` and the value, so it is stable across segments, merges and paginated requests
+/// if the field is unique, i.e. the primary key. Different seeds give independent orders.
+pub struct RandomScorer {
+    seed: u64,
+    field_name: String,
+    fields: Fields,
+}
+
+impl RandomScorer {
+    pub fn new(seed: u64, field_name: &str, fields: &Fields) -> SummaResult<RandomScorer> {
+        let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+        match fields.get_field_entry(field).field_type() {
+            FieldType::U64(options) | FieldType::I64(options) | FieldType::F64(options) | FieldType::Date(options)
+                if options.get_fastfield_cardinality() == Some(Cardinality::SingleValue) =>
+            {
+                Ok(RandomScorer {
+                    seed,
+                    field_name: field_name.to_owned(),
+                    fields: fields.clone(),
+                })
+            }
+            field_type => Err(ValidationError::InvalidFastFieldType {
+                field: field_name.to_owned(),
+                field_type: field_type.to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn get_for_segment_reader(&self, segment_reader: &SegmentReader) -> SummaResult<SegmentRandomScorer> {
+        Ok(SegmentRandomScorer {
+            seed: mix(self.seed),
+            fast_field_iterator: fast_field_to_iter(&self.fields, segment_reader, &self.field_name)?,
+        })
+    }
+}
+
+pub struct SegmentRandomScorer {
+    seed: u64,
+    fast_field_iterator: Box<dyn FastFieldIterator>,
+}
+
+impl SegmentRandomScorer {
+    pub fn score(&mut self, doc_id: DocId) -> f64 {
+        self.fast_field_iterator.advance(doc_id);
+        // Upper 53 bits fit into the mantissa of `f64` exactly
+        (mix(self.seed ^ self.fast_field_iterator.value().to_bits()) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::collector::TopDocs;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{NumericOptions, FAST, INDEXED, STORED};
+    use tantivy::{doc, Index};
+
+    fn random_order(index: &Index, seed: u64) -> Vec<f64> {
+        let random_scorer = RandomScorer::new(seed, "id", &index.schema()).unwrap();
+        let searcher = index.reader().unwrap().searcher();
+        let top_docs = TopDocs::with_limit(100).tweak_score(move |segment_reader: &SegmentReader| {
+            let mut segment_random_scorer = random_scorer.get_for_segment_reader(segment_reader).unwrap();
+            move |doc_id: DocId, _| segment_random_scorer.score(doc_id)
+        });
+        searcher.search(&AllQuery, &top_docs).unwrap().into_iter().map(|(score, _)| score).collect()
+    }
+
+    #[test]
+    fn test_random_scorer() {
+        let mut schema_builder = Fields::builder();
+        let id = schema_builder.add_i64_field("id", FAST | INDEXED | STORED);
+        schema_builder.add_text_field("title", STORED);
+        schema_builder.add_u64_field("tags", NumericOptions::default().set_fast(Cardinality::MultiValues));
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut index_writer = index.writer(3_000_000).unwrap();
+        for id_value in 0..50i64 {
+            index_writer.add_document(doc!(id => id_value)).unwrap();
+            if id_value % 10 == 0 {
+                index_writer.commit().unwrap();
+            }
+        }
+        index_writer.commit().unwrap();
+
+        let scores = random_order(&index, 42);
+        assert_eq!(scores.len(), 50);
+        assert!(scores.iter().all(|score| (0.0..1.0).contains(score)));
+        assert_eq!(scores, random_order(&index, 42));
+        assert_ne!(scores, random_order(&index, 43));
+
+        // Scores do not depend on the layout of segments
+        index_writer.merge(&index.searchable_segment_ids().unwrap()).wait().unwrap();
+        index_writer.wait_merging_threads().unwrap();
+        assert_eq!(scores, random_order(&index, 42));
+
+        assert!(RandomScorer::new(42, "title", &index.schema()).is_err());
+        assert!(RandomScorer::new(42, "tags", &index.schema()).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::errors::{Error, SummaResult, ValidationError};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RankingModelDefinition {
+    Linear {
+        #[serde(default)]
+        bias: f64,
+        weights: HashMap<String, f64>,
+    },
+    Gbdt {
+        #[serde(default)]
+        base_score: f64,
+        trees: Vec<TreeNodeDefinition>,
+    },
+}
+
+/// Node of the tree in the format of XGBoost JSON dump, splits refer to features by their names
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TreeNodeDefinition {
+    Leaf {
+        nodeid: u32,
+        leaf: f64,
+    },
+    Split {
+        nodeid: u32,
+        split: String,
+        split_condition: f64,
+        yes: u32,
+        no: u32,
+        missing: Option<u32>,
+        children: Vec<TreeNodeDefinition>,
+    },
+}
+
+impl TreeNodeDefinition {
+    fn nodeid(&self) -> u32 {
+        match self {
+            TreeNodeDefinition::Leaf { nodeid, .. } | TreeNodeDefinition::Split { nodeid, .. } => *nodeid,
+        }
+    }
+}
+
+enum TreeNode {
+    Leaf(f64),
+    Split {
+        feature: usize,
+        threshold: f64,
+        yes: usize,
+        no: usize,
+        missing: usize,
+    },
+}
+
+enum Model {
+    Linear { bias: f64, weights: Vec<f64> },
+    Gbdt { base_score: f64, trees: Vec<Vec<TreeNode>> },
+}
+
+/// Ranking model trained offline and evaluated over the vector of named features
+///
+/// Linear models are defined as `{"type": "linear", "bias": 0.1, "weights": {"original_score": 1.0, "citations": 0.01}}`
+/// and ensembles of gradient boosted trees as `{"type": "gbdt", "base_score": 0.5, "trees": [...]}` where every tree
+/// is a JSON dump of XGBoost tree trained with named features. Documents go to `yes` branch if the feature is less than
+/// `split_condition` and to `missing` branch if the feature is `NaN`.
+pub struct RankingModel {
+    features: Vec<String>,
+    model: Model,
+}
+
+fn feature_position(features: &mut Vec<String>, feature: &str) -> usize {
+    match features.iter().position(|existing_feature| existing_feature == feature) {
+        Some(position) => position,
+        None => {
+            features.push(feature.to_owned());
+            features.len() - 1
+        }
+    }
+}
+
+/// Flattens the tree into `nodes` in the pre-order so the root always has position `0`
+fn flatten_tree(definition: &TreeNodeDefinition, features: &mut Vec<String>, nodes: &mut Vec<TreeNode>) -> SummaResult<usize> {
+    let position = nodes.len();
+    match definition {
+        TreeNodeDefinition::Leaf { leaf, .. } => nodes.push(TreeNode::Leaf(*leaf)),
+        TreeNodeDefinition::Split {
+            nodeid,
+            split,
+            split_condition,
+            yes,
+            no,
+            missing,
+            children,
+        } => {
+            let feature = feature_position(features, split);
+            nodes.push(TreeNode::Leaf(0.0));
+            let mut children_positions = HashMap::new();
+            for child in children {
+                children_positions.insert(child.nodeid(), flatten_tree(child, features, nodes)?);
+            }
+            let child_position = |child_nodeid: u32| {
+                children_positions
+                    .get(&child_nodeid)
+                    .copied()
+                    .ok_or_else(|| ValidationError::InvalidRankingModel(format!("node {} refers to unknown child {}", nodeid, child_nodeid)))
+            };
+            nodes[position] = TreeNode::Split {
+                feature,
+                threshold: *split_condition,
+                yes: child_position(*yes)?,
+                no: child_position(*no)?,
+                missing: child_position(missing.unwrap_or(*yes))?,
+            };
+        }
+    }
+    Ok(position)
+}
+
+fn predict_tree(nodes: &[TreeNode], features: &[f64]) -> f64 {
+    let mut position = 0;
+    loop {
+        match nodes[position] {
+            TreeNode::Leaf(value) => return value,
+            TreeNode::Split {
+                feature,
+                threshold,
+                yes,
+                no,
+                missing,
+            } => {
+                let value = features[feature];
+                position = if value.is_nan() {
+                    missing
+                } else if value < threshold {
+                    yes
+                } else {
+                    no
+                }
+            }
+        }
+    }
+}
+
+impl RankingModel {
+    pub fn from_json(json: &str) -> SummaResult<RankingModel> {
+        let definition: RankingModelDefinition = serde_json::from_str(json).map_err(|error| ValidationError::InvalidRankingModel(error.to_string()))?;
+        let mut features = vec![];
+        let model = match definition {
+            RankingModelDefinition::Linear { bias, weights } => {
+                let mut weights_vec = vec![];
+                for (feature, weight) in weights {
+                    feature_position(&mut features, &feature);
+                    weights_vec.push(weight);
+                }
+                Model::Linear { bias, weights: weights_vec }
+            }
+            RankingModelDefinition::Gbdt { base_score, trees } => Model::Gbdt {
+                base_score,
+                trees: trees
+                    .iter()
+                    .map(|tree| {
+                        let mut nodes = vec![];
+                        flatten_tree(tree, &mut features, &mut nodes)?;
+                        Ok(nodes)
+                    })
+                    .collect::<SummaResult<_>>()?,
+            },
+        };
+        Ok(RankingModel { features, model })
+    }
+
+    pub fn load(path: &Path) -> SummaResult<RankingModel> {
+        let json = std::fs::read_to_string(path).map_err(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => ValidationError::MissingPath(path.to_path_buf()).into(),
+            _ => Error::IO((error, Some(path.to_path_buf()))),
+        })?;
+        RankingModel::from_json(&json)
+    }
+
+    /// Names of features in the order expected by `predict`
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+
+    pub fn predict(&self, features: &[f64]) -> f64 {
+        match &self.model {
+            Model::Linear { bias, weights } => bias + weights.iter().zip(features).map(|(weight, feature)| weight * feature).sum::<f64>(),
+            Model::Gbdt { base_score, trees } => base_score + trees.iter().map(|tree| predict_tree(tree, features)).sum::<f64>(),
+        }
+    }
+}
+
+/// Lazily loads ranking models from `<models_path>/<model_name>.json` and caches them
+///
+/// Changed model files are picked up after the restart or under a new name.
+pub struct RankingModels {
+    models_path: PathBuf,
+    models: RwLock<HashMap<String, Arc<RankingModel>>>,
+}
+
+impl RankingModels {
+    pub fn new(models_path: &Path) -> RankingModels {
+        RankingModels {
+            models_path: models_path.to_path_buf(),
+            models: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, model_name: &str) -> SummaResult<Arc<RankingModel>> {
+        if let Some(model) = self.models.read().get(model_name) {
+            return Ok(model.clone());
+        }
+        if model_name.is_empty() || model_name.contains(['/', '\\']) || model_name.starts_with('.') {
+            return Err(Error::InvalidSyntax(format!("invalid model name {}", model_name)));
+        }
+        let model = Arc::new(RankingModel::load(&self.models_path.join(format!("{}.json", model_name)))?);
+        self.models.write().insert(model_name.to_owned(), model.clone());
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_model() {
+        let model = RankingModel::from_json(r#"{"type": "linear", "bias": 0.5, "weights": {"original_score": 2.0}}"#).unwrap();
+        assert_eq!(model.features(), &["original_score".to_owned()]);
+        assert_eq!(model.predict(&[1.5]), 3.5);
+    }
+
+    #[test]
+    fn test_gbdt_model() {
+        let model = RankingModel::from_json(
+            r#"{
+                "type": "gbdt",
+                "base_score": 0.5,
+                "trees": [
+                    {"nodeid": 0, "depth": 0, "split": "citations", "split_condition": 10, "yes": 1, "no": 2, "missing": 2, "children": [
+                        {"nodeid": 1, "leaf": -0.5},
+                        {"nodeid": 2, "depth": 1, "split": "original_score", "split_condition": 1.0, "yes": 3, "no": 4, "children": [
+                            {"nodeid": 3, "leaf": 0.25},
+                            {"nodeid": 4, "leaf": 1.0}
+                        ]}
+                    ]},
+                    {"nodeid": 0, "leaf": 0.125}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(model.features(), &["citations".to_owned(), "original_score".to_owned()]);
+        assert_eq!(model.predict(&[5.0, 2.0]), 0.125);
+        assert_eq!(model.predict(&[15.0, 0.5]), 0.875);
+        assert_eq!(model.predict(&[15.0, 2.0]), 1.625);
+        assert_eq!(model.predict(&[f64::NAN, 0.5]), 0.875);
+        assert!(RankingModel::from_json(
+            r#"{"type": "gbdt", "trees": [{"nodeid": 0, "split": "f", "split_condition": 1, "yes": 1, "no": 7, "children": [{"nodeid": 1, "leaf": 1}]}]}"#
+        )
+        .is_err());
+    }
+}
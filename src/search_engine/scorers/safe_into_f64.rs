@@ -27,3 +27,9 @@ impl SafeIntoF64 for DateTime {
         self.into_unix_timestamp() as f64
     }
 }
+
+impl SafeIntoF64 for f32 {
+    fn safe_into_f64(self) -> f64 {
+        self as f64
+    }
+}
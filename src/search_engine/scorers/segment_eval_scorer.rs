@@ -1,6 +1,9 @@
-use super::fast_field_iterator::{FastFieldIterator, FastFieldIteratorImpl};
+use super::fast_field_iterator::{FastFieldIterator, FastFieldIteratorImpl, MultiValuedFastFieldIteratorImpl};
+use super::functions::{call_function, variable_name, variable_source_name};
+use super::multi_value_aggregation::{AggregatedVariable, MultiValueAggregation};
 use crate::errors::{Error, SummaResult, ValidationError};
 use fasteval2::{Compiler, Evaler, Instruction};
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tantivy::schema::{FieldType, Schema as Fields};
 use tantivy::{DocId, Score, SegmentReader};
@@ -10,11 +13,13 @@ pub struct SegmentEvalScorer {
     compiled: Instruction,
     boxed_original_score: Box<f64>,
     _boxed_now: Box<f64>,
+    /// Never resized after creation so pointers to its items stay valid
+    _params_values: Vec<f64>,
     fast_fields_iterators: Vec<Box<dyn FastFieldIterator>>,
     namespace: fn(&str, Vec<f64>) -> Option<f64>,
 }
 
-fn fast_field_to_iter(fields: &Fields, segment_reader: &SegmentReader, field_name: &str) -> SummaResult<Box<dyn FastFieldIterator>> {
+pub(crate) fn fast_field_to_iter(fields: &Fields, segment_reader: &SegmentReader, field_name: &str) -> SummaResult<Box<dyn FastFieldIterator>> {
     let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
     let field_type = fields.get_field_entry(field).field_type();
     let fast_field = match field_type {
@@ -47,27 +52,50 @@ fn fast_field_to_iter(fields: &Fields, segment_reader: &SegmentReader, field_nam
     Ok(fast_field)
 }
 
+fn multi_valued_fast_field_to_iter(
+    fields: &Fields,
+    segment_reader: &SegmentReader,
+    field_name: &str,
+    aggregation: MultiValueAggregation,
+) -> SummaResult<Box<dyn FastFieldIterator>> {
+    let field = fields.get_field(field_name).ok_or_else(|| Error::FieldDoesNotExist(field_name.to_owned()))?;
+    let field_type = fields.get_field_entry(field).field_type();
+    let invalid_fast_field_type = |_| ValidationError::InvalidFastFieldType {
+        field: field_name.to_owned(),
+        field_type: field_type.to_owned(),
+    };
+    let fast_field = match field_type {
+        FieldType::U64(_) => {
+            MultiValuedFastFieldIteratorImpl::from_fast_field_reader(segment_reader.fast_fields().u64s(field).map_err(invalid_fast_field_type)?, aggregation)
+        }
+        FieldType::I64(_) => {
+            MultiValuedFastFieldIteratorImpl::from_fast_field_reader(segment_reader.fast_fields().i64s(field).map_err(invalid_fast_field_type)?, aggregation)
+        }
+        FieldType::F64(_) => {
+            MultiValuedFastFieldIteratorImpl::from_fast_field_reader(segment_reader.fast_fields().f64s(field).map_err(invalid_fast_field_type)?, aggregation)
+        }
+        FieldType::Date(_) => {
+            MultiValuedFastFieldIteratorImpl::from_fast_field_reader(segment_reader.fast_fields().dates(field).map_err(invalid_fast_field_type)?, aggregation)
+        }
+        field_type => return Err(Error::InvalidFieldType(field_name.to_owned(), field_type.to_owned())),
+    };
+    Ok(fast_field)
+}
+
 impl SegmentEvalScorer {
     #[inline]
-    pub fn for_segment(
+    pub(crate) fn for_segment(
         segment_reader: &SegmentReader,
         fields: &Fields,
         parser: &fasteval2::Parser,
         eval_expr: &str,
         var_names: &Vec<String>,
+        aggregated_variables: &[AggregatedVariable],
+        params: &HashMap<String, f64>,
     ) -> SummaResult<SegmentEvalScorer> {
         let mut slab = fasteval2::Slab::new();
 
-        let mut namespace = |name: &str, args: Vec<f64>| -> Option<f64> {
-            match name {
-                "fastsigm" => {
-                    let x = args[0].abs();
-                    let a = args.get(1).unwrap_or(&1f64);
-                    Some(x / (*a + x))
-                }
-                _ => None,
-            }
-        };
+        let mut namespace = |name: &str, args: Vec<f64>| -> Option<f64> { call_function(name, &args) };
 
         let boxed_original_score = Box::new(0f64);
         let boxed_now = Box::new(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64());
@@ -78,15 +106,37 @@ impl SegmentEvalScorer {
             slab.ps.add_unsafe_var("now".to_owned(), boxed_now.as_ref());
         }
 
+        // Set request params
+        let params_values: Vec<f64> = params.values().copied().collect();
+        for (param_name, value) in params.keys().zip(params_values.iter()) {
+            unsafe {
+                slab.ps.add_unsafe_var(variable_name(param_name), value);
+            }
+        }
+
         let mut fast_fields_iterators = vec![];
 
         // Set fast fields
         for var_name in var_names {
-            fast_fields_iterators.push(fast_field_to_iter(fields, segment_reader, var_name)?);
+            fast_fields_iterators.push(fast_field_to_iter(fields, segment_reader, variable_source_name(var_name))?);
             unsafe {
                 slab.ps.add_unsafe_var(var_name.to_owned(), fast_fields_iterators.last().unwrap().value());
             }
         }
+
+        // Set aggregations of multi-valued fast fields
+        for aggregated_variable in aggregated_variables {
+            fast_fields_iterators.push(multi_valued_fast_field_to_iter(
+                fields,
+                segment_reader,
+                &aggregated_variable.field_name,
+                aggregated_variable.aggregation,
+            )?);
+            unsafe {
+                slab.ps
+                    .add_unsafe_var(aggregated_variable.var_name.to_owned(), fast_fields_iterators.last().unwrap().value());
+            }
+        }
         let compiled = parser
             .parse(eval_expr, &mut slab.ps)?
             .from(&slab.ps)
@@ -97,10 +147,15 @@ impl SegmentEvalScorer {
             compiled,
             boxed_original_score,
             _boxed_now: boxed_now,
+            _params_values: params_values,
             fast_fields_iterators,
             namespace,
         })
     }
+
+    /// Scores the document, documents for which the expression cannot be evaluated because of invalid
+    /// decay params taken from fields are scored with `0`. Unknown functions and wrong numbers of arguments
+    /// are rejected earlier by `EvalScorer`
     pub(crate) fn score(&mut self, doc_id: DocId, original_score: Score) -> f64 {
         *self.boxed_original_score = original_score as f64;
         for fast_field_iterator in self.fast_fields_iterators.iter_mut() {
@@ -109,7 +164,7 @@ impl SegmentEvalScorer {
         if let fasteval2::IUnsafeVar { ptr, .. } = self.compiled {
             unsafe { *ptr }
         } else {
-            self.compiled.eval(&self.slab, &mut self.namespace).unwrap()
+            self.compiled.eval(&self.slab, &mut self.namespace).unwrap_or(0.0)
         }
     }
 }
@@ -0,0 +1,69 @@
+//! Scoring of term and phrase queries with similarities configured per field in `IndexConfig`
+
+mod similarity_phrase_query;
+mod similarity_term_query;
+mod term_weight;
+
+use crate::configs::Similarity;
+use std::collections::{HashMap, HashSet};
+use tantivy::query::{BooleanQuery, Occur, PhraseQuery, Query, TermQuery};
+use tantivy::schema::Field;
+
+pub use similarity_phrase_query::SimilarityPhraseQuery;
+pub use similarity_term_query::SimilarityTermQuery;
+
+/// Returns the same term looked up in several `Bm25f` fields if `boolean_query` is a disjunction produced
+/// by the query parser for a term and multiple default fields
+fn bm25f_terms(boolean_query: &BooleanQuery, similarities: &HashMap<Field, Similarity>) -> Option<Vec<(tantivy::Term, Similarity)>> {
+    if boolean_query.clauses().len() < 2 {
+        return None;
+    }
+    let terms: Vec<_> = boolean_query
+        .clauses()
+        .iter()
+        .map(|(occur, subquery)| match (occur, subquery.downcast_ref::<TermQuery>()) {
+            (Occur::Should, Some(term_query)) => match similarities.get(&term_query.term().field()) {
+                Some(similarity @ Similarity::Bm25f { .. }) => Some((term_query.term().clone(), similarity.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    let fields: HashSet<_> = terms.iter().map(|(term, _)| term.field()).collect();
+    if fields.len() == terms.len() && terms.iter().all(|(term, _)| term.value_bytes() == terms[0].0.value_bytes()) {
+        Some(terms)
+    } else {
+        None
+    }
+}
+
+/// Replaces `TermQuery` and `PhraseQuery` over fields having configured similarities with their similarity counterparts
+///
+/// Disjunctions of the same term over several fields with `Bm25f` similarity are scored together as a single BM25F field.
+/// Other queries including `BoostQuery` are left as is.
+pub(crate) fn apply_similarities(query: Box<dyn Query>, similarities: &HashMap<Field, Similarity>) -> Box<dyn Query> {
+    if similarities.is_empty() {
+        return query;
+    }
+    if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+        if let Some(similarity) = similarities.get(&term_query.term().field()) {
+            return Box::new(SimilarityTermQuery::new(vec![(term_query.term().clone(), similarity.clone())]));
+        }
+    } else if let Some(phrase_query) = query.downcast_ref::<PhraseQuery>() {
+        if let Some(similarity) = similarities.get(&phrase_query.field()) {
+            return Box::new(SimilarityPhraseQuery::new(phrase_query.clone(), similarity.clone()));
+        }
+    } else if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+        if let Some(terms) = bm25f_terms(boolean_query, similarities) {
+            return Box::new(SimilarityTermQuery::new(terms));
+        }
+        return Box::new(BooleanQuery::new(
+            boolean_query
+                .clauses()
+                .iter()
+                .map(|(occur, subquery)| (*occur, apply_similarities(subquery.box_clone(), similarities)))
+                .collect(),
+        ));
+    }
+    query
+}
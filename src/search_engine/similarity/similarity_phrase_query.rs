@@ -0,0 +1,117 @@
+use super::term_weight::{fieldnorm_reader, TermWeight};
+use crate::configs::Similarity;
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::{Postings, SegmentPostings};
+use tantivy::query::{Explanation, PhraseQuery, Query, Scorer, Weight};
+use tantivy::schema::IndexRecordOption;
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, Term};
+
+/// Matches documents like the wrapped `PhraseQuery` and scores them as a sum of scores of the phrase terms
+/// computed with the similarity of the field
+#[derive(Clone, Debug)]
+pub struct SimilarityPhraseQuery {
+    phrase_query: PhraseQuery,
+    similarity: Similarity,
+}
+
+impl SimilarityPhraseQuery {
+    pub fn new(phrase_query: PhraseQuery, similarity: Similarity) -> SimilarityPhraseQuery {
+        SimilarityPhraseQuery { phrase_query, similarity }
+    }
+}
+
+impl Query for SimilarityPhraseQuery {
+    fn weight(&self, searcher: &Searcher, _scoring_enabled: bool) -> tantivy::Result<Box<dyn Weight>> {
+        let terms = self.phrase_query.phrase_terms();
+        Ok(Box::new(SimilarityPhraseWeight {
+            phrase_weight: self.phrase_query.weight(searcher, false)?,
+            term_weights: terms
+                .iter()
+                .map(|term| TermWeight::for_term(searcher, term, &self.similarity))
+                .collect::<tantivy::Result<_>>()?,
+            terms,
+        }))
+    }
+
+    fn query_terms(&self, term_set: &mut std::collections::BTreeMap<Term, bool>) {
+        self.phrase_query.query_terms(term_set)
+    }
+}
+
+struct SimilarityPhraseWeight {
+    phrase_weight: Box<dyn Weight>,
+    terms: Vec<Term>,
+    term_weights: Vec<TermWeight>,
+}
+
+impl Weight for SimilarityPhraseWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let field = self.terms[0].field();
+        let inverted_index = reader.inverted_index(field)?;
+        let postings = self
+            .terms
+            .iter()
+            .map(|term| inverted_index.read_postings(term, IndexRecordOption::WithFreqs))
+            .collect::<std::io::Result<_>>()?;
+        Ok(Box::new(SimilarityPhraseScorer {
+            phrase_scorer: self.phrase_weight.scorer(reader, 1.0)?,
+            postings,
+            fieldnorm_reader: fieldnorm_reader(reader, field),
+            term_weights: self.term_weights.clone(),
+            boost,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!("Document #({}) does not match", doc)));
+        }
+        Ok(Explanation::new("SimilarityPhraseQuery", scorer.score()))
+    }
+}
+
+struct SimilarityPhraseScorer {
+    phrase_scorer: Box<dyn Scorer>,
+    postings: Vec<Option<SegmentPostings>>,
+    fieldnorm_reader: FieldNormReader,
+    term_weights: Vec<TermWeight>,
+    boost: Score,
+}
+
+impl DocSet for SimilarityPhraseScorer {
+    fn advance(&mut self) -> DocId {
+        self.phrase_scorer.advance()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.phrase_scorer.seek(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.phrase_scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.phrase_scorer.size_hint()
+    }
+}
+
+impl Scorer for SimilarityPhraseScorer {
+    fn score(&mut self) -> Score {
+        let doc = self.phrase_scorer.doc();
+        let fieldnorm = self.fieldnorm_reader.fieldnorm(doc);
+        let mut score = 0.0;
+        for (postings, term_weight) in self.postings.iter_mut().zip(&self.term_weights) {
+            if let Some(postings) = postings {
+                if postings.doc() < doc {
+                    postings.seek(doc);
+                }
+                if postings.doc() == doc {
+                    score += term_weight.score(postings.term_freq(), fieldnorm);
+                }
+            }
+        }
+        self.boost * score
+    }
+}
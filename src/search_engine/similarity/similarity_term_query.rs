@@ -0,0 +1,147 @@
+use super::term_weight::{bm25f_score, fieldnorm_reader, TermWeight};
+use crate::configs::Similarity;
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::{Postings, SegmentPostings};
+use tantivy::query::{EmptyScorer, Explanation, Query, Scorer, Weight};
+use tantivy::schema::IndexRecordOption;
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, Term, TERMINATED};
+
+/// Matches documents containing any of `terms` and scores them with similarities of the fields
+///
+/// A single term is scored by the similarity of its field. Several terms are expected to be the same text in different
+/// fields having `Bm25f` similarity and are scored together as a single BM25F field.
+#[derive(Clone, Debug)]
+pub struct SimilarityTermQuery {
+    terms: Vec<(Term, Similarity)>,
+}
+
+impl SimilarityTermQuery {
+    pub fn new(terms: Vec<(Term, Similarity)>) -> SimilarityTermQuery {
+        SimilarityTermQuery { terms }
+    }
+}
+
+impl Query for SimilarityTermQuery {
+    fn weight(&self, searcher: &Searcher, _scoring_enabled: bool) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(SimilarityTermWeight {
+            terms: self.terms.iter().map(|(term, _)| term.clone()).collect(),
+            term_weights: self
+                .terms
+                .iter()
+                .map(|(term, similarity)| TermWeight::for_term(searcher, term, similarity))
+                .collect::<tantivy::Result<_>>()?,
+        }))
+    }
+
+    fn query_terms(&self, term_set: &mut std::collections::BTreeMap<Term, bool>) {
+        for (term, _) in &self.terms {
+            term_set.insert(term.clone(), false);
+        }
+    }
+}
+
+struct SimilarityTermWeight {
+    terms: Vec<Term>,
+    term_weights: Vec<TermWeight>,
+}
+
+impl SimilarityTermWeight {
+    fn similarity_scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Option<SimilarityTermScorer>> {
+        let mut postings = vec![];
+        for (position, term) in self.terms.iter().enumerate() {
+            if let Some(segment_postings) = reader.inverted_index(term.field())?.read_postings(term, IndexRecordOption::WithFreqs)? {
+                postings.push((segment_postings, fieldnorm_reader(reader, term.field()), position));
+            }
+        }
+        if postings.is_empty() {
+            return Ok(None);
+        }
+        let mut scorer = SimilarityTermScorer {
+            postings,
+            term_weights: self.term_weights.clone(),
+            boost,
+            doc: TERMINATED,
+        };
+        scorer.doc = scorer.min_doc();
+        Ok(Some(scorer))
+    }
+}
+
+impl Weight for SimilarityTermWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        Ok(match self.similarity_scorer(reader, boost)? {
+            Some(scorer) => Box::new(scorer),
+            None => Box::new(EmptyScorer),
+        })
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!("Document #({}) does not match", doc)));
+        }
+        Ok(Explanation::new("SimilarityTermQuery", scorer.score()))
+    }
+}
+
+struct SimilarityTermScorer {
+    postings: Vec<(SegmentPostings, FieldNormReader, usize)>,
+    term_weights: Vec<TermWeight>,
+    boost: Score,
+    doc: DocId,
+}
+
+impl SimilarityTermScorer {
+    fn min_doc(&self) -> DocId {
+        self.postings.iter().map(|(postings, _, _)| postings.doc()).min().unwrap_or(TERMINATED)
+    }
+}
+
+impl DocSet for SimilarityTermScorer {
+    fn advance(&mut self) -> DocId {
+        for (postings, _, _) in self.postings.iter_mut() {
+            if postings.doc() == self.doc {
+                postings.advance();
+            }
+        }
+        self.doc = self.min_doc();
+        self.doc
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        for (postings, _, _) in self.postings.iter_mut() {
+            if postings.doc() < target {
+                postings.seek(target);
+            }
+        }
+        self.doc = self.min_doc();
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.postings.iter().map(|(postings, _, _)| postings.size_hint()).max().unwrap_or(0)
+    }
+}
+
+impl Scorer for SimilarityTermScorer {
+    fn score(&mut self) -> Score {
+        let doc = self.doc;
+        let freqs = self
+            .postings
+            .iter()
+            .filter(|(postings, _, _)| postings.doc() == doc)
+            .map(|(postings, fieldnorm_reader, position)| (*position, postings.term_freq(), fieldnorm_reader.fieldnorm(doc)));
+        let score = if self.term_weights.len() == 1 {
+            freqs
+                .map(|(position, term_freq, fieldnorm)| self.term_weights[position].score(term_freq, fieldnorm))
+                .sum()
+        } else {
+            bm25f_score(&self.term_weights, freqs)
+        };
+        self.boost * score
+    }
+}
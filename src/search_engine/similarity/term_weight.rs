@@ -0,0 +1,102 @@
+use crate::configs::Similarity;
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::schema::Field;
+use tantivy::{Score, Searcher, SegmentReader, Term};
+
+/// Collection-wide statistics of the term together with the similarity of its field
+#[derive(Clone, Debug)]
+pub(crate) struct TermWeight {
+    similarity: Similarity,
+    idf: Score,
+    average_fieldnorm: Score,
+}
+
+/// Returns the number of documents and the average fieldnorm of the field in the same way as Tantivy BM25 does
+fn field_statistics(searcher: &Searcher, field: Field) -> tantivy::Result<(u64, Score)> {
+    let mut total_num_tokens = 0u64;
+    let mut total_num_docs = 0u64;
+    for segment_reader in searcher.segment_readers() {
+        total_num_tokens += segment_reader.inverted_index(field)?.total_num_tokens();
+        total_num_docs += u64::from(segment_reader.max_doc());
+    }
+    let average_fieldnorm = if total_num_docs > 0 {
+        total_num_tokens as Score / total_num_docs as Score
+    } else {
+        1.0
+    };
+    Ok((total_num_docs, average_fieldnorm))
+}
+
+/// Returns fieldnorm reader of the field or constant reader if fieldnorms are not recorded for the field
+pub(crate) fn fieldnorm_reader(segment_reader: &SegmentReader, field: Field) -> FieldNormReader {
+    segment_reader
+        .get_fieldnorms_reader(field)
+        .unwrap_or_else(|_| FieldNormReader::constant(segment_reader.max_doc(), 1))
+}
+
+/// BM25 saturation of the length-normalized term frequency `tf`
+fn bm25_saturation(tf: Score, k1: Score) -> Score {
+    tf * (k1 + 1.0) / (tf + k1)
+}
+
+impl TermWeight {
+    pub fn for_term(searcher: &Searcher, term: &Term, similarity: &Similarity) -> tantivy::Result<TermWeight> {
+        let (total_num_docs, average_fieldnorm) = field_statistics(searcher, term.field())?;
+        let doc_freq = searcher.doc_freq(term)?.min(total_num_docs);
+        let idf = match similarity {
+            Similarity::Bm25 { .. } | Similarity::Bm25f { .. } => (1.0 + ((total_num_docs - doc_freq) as Score + 0.5) / (doc_freq as Score + 0.5)).ln(),
+            Similarity::TfIdf => 1.0 + (total_num_docs as Score / (doc_freq as Score + 1.0)).ln(),
+        };
+        Ok(TermWeight {
+            similarity: similarity.clone(),
+            idf,
+            average_fieldnorm,
+        })
+    }
+
+    pub fn idf(&self) -> Score {
+        self.idf
+    }
+
+    pub fn similarity(&self) -> &Similarity {
+        &self.similarity
+    }
+
+    /// Term frequency weighted and normalized by the length of the field for BM25F
+    pub fn bm25f_tf(&self, term_freq: u32, fieldnorm: u32) -> Score {
+        match self.similarity {
+            Similarity::Bm25f { weight, b, .. } => {
+                weight * term_freq as Score / (1.0 - b + b * fieldnorm as Score / self.average_fieldnorm.max(Score::EPSILON))
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Scores the term occurring `term_freq` times in the field of `fieldnorm` length
+    pub fn score(&self, term_freq: u32, fieldnorm: u32) -> Score {
+        match self.similarity {
+            Similarity::Bm25 { k1, b } => {
+                let norm = k1 * (1.0 - b + b * fieldnorm as Score / self.average_fieldnorm.max(Score::EPSILON));
+                self.idf * term_freq as Score * (k1 + 1.0) / (term_freq as Score + norm)
+            }
+            Similarity::TfIdf => (term_freq as Score).sqrt() * self.idf * self.idf / (fieldnorm.max(1) as Score).sqrt(),
+            Similarity::Bm25f { k1, .. } => self.idf * bm25_saturation(self.bm25f_tf(term_freq, fieldnorm), k1),
+        }
+    }
+}
+
+/// Scores the term occurring in several fields as a single BM25F field
+///
+/// `freqs` yields positions of matched terms in `term_weights` with term frequencies and fieldnorms.
+/// Weighted term frequencies of all fields are summed before the saturation. The term is considered
+/// as rare as in the field where it is the most frequent and `k1` is taken from the first field.
+pub(crate) fn bm25f_score(term_weights: &[TermWeight], freqs: impl Iterator<Item = (usize, u32, u32)>) -> Score {
+    let tf: Score = freqs
+        .map(|(position, term_freq, fieldnorm)| term_weights[position].bm25f_tf(term_freq, fieldnorm))
+        .sum();
+    let idf = term_weights.iter().map(TermWeight::idf).fold(Score::MAX, Score::min);
+    match term_weights.first().map(TermWeight::similarity) {
+        Some(Similarity::Bm25f { k1, .. }) => idf * bm25_saturation(tf, *k1),
+        _ => 0.0,
+    }
+}
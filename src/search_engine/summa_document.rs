@@ -1,7 +1,10 @@
 use crate::errors::{Error, SummaResult, ValidationError};
-use serde_json::Value as JsonValue;
+use crate::proto;
+use crate::search_engine::queries::encode_vector;
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use std::collections::HashMap;
 use std::str::from_utf8;
-use tantivy::schema::{Facet, FieldType, Schema as Fields, Value};
+use tantivy::schema::{Facet, Field, FieldType, Schema as Fields, Value};
 use tantivy::tokenizer::PreTokenizedString;
 use tantivy::{DateTime, Document};
 use time::format_description::well_known::Rfc3339;
@@ -11,9 +14,133 @@ use time::OffsetDateTime;
 pub enum SummaDocument<'a> {
     BoundJsonBytes((&'a Fields, &'a [u8])),
     UnboundJsonBytes(&'a [u8]),
+    UnboundBytes((proto::DocumentFormat, &'a [u8])),
     TantivyDocument(Document),
 }
 
+/// Value of binary formats, it keeps byte strings as is unlike `serde_json::Value`
+///
+/// MessagePack documents are decoded into it too, so both binary formats share the conversion into `tantivy::Document`
+type BinaryValue = ciborium::value::Value;
+
+/// Converts the value of a binary format into JSON
+///
+/// Byte strings become base64 strings if `encode_bytes` is set and are rejected otherwise
+fn binary_value_to_json(value: BinaryValue, encode_bytes: bool) -> Result<JsonValue, ValueParsingError> {
+    Ok(match value {
+        BinaryValue::Integer(integer) => i64::try_from(integer)
+            .map(JsonValue::from)
+            .or_else(|_| u64::try_from(integer).map(JsonValue::from))
+            .map_err(|_| ValueParsingError::OverflowError {
+                expected: "i64 or u64",
+                json: JsonValue::String(i128::from(integer).to_string()),
+            })?,
+        BinaryValue::Bytes(bytes) if encode_bytes => JsonValue::String(base64::encode(bytes)),
+        BinaryValue::Bytes(_) => return Err(ValueParsingError::UnexpectedBytes { expected: "a non-bytes value" }),
+        BinaryValue::Float(value) => JsonNumber::from_f64(value).map(JsonValue::Number).unwrap_or(JsonValue::Null),
+        BinaryValue::Text(text) => JsonValue::String(text),
+        BinaryValue::Bool(value) => JsonValue::Bool(value),
+        BinaryValue::Null => JsonValue::Null,
+        BinaryValue::Tag(_, value) => binary_value_to_json(*value, encode_bytes)?,
+        BinaryValue::Array(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .map(|item| binary_value_to_json(item, encode_bytes))
+                .collect::<Result<_, _>>()?,
+        ),
+        BinaryValue::Map(entries) => JsonValue::Object(binary_map_to_json(entries, encode_bytes)?),
+        _ => {
+            return Err(ValueParsingError::TypeError {
+                expected: "a supported value",
+                json: JsonValue::Null,
+            })
+        }
+    })
+}
+
+/// Encodes the array of numbers into the value of the vector field of `dimension`
+///
+/// Already encoded vectors are accepted as base64 strings, alone or wrapped into arrays like in stored documents
+fn vector_from_json(dimension: usize, json_value: JsonValue) -> Result<Value, ValueParsingError> {
+    let json_items = match json_value {
+        JsonValue::Array(mut json_items) if json_items.len() == 1 && json_items[0].is_string() => return vector_from_json(dimension, json_items.remove(0)),
+        JsonValue::Array(json_items) => json_items,
+        JsonValue::String(base64) => {
+            let bytes = base64::decode(&base64).map_err(|_| ValueParsingError::InvalidBase64 { base64 })?;
+            if bytes.len() != dimension * 4 {
+                return Err(ValueParsingError::DimensionError {
+                    expected: dimension,
+                    got: bytes.len() / 4,
+                });
+            }
+            return Ok(Value::Bytes(bytes));
+        }
+        JsonValue::Null => return Err(ValueParsingError::NullValueError),
+        json_value => {
+            return Err(ValueParsingError::TypeError {
+                expected: "an array of numbers",
+                json: json_value,
+            })
+        }
+    };
+    if json_items.len() != dimension {
+        return Err(ValueParsingError::DimensionError {
+            expected: dimension,
+            got: json_items.len(),
+        });
+    }
+    let vector = json_items
+        .into_iter()
+        .map(|json_item| match json_item.as_f64().map(|value| value as f32) {
+            Some(value) if value.is_finite() => Ok(value),
+            _ => Err(ValueParsingError::TypeError {
+                expected: "a finite f32 number",
+                json: json_item,
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Bytes(encode_vector(&vector)))
+}
+
+fn binary_map_to_json(entries: Vec<(BinaryValue, BinaryValue)>, encode_bytes: bool) -> Result<JsonMap<String, JsonValue>, ValueParsingError> {
+    entries
+        .into_iter()
+        .map(|(key, value)| Ok((binary_key(key)?, binary_value_to_json(value, encode_bytes)?)))
+        .collect()
+}
+
+fn binary_key(key: BinaryValue) -> Result<String, ValueParsingError> {
+    match key {
+        BinaryValue::Text(key) => Ok(key),
+        key => Err(ValueParsingError::TypeError {
+            expected: "a string key",
+            json: binary_value_to_json(key, true)?,
+        }),
+    }
+}
+
+/// Converts `google.protobuf.Value` into JSON, integral numbers become integers
+fn protobuf_value_to_json(value: prost_types::Value) -> JsonValue {
+    match value.kind {
+        None | Some(prost_types::value::Kind::NullValue(_)) => JsonValue::Null,
+        Some(prost_types::value::Kind::NumberValue(number)) => {
+            if number.fract() == 0.0 && number >= i64::MIN as f64 && number < i64::MAX as f64 {
+                JsonValue::from(number as i64)
+            } else {
+                JsonNumber::from_f64(number).map(JsonValue::Number).unwrap_or(JsonValue::Null)
+            }
+        }
+        Some(prost_types::value::Kind::StringValue(text)) => JsonValue::String(text),
+        Some(prost_types::value::Kind::BoolValue(value)) => JsonValue::Bool(value),
+        Some(prost_types::value::Kind::StructValue(value)) => JsonValue::Object(protobuf_struct_to_json(value)),
+        Some(prost_types::value::Kind::ListValue(list)) => JsonValue::Array(list.values.into_iter().map(protobuf_value_to_json).collect()),
+    }
+}
+
+fn protobuf_struct_to_json(value: prost_types::Struct) -> JsonMap<String, JsonValue> {
+    value.fields.into_iter().map(|(key, value)| (key, protobuf_value_to_json(value))).collect()
+}
+
 /// Possible error that may occur while parsing a field value
 /// At this point the JSON is known to be valid.
 #[derive(thiserror::Error, Debug)]
@@ -24,6 +151,10 @@ pub enum ValueParsingError {
     TypeError { expected: &'static str, json: JsonValue },
     #[error("invalid_base64: {base64}")]
     InvalidBase64 { base64: String },
+    #[error("unexpected_bytes: <expected: {expected}>")]
+    UnexpectedBytes { expected: &'static str },
+    #[error("dimension_error: <expected: {expected}, got: {got}>")]
+    DimensionError { expected: usize, got: usize },
     #[error("null_value_error")]
     NullValueError,
 }
@@ -35,9 +166,15 @@ pub enum DocumentParsingError {
     /// The payload given is not valid JSON.
     #[error("The provided string is not valid JSON")]
     InvalidJson(String),
+    /// The payload given is not a valid object encoded in the binary format.
+    #[error("The provided bytes are not valid {format:?} object: {error}")]
+    InvalidEncoding { format: proto::DocumentFormat, error: String },
     /// One of the value node could not be parsed.
     #[error("The field '{0:?}' could not be parsed: {1:?}")]
     ValueError(String, ValueParsingError),
+    /// The document has keys that are absent in the schema.
+    #[error("The fields {0:?} are absent in the schema")]
+    UnknownFields(Vec<String>),
 }
 
 impl<'a> SummaDocument<'a> {
@@ -140,20 +277,150 @@ impl<'a> SummaDocument<'a> {
         }
     }
 
+    /// Parse a json-object from UTF-8 bytes
+    pub fn parse_json_object(json_bytes: &[u8]) -> SummaResult<serde_json::Map<String, JsonValue>> {
+        let text_document = from_utf8(json_bytes).map_err(ValidationError::Utf8)?;
+        Ok(serde_json::from_str(text_document).map_err(|_| DocumentParsingError::InvalidJson(text_document.to_owned()))?)
+    }
+
+    /// Decode entries of the object encoded in binary `format`
+    fn parse_binary_object(format: proto::DocumentFormat, bytes: &[u8]) -> SummaResult<Vec<(BinaryValue, BinaryValue)>> {
+        let invalid_encoding = |error: String| DocumentParsingError::InvalidEncoding { format, error };
+        let binary_value = match format {
+            proto::DocumentFormat::Cbor => ciborium::de::from_reader::<BinaryValue, _>(bytes).map_err(|error| invalid_encoding(error.to_string()))?,
+            proto::DocumentFormat::MessagePack => rmp_serde::from_slice::<BinaryValue>(bytes).map_err(|error| invalid_encoding(error.to_string()))?,
+            format => return Err(invalid_encoding(format!("{:?} is not a binary format", format)).into()),
+        };
+        match binary_value {
+            BinaryValue::Map(entries) => Ok(entries),
+            binary_value => Err(invalid_encoding(format!("expected an object, got: {:?}", binary_value)).into()),
+        }
+    }
+
+    /// Parse a json-object from bytes encoded in `format`
+    ///
+    /// Byte strings of binary formats become base64 strings
+    pub fn parse_json_object_with_format(format: proto::DocumentFormat, bytes: &[u8]) -> SummaResult<serde_json::Map<String, JsonValue>> {
+        match format {
+            proto::DocumentFormat::Json => SummaDocument::parse_json_object(bytes),
+            proto::DocumentFormat::Cbor | proto::DocumentFormat::MessagePack => {
+                Ok(
+                    binary_map_to_json(SummaDocument::parse_binary_object(format, bytes)?, true).map_err(|error| DocumentParsingError::InvalidEncoding {
+                        format,
+                        error: error.to_string(),
+                    })?,
+                )
+            }
+            proto::DocumentFormat::ProtobufStruct => {
+                let protobuf_struct: prost_types::Struct = prost::Message::decode(bytes).map_err(|error| DocumentParsingError::InvalidEncoding {
+                    format,
+                    error: error.to_string(),
+                })?;
+                Ok(protobuf_struct_to_json(protobuf_struct))
+            }
+        }
+    }
+
+    /// Build a document object from bytes encoded in binary `format` without conversion into JSON
+    ///
+    /// Byte strings are accepted only by bytes fields, arrays of numbers put into `vector_fields` are encoded into vectors.
+    /// Returns the document and keys that are absent in the schema
+    pub fn parse_binary_document(
+        &self,
+        schema: &Fields,
+        vector_fields: &HashMap<Field, usize>,
+        format: proto::DocumentFormat,
+        bytes: &[u8],
+    ) -> SummaResult<(Document, Vec<String>)> {
+        let mut doc = Document::default();
+        let mut unknown_fields = vec![];
+        for (key, binary_value) in SummaDocument::parse_binary_object(format, bytes)? {
+            let field_name = binary_key(key).map_err(|error| DocumentParsingError::InvalidEncoding {
+                format,
+                error: error.to_string(),
+            })?;
+            match schema.get_field(&field_name) {
+                Some(field) => {
+                    let field_type = schema.get_field_entry(field).field_type();
+                    let values = match vector_fields.get(&field) {
+                        Some(dimension) => binary_value_to_json(binary_value, true)
+                            .and_then(|json_value| vector_from_json(*dimension, json_value))
+                            .map(|value| vec![value]),
+                        None => self.values_from_binary(field_type, binary_value),
+                    };
+                    match values {
+                        Ok(values) => values.into_iter().for_each(|value| doc.add_field_value(field, value)),
+                        Err(ValueParsingError::NullValueError) => continue,
+                        Err(error) => return Err(DocumentParsingError::ValueError(field_name.to_owned(), error).into()),
+                    }
+                }
+                None => unknown_fields.push(field_name),
+            }
+        }
+        unknown_fields.sort();
+        Ok((doc, unknown_fields))
+    }
+
+    /// Parse values of the field from the value of binary format
+    fn values_from_binary(&self, field_type: &FieldType, binary_value: BinaryValue) -> Result<Vec<Value>, ValueParsingError> {
+        match binary_value {
+            BinaryValue::Array(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.extend(self.value_from_binary(field_type, item)?);
+                }
+                Ok(values)
+            }
+            binary_value => Ok(self.value_from_binary(field_type, binary_value)?.into_iter().collect()),
+        }
+    }
+
+    /// Parse single value of binary format, `None` for nulls
+    fn value_from_binary(&self, field_type: &FieldType, binary_value: BinaryValue) -> Result<Option<Value>, ValueParsingError> {
+        let json = match (field_type, binary_value) {
+            (FieldType::Bytes(_), BinaryValue::Bytes(bytes)) => return Ok(Some(Value::Bytes(bytes))),
+            (field_type, BinaryValue::Bytes(_)) => {
+                return Err(ValueParsingError::UnexpectedBytes {
+                    expected: field_type.value_type().name(),
+                })
+            }
+            (field_type, BinaryValue::Tag(_, binary_value)) => return self.value_from_binary(field_type, *binary_value),
+            (_, binary_value) => binary_value_to_json(binary_value, false)?,
+        };
+        match self.value_from_json(field_type, json) {
+            Ok(value) => Ok(Some(value)),
+            Err(ValueParsingError::NullValueError) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
     /// Build a document object from a json-object.
     pub fn parse_document(&self, schema: &Fields, doc_json: &str) -> SummaResult<Document> {
         let json_obj: serde_json::Map<String, JsonValue> =
             serde_json::from_str(doc_json).map_err(|_| DocumentParsingError::InvalidJson(doc_json.to_owned()))?;
-        self.json_object_to_doc(schema, json_obj)
+        self.json_object_to_doc(schema, &HashMap::new(), json_obj)
     }
 
-    /// Build a document object from a json-object.
-    pub fn json_object_to_doc(&self, schema: &Fields, json_obj: serde_json::Map<String, JsonValue>) -> SummaResult<Document> {
+    /// Build a document object from a json-object, arrays of numbers put into `vector_fields` are encoded into vectors
+    pub fn json_object_to_doc(
+        &self,
+        schema: &Fields,
+        vector_fields: &HashMap<Field, usize>,
+        json_obj: serde_json::Map<String, JsonValue>,
+    ) -> SummaResult<Document> {
         let mut doc = Document::default();
         for (field_name, json_value) in json_obj {
             if let Some(field) = schema.get_field(&field_name) {
                 let field_entry = schema.get_field_entry(field);
                 let field_type = field_entry.field_type();
+                if let Some(dimension) = vector_fields.get(&field) {
+                    match vector_from_json(*dimension, json_value) {
+                        Ok(value) => doc.add_field_value(field, value),
+                        Err(ValueParsingError::NullValueError) => continue,
+                        Err(error) => return Err(DocumentParsingError::ValueError(field_name.to_owned(), error).into()),
+                    }
+                    continue;
+                }
                 match json_value {
                     JsonValue::Array(json_items) => {
                         for json_item in json_items {
@@ -185,7 +452,7 @@ impl<'a> TryInto<Document> for SummaDocument<'a> {
                 let text_document = from_utf8(json_bytes).map_err(ValidationError::Utf8)?;
                 Ok(self.parse_document(schema, text_document)?)
             }
-            SummaDocument::UnboundJsonBytes(_) => Err(Error::UnboundDocument),
+            SummaDocument::UnboundJsonBytes(_) | SummaDocument::UnboundBytes(_) => Err(Error::UnboundDocument),
             SummaDocument::TantivyDocument(document) => Ok(document),
         }
     }
@@ -1,16 +1,21 @@
 use crate::configs::{ApplicationConfigHolder, ConsumerConfig, IndexConfigBuilder, IndexConfigProxy, IndexEngine};
 use crate::errors::{Error, SummaResult, ValidationError};
 use crate::proto;
-use crate::requests::{AlterIndexRequest, CreateConsumerRequest, CreateIndexRequest, DeleteConsumerRequest, DeleteIndexRequest};
-use crate::search_engine::IndexHolder;
+use crate::requests::{AlterIndexRequest, CreateConsumerRequest, CreateIndexRequest, DeleteConsumerRequest, DeleteIndexRequest, ReindexRequest};
+use crate::search_engine::{extend_fields, reindex_segment, IndexBulkResult, IndexHolder};
 use crate::utils::sync::{Handler, OwningHandler};
 use futures_util::future::join_all;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use tantivy::query::Weight;
 use tantivy::IndexSettings;
-use tracing::instrument;
+use tokio::sync::mpsc;
+use tracing::{info, info_span, instrument, warn, Instrument};
+
+/// Number of progress messages of reindexing kept until they are received
+const REINDEX_PROGRESS_BUFFER_SIZE: usize = 16;
 
 /// The main struct responsible for indices lifecycle. Here lives indices creation and deletion as well as committing and indexing new documents.
 #[derive(Clone, Default, Debug)]
@@ -106,7 +111,15 @@ impl IndexService {
             .primary_key(create_index_request.primary_key.to_owned())
             .default_fields(create_index_request.default_fields.clone())
             .multi_fields(HashSet::from_iter(create_index_request.multi_fields.clone().into_iter()))
+            .similarities(create_index_request.similarities.clone())
             .stop_words(create_index_request.stop_words.clone())
+            .expiration(create_index_request.expiration.clone())
+            .flatten_nested(create_index_request.flatten_nested)
+            .ingest_pipeline(create_index_request.ingest_pipeline.clone())
+            .unknown_fields(create_index_request.unknown_fields)
+            .version_field(create_index_request.version_field.clone())
+            .version_conflict(create_index_request.version_conflict)
+            .vector_fields(create_index_request.vector_fields.clone())
             .autocommit_interval_ms(create_index_request.autocommit_interval_ms);
         if let Some(writer_threads) = create_index_request.writer_threads {
             index_config_builder.writer_threads(writer_threads);
@@ -128,6 +141,7 @@ impl IndexService {
     /// Create consumer and insert it into the consumer registry. Add it to the `IndexHolder` afterwards.
     #[instrument(skip_all)]
     pub async fn create_index(&self, create_index_request: CreateIndexRequest) -> SummaResult<Handler<IndexHolder>> {
+        create_index_request.validate()?;
         self.insert_config(&create_index_request)?;
         let index_settings = IndexSettings {
             docstore_compression: create_index_request.compression,
@@ -149,22 +163,67 @@ impl IndexService {
     }
 
     /// Alters index
+    ///
+    /// Adding fields reopens `IndexHolder` with the extended schema, so the index is unavailable until it is set up again.
+    /// `IndexHolder` is taken out of `index_holders` and put back after altering, so other indices are not locked meanwhile.
+    /// If altering fails, the original `IndexHolder` is put back, see `IndexHolder::alter_fields`
     #[instrument(skip_all)]
     pub async fn alter_index(&self, alter_index_request: AlterIndexRequest) -> SummaResult<Handler<IndexHolder>> {
-        let index_holder = self.get_index_holder_by_name(&alter_index_request.index_name)?;
+        let AlterIndexRequest {
+            index_name,
+            compression,
+            sort_by_field,
+            added_fields,
+        } = alter_index_request;
+        let index_holder = self.get_index_holder_by_name(&index_name)?;
+        let fields = added_fields
+            .map(|added_fields| extend_fields(index_holder.fields(), &added_fields))
+            .transpose()?;
         let index_updater = index_holder.index_updater();
-        let mut index_updater = index_updater.write();
-        if let Some(compression) = alter_index_request.compression {
-            index_updater.index_mut().settings_mut().docstore_compression = compression
-        }
-        if let Some(sort_by_field) = alter_index_request.sort_by_field {
-            index_updater.index_mut().settings_mut().sort_by_field = match sort_by_field.field.as_str() {
-                "" => None,
-                _ => Some(sort_by_field),
+        // Settings are changed and committed by the blocking thread, so the lock of `IndexUpdater` is not held across `.await`
+        tokio::task::spawn_blocking(move || -> SummaResult<()> {
+            let mut index_updater = index_updater.write();
+            if let Some(compression) = compression {
+                index_updater.index_mut().settings_mut().docstore_compression = compression
+            }
+            if let Some(sort_by_field) = sort_by_field {
+                index_updater.index_mut().settings_mut().sort_by_field = match sort_by_field.field.as_str() {
+                    "" => None,
+                    _ => Some(sort_by_field),
+                }
+            }
+            tokio::runtime::Handle::current().block_on(index_updater.commit())?;
+            Ok(())
+        })
+        .await??;
+        match fields {
+            None => Ok(index_holder),
+            Some(fields) => {
+                // Own handler must be released before waiting for the rest ones
+                drop(index_holder);
+                let index_holder = self
+                    .index_holders
+                    .write()
+                    .remove(&index_name)
+                    .ok_or_else(|| ValidationError::MissingIndex(index_name.to_owned()))?;
+                let inner = tokio::task::spawn_blocking(move || index_holder.into_inner()).await?;
+                let insert_index_holder = |index_holder: IndexHolder| {
+                    let owning_handler = OwningHandler::new(index_holder);
+                    let handler = owning_handler.handler();
+                    self.index_holders.write().insert(index_name.to_owned(), owning_handler);
+                    handler
+                };
+                match inner.alter_fields(fields).await {
+                    Ok(index_holder) => Ok(insert_index_holder(index_holder)),
+                    Err((error, original_index_holder)) => {
+                        if let Some(original_index_holder) = original_index_holder {
+                            insert_index_holder(original_index_holder);
+                        }
+                        Err(error)
+                    }
+                }
             }
         }
-        index_updater.commit().await?;
-        Ok(index_holder)
     }
 
     fn check_delete_conditions(&self, aliases: &[String], index_holder: &IndexHolder) -> SummaResult<()> {
@@ -210,6 +269,85 @@ impl IndexService {
         Ok(delete_index_result)
     }
 
+    /// Copies stored documents of the source index into the target index in the background
+    ///
+    /// Documents are read segment by segment from the snapshot of the source index taken on the call and passed through
+    /// `DocumentParser` of the target index. The returned receiver gets the progress after every segment and the final
+    /// progress after the target index is committed and `index_alias` is set. Dropping the receiver does not stop reindexing
+    #[instrument(skip_all, fields(source_index_alias = ?reindex_request.source_index_alias, target_index_alias = ?reindex_request.target_index_alias))]
+    pub fn reindex(&self, reindex_request: ReindexRequest) -> SummaResult<mpsc::Receiver<SummaResult<proto::ReindexProgress>>> {
+        let source_index_holder = self.get_index_holder(&reindex_request.source_index_alias)?;
+        let target_index_holder = self.get_index_holder(&reindex_request.target_index_alias)?;
+        if source_index_holder.index_name() == target_index_holder.index_name() {
+            return Err(ValidationError::SameIndex(source_index_holder.index_name().to_owned()).into());
+        }
+        let searcher = Arc::new(source_index_holder.index_reader().searcher());
+        let weight: Option<Arc<dyn Weight>> = reindex_request
+            .query
+            .map(|query| Ok::<_, Error>(Arc::from(source_index_holder.parse_query(&query)?.weight(&searcher, false)?)))
+            .transpose()?;
+        let field_mapping = Arc::new(reindex_request.field_mapping);
+        let index_alias = reindex_request.index_alias;
+        let application_config = self.application_config.clone();
+        let (progress_sender, progress_receiver) = mpsc::channel(REINDEX_PROGRESS_BUFFER_SIZE);
+        tokio::spawn(
+            async move {
+                let total_segments = searcher.segment_readers().len() as u64;
+                let mut result = IndexBulkResult::default();
+                let progress = |processed_segments: u64, result: &mut IndexBulkResult| proto::ReindexProgress {
+                    processed_segments,
+                    total_segments,
+                    success_docs: result.success_docs,
+                    failed_docs: result.failed_docs,
+                    skipped_docs: result.skipped_docs,
+                    errors: std::mem::take(&mut result.errors),
+                    finished: false,
+                    old_index_name: None,
+                };
+                let reindexing = async {
+                    for segment_ord in 0..total_segments {
+                        let (searcher, weight, field_mapping, target_index_holder) =
+                            (searcher.clone(), weight.clone(), field_mapping.clone(), target_index_holder.clone());
+                        result = tokio::task::spawn_blocking(move || -> SummaResult<IndexBulkResult> {
+                            reindex_segment(
+                                searcher.schema(),
+                                searcher.segment_reader(segment_ord as u32),
+                                weight.as_deref(),
+                                &field_mapping,
+                                &target_index_holder.index_updater().read(),
+                                &mut result,
+                            )?;
+                            Ok(result)
+                        })
+                        .await??;
+                        info!(action = "reindexed_segment", segment_ord = segment_ord, success_docs = result.success_docs);
+                        progress_sender.send(Ok(progress(segment_ord + 1, &mut result))).await.ok();
+                    }
+                    target_index_holder.index_updater().write().commit().await?;
+                    let old_index_name = match index_alias {
+                        Some(index_alias) => application_config
+                            .write()
+                            .autosave()
+                            .set_index_alias(&index_alias, target_index_holder.index_name())?,
+                        None => None,
+                    };
+                    Ok(proto::ReindexProgress {
+                        finished: true,
+                        old_index_name,
+                        ..progress(total_segments, &mut result)
+                    })
+                };
+                let final_progress = reindexing.await;
+                if let Err(error) = &final_progress {
+                    warn!(action = "reindexing_failed", error = ?error);
+                }
+                progress_sender.send(final_progress).await.ok();
+            }
+            .instrument(info_span!(parent: None, "reindex", source_index_name = ?source_index_holder.index_name())),
+        );
+        Ok(progress_receiver)
+    }
+
     /// Returns all existent consumers for all indices
     pub fn get_consumers(&self) -> SummaResult<Vec<(String, String)>> {
         let application_config = self.application_config.read();